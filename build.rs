@@ -1,13 +1,32 @@
-use clap::{CommandFactory, ValueEnum};
+use clap::{Arg, Command, CommandFactory, ValueEnum};
 use clap_complete as completion;
-use std::{env, io};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{env, fs, io, process};
 
 include!("src/cli.rs");
 
 fn main() -> Result<(), io::Error> {
+    // Record what the binary is built for, for `version --verbose`.
+    for variable in ["TARGET", "PROFILE"] {
+        if let Some(value) = env::var_os(variable) {
+            println!(
+                "cargo:rustc-env=BUILD_{variable}={}",
+                value.to_string_lossy()
+            );
+        }
+    }
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    if let Ok(output) = process::Command::new(rustc).arg("--version").output() {
+        println!(
+            "cargo:rustc-env=BUILD_RUSTC={}",
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+    }
+
     // The clap command.
     let mut command = Args::command();
-    // The directory to place the shell completions.
+    // The directory to place the shell completions, man pages, and example config.
     let out_dir = match env::var_os("OUT_DIR") {
         Some(out_dir) => out_dir,
 
@@ -19,5 +38,109 @@ fn main() -> Result<(), io::Error> {
         completion::generate_to(shell, &mut command, NAME, &out_dir)?;
     }
 
+    // Generate an example config file with every option commented out.
+    fs::write(
+        Path::new(&out_dir).join("config.toml"),
+        example_config(&command),
+    )?;
+
+    // Generate a man page for the command and each of its subcommands.
+    clap_mangen::generate_to(command, &out_dir)?;
+
     Ok(())
 }
+
+/// An example config file for the given `command`, with every option of it and its subcommands
+/// commented out and described by its help.
+fn example_config(command: &Command) -> String {
+    let mut options = BTreeMap::new();
+    add_options(command, &mut options);
+
+    let mut config = format!(
+        "# An example config file for {NAME}, with every option commented out.\n\
+         #\n\
+         # Each key is the long name of an option, which is used as its default.\n"
+    );
+    for (name, arg) in options {
+        config.push('\n');
+        if let Some(help) = arg.get_help() {
+            for line in help.to_string().lines() {
+                config.push_str(format!("# {line}").trim_end());
+                config.push('\n');
+            }
+        }
+        config.push_str(&format!("# {name} = {}\n", example_value(arg)));
+    }
+
+    config.push_str(
+        "\n\
+         # Names for sizes, which can be given wherever a size is expected.\n\
+         [sizes]\n\
+         # banner = \"1500x500\"\n\
+         \n\
+         # Presets of options, used with --preset NAME.\n\
+         [presets]\n\
+         # web = { quality = 80, strip-metadata = true }\n",
+    );
+
+    config
+}
+
+/// Adds each option of the given `command` and its subcommands to `options` by its long name,
+/// leaving out those that cannot be given in the config file.
+fn add_options<'a>(command: &'a Command, options: &mut BTreeMap<&'a str, &'a Arg>) {
+    for arg in command.get_arguments() {
+        let Some(name) = arg.get_long() else {
+            continue;
+        };
+        if matches!(
+            arg.get_action(),
+            ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+        ) {
+            continue;
+        }
+
+        options.entry(name).or_insert(arg);
+    }
+
+    // Completions and man pages are only generated on demand.
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.get_name().starts_with("generate"))
+    {
+        add_options(subcommand, options);
+    }
+}
+
+/// An example value for the given option in the config file: its default if it has one, `false`
+/// for flags, and its value name otherwise.
+fn example_value(arg: &Arg) -> String {
+    match arg.get_action() {
+        ArgAction::SetTrue => return "false".to_owned(),
+        ArgAction::Count => return "0".to_owned(),
+
+        _ => {}
+    }
+
+    match arg.get_default_values().first() {
+        // Numbers are given as TOML numbers, and everything else as strings.
+        Some(default) => {
+            let default = default.to_string_lossy();
+
+            if default.parse::<f64>().is_ok() {
+                default.into_owned()
+            } else {
+                format!("{default:?}")
+            }
+        }
+
+        None => {
+            let value_name = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .map_or_else(|| arg.get_id().to_string(), ToString::to_string);
+
+            format!("{value_name:?}")
+        }
+    }
+}
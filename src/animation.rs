@@ -0,0 +1,379 @@
+use crate::cli::EncodeArgs;
+use crate::encode;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::error::{EncodingError, ImageFormatHint, LimitError, LimitErrorKind};
+use image::io::Limits;
+use image::{
+    AnimationDecoder, Frame, ImageDecoder, ImageError, ImageFormat, ImageResult, RgbaImage,
+};
+use std::error::Error;
+use std::iter;
+use webp::{AnimEncoder, AnimFrame};
+
+/// An animated image.
+#[derive(Clone)]
+pub struct Animation {
+    /// The frames of the animation, each covering the whole image.
+    pub frames: Vec<Frame>,
+    /// How many times the animation is played, or 0 if it loops forever.
+    pub plays: u32,
+}
+
+impl Animation {
+    /// Decodes the given encoded image `bytes` in the given `format` as an animation, within the
+    /// given `limits`.
+    ///
+    /// Returns [`None`] without decoding anything if the image has only one frame, or if the format
+    /// cannot be animated.
+    pub fn decode(bytes: &[u8], format: ImageFormat, limits: Limits) -> ImageResult<Option<Self>> {
+        let frame_count = frame_count(bytes, format);
+        if frame_count <= 1 {
+            return Ok(None);
+        }
+
+        let animation = match format {
+            ImageFormat::Gif => {
+                let mut decoder = GifDecoder::new(bytes)?;
+                decoder.set_limits(limits.clone())?;
+                check_frames_fit(frame_count, decoder.dimensions(), &limits)?;
+
+                Self {
+                    frames: decoder.into_frames().collect_frames()?,
+                    plays: gif_plays(bytes),
+                }
+            }
+
+            ImageFormat::WebP => {
+                let mut decoder = WebPDecoder::new(bytes)?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                decoder.set_limits(limits.clone())?;
+                check_frames_fit(frame_count, decoder.dimensions(), &limits)?;
+
+                Self {
+                    frames: decoder.into_frames().collect_frames()?,
+                    plays: webp_plays(bytes),
+                }
+            }
+
+            ImageFormat::Png => {
+                let mut decoder = PngDecoder::new(bytes)?;
+                if !decoder.is_apng() {
+                    return Ok(None);
+                }
+                decoder.set_limits(limits.clone())?;
+                check_frames_fit(frame_count, decoder.dimensions(), &limits)?;
+
+                Self {
+                    frames: decoder.apng().into_frames().collect_frames()?,
+                    plays: png_plays(bytes),
+                }
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(animation).filter(|animation| animation.frames.len() > 1))
+    }
+
+    /// Whether animations can be encoded in the given `format`.
+    pub fn can_encode(format: ImageFormat) -> bool {
+        matches!(
+            format,
+            ImageFormat::Gif | ImageFormat::Png | ImageFormat::WebP
+        )
+    }
+
+    /// Replaces every frame with the result of applying `map` to it, keeping its delay.
+    pub fn map_frames<F>(self, mut map: F) -> Self
+    where
+        F: FnMut(&RgbaImage) -> RgbaImage,
+    {
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|frame| Frame::from_parts(map(frame.buffer()), 0, 0, frame.delay()))
+            .collect();
+
+        Self { frames, ..self }
+    }
+
+    /// The dimensions of the animation.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames
+            .first()
+            .map_or((0, 0), |frame| frame.buffer().dimensions())
+    }
+
+    /// Encodes the animation in the given `format`.
+    ///
+    /// Returns [`None`] if the format [cannot be animated].
+    ///
+    /// [cannot be animated]: Self::can_encode
+    pub fn encode(
+        self,
+        format: ImageFormat,
+        encode_args: &EncodeArgs,
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let encoded = match format {
+            ImageFormat::Gif => self.encode_gif()?,
+            ImageFormat::Png => self.encode_png().map_err(png_encoding_error)?,
+            ImageFormat::WebP => self.encode_webp(encode_args)?,
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(encoded))
+    }
+
+    fn encode_gif(self) -> ImageResult<Vec<u8>> {
+        let mut encoded = Vec::new();
+        let mut encoder = GifEncoder::new(&mut encoded);
+
+        // GIFs play once unless they have a loop count, which is the number of times they are
+        // played again.
+        match self.plays {
+            0 => encoder.set_repeat(Repeat::Infinite)?,
+            1 => (),
+
+            plays => {
+                let loops = u16::try_from(plays - 1).unwrap_or(u16::MAX);
+                encoder.set_repeat(Repeat::Finite(loops))?;
+            }
+        }
+
+        encoder.encode_frames(self.frames)?;
+        drop(encoder);
+
+        Ok(encoded)
+    }
+
+    fn encode_png(self) -> Result<Vec<u8>, png::EncodingError> {
+        let (width, height) = self.dimensions();
+
+        let mut encoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut encoded, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, self.plays)?;
+
+        let mut writer = encoder.write_header()?;
+
+        for frame in &self.frames {
+            // Delays are written as a fraction of a second.
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let milliseconds = (numerator / denominator.max(1)).min(u32::from(u16::MAX));
+            writer.set_frame_delay(milliseconds as u16, 1000)?;
+
+            writer.write_image_data(frame.buffer())?;
+        }
+
+        writer.finish()?;
+
+        Ok(encoded)
+    }
+
+    fn encode_webp(self, encode_args: &EncodeArgs) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (width, height) = self.dimensions();
+        let config = encode::webp_config(encode_args)?;
+
+        let mut encoder = AnimEncoder::new(width, height, &config);
+        encoder.set_loop_count(self.plays.try_into().unwrap_or(i32::MAX));
+
+        // Each frame is given the time at which it is shown, in milliseconds.
+        let mut timestamp = 0;
+        for frame in &self.frames {
+            encoder.add_frame(AnimFrame::from_rgba(
+                frame.buffer(),
+                width,
+                height,
+                timestamp,
+            ));
+
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let milliseconds = numerator / denominator.max(1);
+            timestamp = timestamp.saturating_add(milliseconds.try_into().unwrap_or(i32::MAX));
+        }
+
+        let encoded = encoder
+            .try_encode()
+            .map_err(|error| format!("failed to encode WebP animation: {error:?}"))?;
+
+        Ok(encoded.to_vec())
+    }
+}
+
+/// Reads how many frames the image in the given encoded `bytes` in the given `format` has, without
+/// decoding them.
+///
+/// Images in formats which cannot be animated have one frame.
+pub fn frame_count(bytes: &[u8], format: ImageFormat) -> usize {
+    match format {
+        ImageFormat::Gif => {
+            let mut options = gif::DecodeOptions::new();
+            options.skip_frame_decoding(true);
+
+            let Ok(mut decoder) = options.read_info(bytes) else {
+                return 1;
+            };
+            let mut frames = 0;
+            while let Ok(Some(_)) = decoder.next_frame_info() {
+                frames += 1;
+            }
+
+            frames
+        }
+
+        ImageFormat::Png => png::Decoder::new(bytes)
+            .read_info()
+            .ok()
+            .and_then(|reader| reader.info().animation_control)
+            .map_or(1, |control| control.num_frames as usize),
+
+        // Each frame of an animated WebP image is in its own chunk.
+        ImageFormat::WebP => webp_chunks(bytes)
+            .filter(|(kind, _)| kind == b"ANMF")
+            .count()
+            .max(1),
+
+        _ => 1,
+    }
+}
+
+/// Errors if `frame_count` frames with the given `dimensions`, each of which is decoded as 8-bit
+/// RGBA, would allocate more memory than the given `limits` allow.
+fn check_frames_fit(
+    frame_count: usize,
+    (width, height): (u32, u32),
+    limits: &Limits,
+) -> ImageResult<()> {
+    let bytes = (frame_count as u64)
+        .saturating_mul(u64::from(width) * u64::from(height))
+        .saturating_mul(4);
+
+    match limits.max_alloc {
+        Some(max_alloc) if bytes > max_alloc => Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::InsufficientMemory,
+        ))),
+
+        _ => Ok(()),
+    }
+}
+
+/// Reads how many times the GIF image in the given `bytes` is played.
+///
+/// The loop count is stored in an extension which may come after any frame, so every frame's
+/// metadata must be read (but not decoded) to find it.
+fn gif_plays(bytes: &[u8]) -> u32 {
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+
+    let Ok(mut decoder) = options.read_info(bytes) else {
+        return 1;
+    };
+    while let Ok(Some(_)) = decoder.next_frame_info() {}
+
+    // GIFs play once unless they have a loop count; a missing loop count is read as 0.
+    match decoder.repeat() {
+        gif::Repeat::Infinite => 0,
+        gif::Repeat::Finite(loops) => u32::from(loops) + 1,
+    }
+}
+
+/// Reads how many times the APNG image in the given `bytes` is played.
+fn png_plays(bytes: &[u8]) -> u32 {
+    png::Decoder::new(bytes)
+        .read_info()
+        .ok()
+        .and_then(|reader| reader.info().animation_control)
+        .map_or(0, |control| control.num_plays)
+}
+
+/// Reads how many times the animated WebP image in the given `bytes` is played.
+fn webp_plays(bytes: &[u8]) -> u32 {
+    // Background color, loop count.
+    match webp_chunks(bytes).find(|(kind, _)| kind == b"ANIM") {
+        Some((_, [_, _, _, _, loops0, loops1, ..])) => {
+            u32::from(u16::from_le_bytes([*loops0, *loops1]))
+        }
+
+        _ => 0,
+    }
+}
+
+/// Iterates over the type and data of each chunk in the WebP image in the given `bytes`.
+fn webp_chunks(bytes: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    // RIFF header, file length, WebP header.
+    let mut rest = bytes.get(12..).unwrap_or_default();
+
+    iter::from_fn(move || {
+        // Chunk type, chunk length, chunk data (padded to an even length).
+        let [a, b, c, d, l0, l1, l2, l3, data @ ..] = rest else {
+            return None;
+        };
+        let length = u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize;
+
+        rest = data.get(length + length % 2..).unwrap_or_default();
+
+        Some(([*a, *b, *c, *d], data.get(..length).unwrap_or(data)))
+    })
+}
+
+fn png_encoding_error(error: png::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        error,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, Rgba};
+
+    fn gif(frame_count: u32) -> Vec<u8> {
+        let frames = (0..frame_count).map(|index| {
+            let buffer = RgbaImage::from_pixel(16, 16, Rgba([index as u8 * 50, 0, 0, 255]));
+            Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+        });
+
+        let mut encoded = Vec::new();
+        GifEncoder::new(&mut encoded)
+            .encode_frames(frames)
+            .expect("frames are encodable");
+
+        encoded
+    }
+
+    #[test]
+    fn animations_are_decoded() {
+        let animation = Animation::decode(&gif(3), ImageFormat::Gif, Limits::default())
+            .expect("the animation is decodable")
+            .expect("the GIF is animated");
+
+        assert_eq!(animation.frames.len(), 3);
+        assert_eq!(animation.dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn still_images_are_not_animations() {
+        let animation = Animation::decode(&gif(1), ImageFormat::Gif, Limits::default())
+            .expect("the image is decodable");
+
+        assert!(animation.is_none());
+    }
+
+    #[test]
+    fn animations_are_limited_across_frames() {
+        let mut limits = Limits::default();
+        // Enough for two frames, but not three.
+        limits.max_alloc = Some(2 * 16 * 16 * 4);
+
+        let error = Animation::decode(&gif(3), ImageFormat::Gif, limits).err();
+
+        assert!(matches!(error, Some(ImageError::Limits(_))));
+    }
+}
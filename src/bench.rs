@@ -0,0 +1,91 @@
+use crate::cli::{Alpha, BenchArgs};
+use crate::error::InvalidArguments;
+use crate::output::Output;
+use crate::tiling::Layout;
+use crate::{encode, file};
+use image::{GenericImageView, ImageFormat};
+use indicatif::ProgressBar;
+use log::info;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+/// Runs each stage of resizing the image according to the given `bench_args` the given number of
+/// times, printing how long each took to `output`.
+///
+/// The image is encoded in memory, and nothing is written.
+pub fn run(bench_args: &BenchArgs, output: &Output) -> Result<(), Box<dyn Error>> {
+    let BenchArgs {
+        input_path,
+        fit_scale,
+        output_scale,
+        round,
+        iterations,
+        format,
+        encode_args,
+        limit_args,
+    } = bench_args;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let format = match format {
+        Some(extension) => ImageFormat::from_extension(extension)
+            .ok_or_else(|| InvalidArguments(format!("unknown image format: {extension}")))?,
+        None => ImageFormat::from_path(input_path)?,
+    };
+
+    let (decoded, image) = time(iterations.get(), || input.clone().decode())?;
+    let (image, _) = image;
+
+    let dimensions =
+        crate::scale_dimensions(image.dimensions(), *fit_scale, *output_scale, *round)?;
+    crate::check_output_dimensions(dimensions, limit_args)?;
+    info!("repeating {} to {dimensions:?}", input_path.display());
+    let (repeated, new_image) = time(iterations.get(), || {
+        Ok::<_, Box<dyn Error>>(crate::repeated(
+            &image,
+            dimensions,
+            Alpha::Blend,
+            &Layout::default(),
+            &ProgressBar::hidden(),
+        ))
+    })?;
+
+    let (encoded, _) = time(iterations.get(), || {
+        encode::encode(&new_image, format, encode_args, input.indexed)
+    })?;
+
+    print_timings("decode", &decoded, output);
+    print_timings("repeat", &repeated, output);
+    print_timings("encode", &encoded, output);
+
+    Ok(())
+}
+
+/// Runs `stage` the given number of `iterations`, returning how long each run took along with
+/// the result of the last run.
+fn time<T, E>(
+    iterations: usize,
+    mut stage: impl FnMut() -> Result<T, E>,
+) -> Result<(Vec<Duration>, T), E> {
+    let mut timings = Vec::with_capacity(iterations);
+    let mut result = None;
+
+    for _ in 0..iterations {
+        let start = Instant::now();
+        result = Some(stage()?);
+        timings.push(start.elapsed());
+    }
+
+    Ok((timings, result.expect("there is at least one iteration")))
+}
+
+/// Prints the mean, minimum, and maximum of the given `timings` of the named `stage` to `output`.
+fn print_timings(stage: &str, timings: &[Duration], output: &Output) {
+    let total: Duration = timings.iter().sum();
+    let mean = total / timings.len() as u32;
+    let min = timings.iter().min().copied().unwrap_or_default();
+    let max = timings.iter().max().copied().unwrap_or_default();
+
+    output.println(format_args!(
+        "{stage}: mean {mean:.2?}, min {min:.2?}, max {max:.2?}"
+    ));
+}
@@ -0,0 +1,28 @@
+use crate::cli::{BlurArgs, Sigma};
+use crate::file;
+use log::{debug, info};
+use std::error::Error;
+
+/// Blurs the image according to the given `blur_args`.
+pub fn run(blur_args: &BlurArgs) -> Result<(), Box<dyn Error>> {
+    let BlurArgs {
+        input_path,
+        output_path,
+        sigma: Sigma(sigma),
+        encode_args,
+        limit_args,
+    } = blur_args;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    let (image, metadata) = input.decode()?;
+
+    debug!("{}: blurring with a sigma of {sigma}", input_path.display());
+    let new_image = image.blur(*sigma);
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image, &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::cli::{BorderArgs, Color};
+use crate::{file, scale};
+use image::{imageops, DynamicImage, GenericImageView, Pixel, Rgba, Rgba32FImage};
+use log::{debug, info};
+use std::error::Error;
+
+/// Draws a border around the image according to the given `border_args`.
+pub fn run(border_args: &BorderArgs) -> Result<(), Box<dyn Error>> {
+    let BorderArgs {
+        input_path,
+        output_path,
+        thickness,
+        color,
+        dash,
+        outside,
+        encode_args,
+        limit_args,
+    } = border_args;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    let (image, metadata) = input.decode()?;
+    let thickness = thickness.get();
+    let dash = dash.map(|dash| dash.get());
+
+    let new_dimensions = if *outside {
+        let (width, height) = image.dimensions();
+        let extend = |length: u32| -> Result<u32, Box<dyn Error>> {
+            length
+                .checked_add(thickness * 2)
+                .ok_or_else(|| "the border would make the image too large".into())
+        };
+
+        (extend(width)?, extend(height)?)
+    } else {
+        image.dimensions()
+    };
+    crate::check_output_dimensions(new_dimensions, limit_args)?;
+
+    // Gaps between dashes and translucent borders outside the image are transparent, which needs an
+    // alpha channel.
+    let transparent = *outside && (dash.is_some() || color.0[3] < u8::MAX);
+    let image = if transparent && !image.color().has_alpha() {
+        crate::with_alpha(&image)
+    } else {
+        image
+    };
+
+    debug!(
+        "{}: drawing a {thickness}-pixel border",
+        input_path.display()
+    );
+    let new_image = bordered(&image, thickness, *color, dash, *outside);
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image, &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
+
+/// Draws a border of the given `thickness` and `color` around the given `image`, keeping its
+/// sample format.
+///
+/// If a `dash` length is given, the border is drawn as dashes of that length with gaps of the same
+/// length between them. If `outside` is true, the canvas is extended by the border's thickness on
+/// each side for the border to be drawn around the image, rather than over its edges.
+pub fn bordered(
+    image: &DynamicImage,
+    thickness: u32,
+    Color(color): Color,
+    dash: Option<u32>,
+    outside: bool,
+) -> DynamicImage {
+    let (mut width, mut height) = image.dimensions();
+    let mut new_image = image.to_rgba32f();
+
+    if outside {
+        (width, height) = (width + thickness * 2, height + thickness * 2);
+
+        let mut extended = Rgba32FImage::new(width, height);
+        imageops::overlay(
+            &mut extended,
+            &new_image,
+            i64::from(thickness),
+            i64::from(thickness),
+        );
+        new_image = extended;
+    }
+
+    let color = Rgba(color.map(|sample| f32::from(sample) / 255.0));
+
+    for (x, y, pixel) in new_image.enumerate_pixels_mut() {
+        let on_vertical_edge = x < thickness || x >= width.saturating_sub(thickness);
+        let on_horizontal_edge = y < thickness || y >= height.saturating_sub(thickness);
+
+        // Dashes run along each edge, so the position along a vertical edge is its row.
+        let position = if on_horizontal_edge { x } else { y };
+        let in_dash = dash.is_none_or(|dash| (position / dash) % 2 == 0);
+
+        if (on_vertical_edge || on_horizontal_edge) && in_dash {
+            pixel.blend(&color);
+        }
+    }
+
+    scale::with_color(new_image.into(), image.color())
+}
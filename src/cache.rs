@@ -0,0 +1,187 @@
+use crate::error::FileError;
+use crate::file::Input;
+use crate::metadata::Metadata;
+use image::DynamicImage;
+use log::debug;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// A decoded image shared between the uses of its input, once decoded by the first of them.
+type Shared = Arc<Mutex<Option<(Arc<DynamicImage>, Metadata)>>>;
+
+/// Decoded images kept for the inputs used more than once in a run, such as to make several
+/// outputs from one image, so that each is only decoded once.
+///
+/// Images are kept by path and modification time, so an input that changes is decoded again, and
+/// each is dropped once the last [use] of its input has finished, whether or not it decoded it.
+///
+/// [use]: Cache::start_use
+#[derive(Debug, Default)]
+pub struct Cache {
+    /// How many uses of each input used more than once are yet to finish.
+    remaining: Mutex<HashMap<PathBuf, usize>>,
+    /// The images decoded so far.
+    images: Mutex<HashMap<(PathBuf, SystemTime), Shared>>,
+}
+
+/// A use of the input at a path, which is finished once it has [decoded] the input or is dropped,
+/// such as when the input is skipped.
+///
+/// [decoded]: Use::decode
+#[derive(Debug)]
+pub struct Use<'a> {
+    cache: &'a Cache,
+    path: PathBuf,
+}
+
+impl Cache {
+    /// A cache for the inputs at the given `paths`, one for each use of them.
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut uses = HashMap::new();
+        for path in paths {
+            *uses.entry(path.to_owned()).or_insert(0) += 1;
+        }
+        uses.retain(|_, uses| *uses > 1);
+
+        Self {
+            remaining: Mutex::new(uses),
+            images: Mutex::default(),
+        }
+    }
+
+    /// Starts one of the uses of the input at `path`.
+    pub fn start_use(&self, path: &Path) -> Use<'_> {
+        Use {
+            cache: self,
+            path: path.to_owned(),
+        }
+    }
+
+    /// Whether the input at `path` is used more than once.
+    fn is_shared(&self, path: &Path) -> bool {
+        self.remaining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(path)
+    }
+
+    /// Counts a use of the input at `path` as finished, dropping its decoded images if it was the
+    /// last.
+    fn finish_use(&self, path: &Path) {
+        let mut remaining = self
+            .remaining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        match remaining.get_mut(path) {
+            Some(uses) if *uses > 1 => *uses -= 1,
+
+            Some(_) => {
+                remaining.remove(path);
+                drop(remaining);
+
+                self.images
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .retain(|(image_path, _), _| image_path != path);
+            }
+
+            None => (),
+        }
+    }
+}
+
+impl Use<'_> {
+    /// Decodes the given `input` as by [`Input::decode`], or shares the image if another use has
+    /// already decoded it.
+    pub fn decode(self, input: Input) -> Result<(Arc<DynamicImage>, Metadata), FileError> {
+        let Some(modified) = input.modified.filter(|_| self.cache.is_shared(&self.path)) else {
+            let (image, metadata) = input.decode()?;
+            return Ok((Arc::new(image), metadata));
+        };
+        let key = (self.path.clone(), modified);
+
+        let shared = Arc::clone(
+            self.cache
+                .images
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(key)
+                .or_default(),
+        );
+        // Other uses of the same input wait for it to be decoded rather than decoding it too.
+        let mut decoded = shared.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match &*decoded {
+            Some((image, metadata)) => {
+                debug!("{}: reusing the decoded image", self.path.display());
+                Ok((Arc::clone(image), metadata.clone()))
+            }
+
+            None => {
+                let (image, metadata) = input.decode()?;
+                let image = Arc::new(image);
+                *decoded = Some((Arc::clone(&image), metadata.clone()));
+
+                Ok((image, metadata))
+            }
+        }
+    }
+}
+
+impl Drop for Use<'_> {
+    fn drop(&mut self) {
+        self.cache.finish_use(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDirectory;
+    use image::RgbaImage;
+
+    /// Writes an image into the given `directory`, returning its path.
+    fn test_image(directory: &TestDirectory) -> PathBuf {
+        let path = directory.join("image.png");
+        RgbaImage::new(2, 2).save(&path).unwrap();
+
+        path
+    }
+
+    fn read(path: &Path) -> Input {
+        Input::read(path, false).unwrap()
+    }
+
+    #[test]
+    fn images_are_shared_between_uses() {
+        let directory = TestDirectory::new("shared");
+        let path = test_image(&directory);
+        let cache = Cache::new([&*path, &*path]);
+
+        let (first, _) = cache.start_use(&path).decode(read(&path)).unwrap();
+        let (second, _) = cache.start_use(&path).decode(read(&path)).unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(cache.images.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skipped_uses_are_finished() {
+        let directory = TestDirectory::new("skipped");
+        let path = test_image(&directory);
+        let cache = Cache::new([&*path, &*path, &*path]);
+
+        cache.start_use(&path).decode(read(&path)).unwrap();
+        // Skipped without decoding, such as by `--only-newer`.
+        drop(cache.start_use(&path));
+        assert!(!cache.images.lock().unwrap().is_empty());
+
+        drop(cache.start_use(&path));
+
+        assert!(cache.remaining.lock().unwrap().is_empty());
+        assert!(cache.images.lock().unwrap().is_empty());
+    }
+}
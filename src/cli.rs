@@ -1,55 +1,1659 @@
-use clap::{Parser, Subcommand, ValueHint};
-use clap_complete::Shell;
+use clap::{ArgAction, Parser, Subcommand, ValueHint};
+use clap_complete::Generator;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use std::num::{NonZeroU32, NonZeroUsize, ParseFloatError};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 /// The name of the command.
 pub const NAME: &str = "img-resize";
 
+/// A description of the exit codes, shown after the full help.
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  Failure
+  2  Invalid arguments, config file, or preset
+  3  An image could not be decoded
+  4  An image could not be encoded
+  5  A file could not be read or written
+
+When some of many images fail, the exit code is that of their failures if they all share one.";
+
 #[derive(Debug, PartialEq, Eq, Clone, Parser)]
-#[command(name = NAME, args_conflicts_with_subcommands = true)]
+#[command(name = NAME, args_conflicts_with_subcommands = true, after_long_help = EXIT_CODES)]
 pub struct Args {
     #[command(subcommand)]
     pub subcommand: Option<Subcommands>,
 
     #[command(flatten)]
     pub resize_args: Option<ResizeArgs>,
+
+    /// Print more about what is being done - can be repeated for more detail.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Print less about what is being done - can be repeated to silence errors too.
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+    /// Never ask for confirmation or show progress, for running from other programs.
+    ///
+    /// Existing files are overwritten without asking, as when stdin is not a terminal. Messages are
+    /// only ever printed to stderr, leaving stdout for data such as JSON.
+    #[arg(long, global = true)]
+    pub batch: bool,
+
+    /// The most threads to resize and composite images on at once - defaults to the number of
+    /// CPUs.
+    ///
+    /// Lower it to leave CPUs free for other work, such as on shared CI machines.
+    #[arg(short, long, value_name = "N", global = true)]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Use the options saved in the given preset as defaults.
+    ///
+    /// Options given on the command line override the preset's.
+    #[arg(long, value_name = "NAME", global = true)]
+    pub preset: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Parser)]
+// clap leaves the group of a struct with flattened fields empty, so the inputs are added to it
+// explicitly for `resize_args` to be parsed when they are given.
+#[group(args = ["input_paths", "files_from", "job"])]
 pub struct ResizeArgs {
-    /// The image to extend.
-    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
-    pub input_path: String,
+    /// The images to extend, or directories of them with `--recursive`.
+    #[arg(value_name = "FILE", value_hint = ValueHint::AnyPath, required_unless_present_any = ["files_from", "job"])]
+    pub input_paths: Vec<PathBuf>,
+    /// Also extend the images listed in the given file, one path per line - or those listed on
+    /// stdin if `-` is given.
+    #[arg(long, value_name = "LIST", value_hint = ValueHint::FilePath)]
+    pub files_from: Option<PathBuf>,
+    /// Separate the paths in the `--files-from` list with NUL rather than newlines, as printed by
+    /// `find -print0`.
+    #[arg(short = '0', long, requires = "files_from")]
+    pub null: bool,
+    /// Process the images described by the given YAML job file, each with its own output and
+    /// operations, rather than extending images.
+    ///
+    /// The file has a list of `jobs`, each with an `input` path, an `output` path - the input if
+    /// not given - and a list of `operations` as for the `run` subcommand, such as `scale 2x`.
+    /// Relative paths are relative to the job file.
+    ///
+    /// Existing outputs are only overwritten once confirmed, as when extending images, and
+    /// `--dry-run` prints what would be written instead.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["input_paths", "files_from", "output_path", "output_template", "format", "watch", "json", "manifest", "stream"]
+    )]
+    pub job: Option<PathBuf>,
 
-    /// The output image path - will overwrite the `input_path` if not provided.
+    /// The output image path - will overwrite the input if not provided.
+    ///
+    /// Can only be used with a single input.
     #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
-    pub output_path: Option<String>,
+    pub output_path: Option<PathBuf>,
+
+    /// A template for the output path of each input, such as `{stem}_{width}x{height}.{ext}`.
+    ///
+    /// `{dir}`, `{stem}`, and `{ext}` are the input's directory, file name without its extension,
+    /// and extension. `{width}` and `{height}` are the output's dimensions, `{format}` is the
+    /// extension of the `--format` given - or of the input's format - and `{index}` is the input's
+    /// position among the inputs, from 1. `{{` and `}}` are literal braces.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "output_path")]
+    pub output_template: Option<String>,
+    /// The file extension of the format to write the outputs in, such as `webp`, in place of each
+    /// input's extension.
+    ///
+    /// With `--output-template`, it is only used for `{format}`.
+    #[arg(long, value_name = "EXTENSION", conflicts_with = "output_path")]
+    pub format: Option<String>,
+
+    /// The directory to write the outputs to, created if it does not exist, keeping the file name
+    /// of each input and mirroring the structure of any input directories.
+    ///
+    /// With `--output-template`, the template gives the file name of each output within it.
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath, conflicts_with = "output_path")]
+    pub output_dir: Option<PathBuf>,
+    /// Resize every image in each input directory and its subdirectories, without following
+    /// symbolic links to directories.
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Only resize the files in input directories with these extensions, rather than every
+    /// supported format.
+    #[arg(
+        long,
+        value_name = "EXT",
+        value_delimiter = ',',
+        requires = "recursive"
+    )]
+    pub extensions: Vec<String>,
+
+    /// Fail rather than overwrite an existing output file.
+    #[arg(long, conflicts_with = "backup")]
+    pub no_clobber: bool,
+    /// Skip images whose output already exists and was modified no earlier than the image, as
+    /// `make` does, so that repeated runs only resize the images that have changed.
+    ///
+    /// Images resized in place are never skipped.
+    #[arg(long)]
+    pub only_newer: bool,
+    /// Record the hash of each input and the settings used for it in the given JSON manifest,
+    /// and skip images whose output was already made from the same input with the same settings,
+    /// even if their modification times have changed, such as after a `git checkout`.
+    ///
+    /// The manifest is created if it does not exist.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = ["job", "watch"])]
+    pub manifest: Option<PathBuf>,
+    /// Copy an existing output file to its path with the given suffix before overwriting it - `~`
+    /// if no suffix is given.
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, require_equals = true, default_missing_value = "~")]
+    pub backup: Option<String>,
+    /// Copy the given attributes of each input file onto its output, such as when overwriting it
+    /// in place.
+    #[arg(long, value_name = "ATTRIBUTES", value_delimiter = ',')]
+    pub preserve: Vec<Preserve>,
+    /// Overwrite existing files without asking, whether they are the input images or other files
+    /// at the output paths.
+    ///
+    /// Confirmation is only asked for when stdin is a terminal, and not with `--backup` or
+    /// `--no-clobber`.
+    #[arg(short, long)]
+    pub yes: bool,
 
     /// The scale (arbitrary units) of the image currently.
-    #[arg(long, value_name = "WIDTH x HEIGHT")]
-    pub fit_scale: Size<u32>,
+    ///
+    /// `auto` uses the image's own dimensions, so that `output_scale` is the output's dimensions,
+    /// and `auto:reduced` their simplest ratio, so that `output_scale` multiplies that ratio.
+    #[arg(long, value_name = "WIDTH x HEIGHT", required_unless_present_any = ["tiles", "output_size", "job"])]
+    pub fit_scale: Option<FitScale>,
     /// The scale to extend the image to, in relation to `fit_scale`.
-    #[arg(long, value_name = "WIDTH x HEIGHT")]
-    pub output_scale: Size<u32>,
+    #[arg(long, value_name = "WIDTH x HEIGHT", value_parser = Size::<Ratio>::parse_nonzero, required_unless_present_any = ["tiles", "output_size", "job"])]
+    pub output_scale: Option<Size<Ratio>>,
+    /// How fractional output dimensions scaled by `fit_scale` and `output_scale`, or converted
+    /// from physical units, are rounded.
+    #[arg(long, value_enum, default_value_t = Rounding::Nearest, conflicts_with = "tiles")]
+    pub round: Rounding,
+    /// Extend the image to exactly this many repetitions across and down, instead of giving
+    /// `fit_scale` and `output_scale`.
+    #[arg(long, value_name = "ACROSS x DOWN", conflicts_with_all = ["fit_scale", "output_scale"])]
+    pub tiles: Option<Size<NonZeroU32>>,
+    /// Extend the image to exactly this size, instead of giving `fit_scale` and `output_scale`.
+    ///
+    /// Each dimension is in pixels, optionally suffixed with `px`, a percentage of the image's
+    /// own, or in `mm`, `cm`, or `in` at the resolution given by `--dpi`, such as `200%x1080px`
+    /// or `210mmx297mm`.
+    #[arg(long, value_name = "WIDTH x HEIGHT", value_parser = Size::<Length>::parse_nonzero, conflicts_with_all = ["fit_scale", "output_scale", "tiles"])]
+    pub output_size: Option<Size<Length>>,
+    /// A Rhai script run for each image, which can override the size and output path given for
+    /// it.
+    ///
+    /// The script is given the input's `path`, `width`, `height`, and `index`, and can evaluate to
+    /// a map of the options to override, such as `#{ size: "50%x50%", output: "small.png" }` for
+    /// `--output-size` and `--output-path`.
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub script: Option<PathBuf>,
+    /// The resolution of the output in dots per inch, which `output_size` is converted to pixels
+    /// at, and which is recorded in the output's metadata for printing unless `--set-dpi` is
+    /// given.
+    #[arg(long, value_name = "N")]
+    pub dpi: Option<NonZeroU32>,
+
+    /// Re-run the resize whenever the input image changes.
+    ///
+    /// Requires an `output_path` other than the input, otherwise every run would trigger another.
+    #[arg(short, long)]
+    pub watch: bool,
+
+    /// Show progress - by default, progress is only shown if stdout is a terminal.
+    #[arg(long, overrides_with = "no_progress")]
+    pub progress: bool,
+    /// Never show progress.
+    #[arg(long, overrides_with = "progress")]
+    pub no_progress: bool,
+
+    /// Print the output dimensions, number of tiles, and output path without writing anything.
+    ///
+    /// Only the input's header is read.
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Print a JSON object describing the result of each resize, one per line.
+    #[arg(long)]
+    pub json: bool,
+
+    /// Don't copy EXIF, XMP, and ICC profile metadata from the input to the output.
+    ///
+    /// Metadata can only be copied between PNG and JPEG images.
+    #[arg(long)]
+    pub strip_metadata: bool,
+
+    /// Rotate and flip the input according to its EXIF orientation before resizing it - the
+    /// default.
+    #[arg(long, overrides_with = "no_auto_orient")]
+    pub auto_orient: bool,
+    /// Don't apply the input's EXIF orientation.
+    #[arg(long, overrides_with = "auto_orient")]
+    pub no_auto_orient: bool,
+
+    /// Convert the input from its ICC profile's color space to sRGB.
+    ///
+    /// The profile is not copied to the output, as it no longer applies.
+    #[arg(long)]
+    pub convert_to_srgb: bool,
+
+    /// Write the output one row at a time, without holding the whole image in memory.
+    ///
+    /// Only PNG inputs and outputs are supported, EXIF orientations cannot be applied, and the
+    /// output cannot be reduced to a palette with `--colors`.
+    #[arg(long, conflicts_with_all = ["convert_to_srgb", "colors"])]
+    pub stream: bool,
+
+    /// How to fill the extended canvas: `tile` repeats the input across it, and `blur` centers the
+    /// input over a scaled-up, heavily blurred copy of itself.
+    ///
+    /// `gradient:COLOR..COLOR[@ANGLE]` repeats the input across a linear gradient between two hex
+    /// colors, such as `gradient:#fff..#08f@45`. The angle is in degrees clockwise from pointing
+    /// right, and defaults to 90 - pointing down.
+    #[arg(long, value_name = "FILL", default_value = "tile")]
+    pub fill: Fill,
+
+    /// Overlap adjacent repetitions by this many pixels, fading each into the next to hide the
+    /// seams between them.
+    ///
+    /// Must be at most half the width and height of the input.
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "stream")]
+    pub feather: u32,
+
+    /// Blur the output with a Gaussian blur of this standard deviation, in pixels, to soften it.
+    #[arg(long, value_name = "SIGMA", conflicts_with = "stream")]
+    pub blur: Option<Sigma>,
+
+    /// Round the corners of the output to this radius, in pixels, making them transparent.
+    #[arg(long, value_name = "N", conflicts_with = "stream")]
+    pub corner_radius: Option<NonZeroU32>,
+
+    /// Draw a blurred drop shadow behind the output, extending the canvas to fit it.
+    ///
+    /// Given as comma-separated `offset=XxY`, `blur=SIGMA`, and `color=COLOR` settings, any of
+    /// which can be left out for the defaults: `offset=0x4,blur=8,color=#00000080`.
+    #[arg(long, value_name = "SETTINGS", num_args = 0..=1, require_equals = true, default_missing_value = "", conflicts_with = "stream")]
+    pub shadow: Option<Shadow>,
+
+    /// Where the repetitions start from: the top left `corner`, or the `center`, which splits any
+    /// space left over evenly between each side.
+    #[arg(long, value_enum, default_value_t = TileOrigin::Corner, conflicts_with_all = ["stream", "feather"])]
+    pub tile_origin: TileOrigin,
+    /// How the rows of repetitions are laid out: a `grid`, or `brick[:OFFSET]` to offset every
+    /// other row by half a repetition (or the given fraction of one, such as `0.25`).
+    #[arg(long, value_name = "LAYOUT", default_value = "grid", conflicts_with_all = ["repeat_x", "repeat_y", "stream", "feather"])]
+    pub tile_layout: TileLayout,
+
+    /// Only repeat the input horizontally, placing a single row of repetitions.
+    #[arg(long, conflicts_with_all = ["repeat_y", "stream", "feather"])]
+    pub repeat_x: bool,
+    /// Only repeat the input vertically, placing a single column of repetitions.
+    #[arg(long, conflicts_with_all = ["stream", "feather"])]
+    pub repeat_y: bool,
+    /// Extend the edges of the repetitions across the rest of the output along the axis that is
+    /// not repeated, rather than leaving it transparent.
+    #[arg(long)]
+    pub clamp_edges: bool,
+
+    /// Leave this many pixels between adjacent repetitions, filled with `gap_color`.
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with_all = ["stream", "feather"])]
+    pub tile_gap: u32,
+    /// The hex color of the gaps between repetitions, such as `#fff` - transparent by default.
+    #[arg(long, value_name = "COLOR", default_value = "#0000")]
+    pub gap_color: Color,
+
+    /// Randomly rotate and/or flip each repetition to break up obvious repetition.
+    ///
+    /// Given as comma-separated settings: `rotate` for quarter turns (half turns unless the input
+    /// is square), `flip` for mirroring, and `seed=N` to make the output reproducible.
+    #[arg(long, value_name = "SETTINGS", conflicts_with_all = ["stream", "feather"])]
+    pub tile_random: Option<TileRandom>,
+
+    /// How the alpha channel of the image is applied to each repetition, over the gradient of a
+    /// `--fill gradient` and the `gap_color`.
+    #[arg(long, value_enum, default_value_t = Alpha::Blend)]
+    pub alpha: Alpha,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Subcommand)]
+#[derive(Debug, PartialEq, Eq, Clone, Subcommand)]
 pub enum Subcommands {
     /// Generates shell completions for the given shell.
     Generate {
-        #[arg(short, long, value_name = "SHELL")]
-        shell: Shell,
+        #[arg(
+            short,
+            long,
+            value_name = "SHELL",
+            required_unless_present = "all",
+            conflicts_with = "all"
+        )]
+        shell: Option<Shell>,
+        /// Generate completions for every supported shell into the `--output` directory.
+        #[arg(long, requires = "output")]
+        all: bool,
+        /// Write the completions to the given file rather than stdout, or into the given directory
+        /// under the file name the shell expects.
+        ///
+        /// With `--all`, the directory is created if it does not exist.
+        #[arg(short, long, value_name = "PATH", value_hint = ValueHint::AnyPath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Generates a man page.
+    GenerateMan,
+
+    /// Prints the version, and with `--verbose`, the optional features, image formats, and build
+    /// it was compiled with.
+    Version,
+
+    /// Prints the dimensions, color type, format, number of frames, and metadata of images.
+    Info(InfoArgs),
+
+    /// Scales an image to the given size, resampling it rather than extending it.
+    Scale(ScaleArgs),
+
+    /// Makes an image tile seamlessly, by blending its edges with a copy offset by half its size.
+    ///
+    /// The output can then be extended without visible seams between its repetitions.
+    Seamless(SeamlessArgs),
+
+    /// Overlays one image onto another.
+    Composite(CompositeArgs),
+
+    /// Blurs an image with a Gaussian blur.
+    Blur(BlurArgs),
+
+    /// Draws a solid or dashed border around an image.
+    Border(BorderArgs),
+
+    /// Prints how similar two images of the same dimensions are.
+    Compare(CompareArgs),
+
+    /// Writes an image highlighting where two images of the same dimensions differ.
+    Diff(DiffArgs),
+
+    /// Quickly shrinks images to thumbnails, such as for gallery previews.
+    Thumbnail(ThumbnailArgs),
+
+    /// Lays out images in a grid on a single contact sheet.
+    Montage(MontageArgs),
+
+    /// Slices an image into a grid of tiles, each written to its own file.
+    Split(SplitArgs),
+
+    /// Packs images, or the frames of an animation, onto a single sprite sheet in a grid.
+    Spritesheet(SpritesheetArgs),
+
+    /// Appends images side by side or one above another.
+    Concat(ConcatArgs),
+
+    /// Applies a pipeline of operations to an image in order, decoding it once and encoding only
+    /// the result.
+    ///
+    /// Each operation follows a `--`, such as `run in.png -- crop 100x100+0+0 -- scale 2x -- tile
+    /// 3840x2160`.
+    Run(RunArgs),
+
+    /// Manages presets: named sets of options saved in the config file.
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+
+    /// Times each stage of resizing an image, without writing the result.
+    #[command(hide = true)]
+    Bench(BenchArgs),
+
+    /// Applies operations loaded from WebAssembly plugins to an image, in the order they are
+    /// given.
+    #[cfg(feature = "plugins")]
+    Apply(ApplyArgs),
+
+    /// Replaces this binary with the latest release on GitHub, if it is newer.
+    #[cfg(feature = "self-update")]
+    SelfUpdate(SelfUpdateArgs),
+
+    /// Serves an HTTP endpoint which processes each image uploaded to it and responds with the
+    /// result.
+    ///
+    /// Images are uploaded as the body of a `POST` request, with the query parameters `size`
+    /// (`WIDTHxHEIGHT`), `mode` (`tile` - the default - `scale`, `fit`, or `cover`), and
+    /// `format` (the extension of the response's format - the upload's if not given).
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+
+    /// Listens on a Unix socket for commands to run, which saves starting a process for each of
+    /// many invocations.
+    ///
+    /// Each line sent is a JSON array of the arguments to run, as given after `img-resize` on the
+    /// command line, such as `["in.png", "--tiles", "2x2"]`. Each is answered by a line with a
+    /// JSON object of its `exit_code`, a `message` if it failed, and the `output` it printed if
+    /// any, such as its JSON reports. Commands are run with `--batch`, relative to the daemon's
+    /// working directory.
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct InfoArgs {
+    /// The images to describe.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, required = true)]
+    pub input_paths: Vec<PathBuf>,
+
+    /// Print a JSON object describing each image, one per line.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Subcommand)]
+pub enum PresetAction {
+    /// Saves the given options as a preset, replacing any preset with the same name.
+    Save {
+        /// The name of the preset.
+        name: String,
+        /// The options to save, such as `--fit-scale 16x9 --output-scale 3840x2160`.
+        #[arg(
+            value_name = "OPTIONS",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        options: Vec<String>,
     },
+
+    /// Lists the saved presets and their options.
+    List,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct ScaleArgs {
+    /// The image to scale.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The size to scale the image to, in pixels.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub size: Size<NonZeroU32>,
+
+    /// The filter used to resample the image.
+    #[arg(long, value_enum, default_value_t = Filter::Lanczos3)]
+    pub filter: Filter,
+
+    /// Resample in linear light rather than on gamma-encoded values - the default.
+    #[arg(long, overrides_with = "no_linear")]
+    pub linear: bool,
+    /// Resample the gamma-encoded values directly, which darkens fine detail.
+    #[arg(long, overrides_with = "linear")]
+    pub no_linear: bool,
+
+    /// Resample to this many times the size first, then average each block of pixels down to
+    /// one, which keeps the thin lines of line art and screenshots crisp when shrinking.
+    ///
+    /// Vector images are rasterized at the larger size instead.
+    #[arg(long, value_name = "N")]
+    pub supersample: Option<NonZeroU32>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct SeamlessArgs {
+    /// The image to make seamless.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The width of the border blended with the offset copy, in pixels.
+    ///
+    /// Defaults to a quarter of the image's shorter side, and can be at most half of it.
+    #[arg(long, value_name = "N")]
+    pub blend: Option<NonZeroU32>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct CompositeArgs {
+    /// The image to overlay onto.
+    #[arg(value_name = "BASE", value_hint = ValueHint::FilePath)]
+    pub base_path: PathBuf,
+    /// The image to overlay.
+    #[arg(value_name = "OVERLAY", value_hint = ValueHint::FilePath)]
+    pub overlay_path: PathBuf,
+
+    /// The output image path - will overwrite the base if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The offset of the overlay from the position given by `gravity`, in pixels.
+    #[arg(
+        long,
+        value_name = "X x Y",
+        default_value = "0x0",
+        allow_hyphen_values = true
+    )]
+    pub position: Size<i64>,
+    /// Where the overlay is placed on the base before it is offset by `position`.
+    #[arg(long, value_enum, default_value_t = Gravity::NorthWest)]
+    pub gravity: Gravity,
+    /// The opacity of the overlay, as a percentage.
+    #[arg(long, value_name = "P", default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub opacity: u8,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct BlurArgs {
+    /// The image to blur.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The standard deviation of the blur, in pixels.
+    #[arg(long, value_name = "SIGMA")]
+    pub sigma: Sigma,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct BorderArgs {
+    /// The image to draw a border around.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The thickness of the border, in pixels.
+    #[arg(long, value_name = "N")]
+    pub thickness: NonZeroU32,
+    /// The hex color of the border, such as `#000` or `#ff880080`.
+    #[arg(long, value_name = "COLOR", default_value = "#000")]
+    pub color: Color,
+    /// Draw the border as dashes of this length, in pixels, with gaps of the same length between
+    /// them.
+    #[arg(long, value_name = "LENGTH")]
+    pub dash: Option<NonZeroU32>,
+    /// Extend the canvas to draw the border around the image, rather than over its edges.
+    #[arg(long)]
+    pub outside: bool,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct CompareArgs {
+    /// The first image to compare.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub first_path: PathBuf,
+    /// The second image to compare.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub second_path: PathBuf,
+
+    /// How the similarity of the images is measured.
+    #[arg(long, value_enum, default_value_t = Metric::Psnr)]
+    pub metric: Metric,
+
+    /// Print a JSON object with the score instead of just the score.
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct DiffArgs {
+    /// The first image to compare, which is shown faded beneath the highlights.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub first_path: PathBuf,
+    /// The second image to compare.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub second_path: PathBuf,
+
+    /// The output image path.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: PathBuf,
+
+    /// Only highlight pixels where a channel differs by more than this, from 0 to 255.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub threshold: u8,
+    /// The hex color of the highlights, such as `#f00` - more opaque the more the pixels differ.
+    #[arg(long, value_name = "COLOR", default_value = "#f00")]
+    pub color: Color,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct ThumbnailArgs {
+    /// The images to make thumbnails of.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, required = true)]
+    pub input_paths: Vec<PathBuf>,
+
+    /// The path of each thumbnail, with the same placeholders as the main command's
+    /// `output_template`.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{dir}/{stem}.thumb.{ext}"
+    )]
+    pub output_template: String,
+
+    /// The size to fit each thumbnail within, in pixels.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub size: Size<NonZeroU32>,
+    /// Cover the whole size and crop what overflows it, rather than fitting within it.
+    #[arg(long)]
+    pub crop: bool,
+    /// Crop to where the image is busiest rather than to its center, which keeps subjects that
+    /// are off-center from being cut off.
+    #[arg(long, requires = "crop")]
+    pub smart_crop: bool,
+
+    /// How good the thumbnails look, traded against their size and how long they take.
+    ///
+    /// Sets the encoding `quality` unless it is given.
+    #[arg(long, value_enum, default_value_t = ThumbnailQuality::Medium)]
+    pub quality_preset: ThumbnailQuality,
+
+    /// Don't rotate and flip each input according to its EXIF orientation before making its
+    /// thumbnail.
+    #[arg(long)]
+    pub no_auto_orient: bool,
+
+    /// Show progress - by default, progress is only shown if stdout is a terminal.
+    #[arg(long, overrides_with = "no_progress")]
+    pub progress: bool,
+    /// Never show progress.
+    #[arg(long, overrides_with = "progress")]
+    pub no_progress: bool,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct MontageArgs {
+    /// The images to lay out, in order from left to right and then top to bottom.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, required = true)]
+    pub input_paths: Vec<PathBuf>,
+
+    /// The output image path.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: PathBuf,
+
+    /// The number of images in each row - defaults to as close to a square grid as possible.
+    #[arg(long, value_name = "N")]
+    pub columns: Option<NonZeroU32>,
+    /// The size of each cell of the grid, in pixels, which larger images are shrunk to fit.
+    ///
+    /// Defaults to the size of the largest image.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub cell: Option<Size<NonZeroU32>>,
+    /// The space around and between the cells, in pixels.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub spacing: u32,
+    /// The hex color behind the images, such as `#fff` or `#0000` for transparent.
+    #[arg(long, value_name = "COLOR", default_value = "#fff")]
+    pub background: Color,
+
+    /// Label each cell with the file name of its image, drawn in uppercase in a small built-in
+    /// font.
+    #[arg(long)]
+    pub labels: bool,
+    /// The hex color of the labels.
+    #[arg(long, value_name = "COLOR", default_value = "#000")]
+    pub label_color: Color,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct SplitArgs {
+    /// The image to split.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The path of each tile, with the same placeholders as the main command's
+    /// `output_template`, along with `{column}` and `{row}` for the tile's position, from 1.
+    ///
+    /// `{width}` and `{height}` are the tile's dimensions, and `{index}` is its position in
+    /// reading order.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{dir}/{stem}-{row}-{column}.{ext}"
+    )]
+    pub output_template: String,
+
+    /// Split the image into this many tiles across and down, as close to the same size as
+    /// possible.
+    #[arg(
+        long,
+        value_name = "ACROSS x DOWN",
+        required_unless_present = "tile_size",
+        conflicts_with = "tile_size"
+    )]
+    pub grid: Option<Size<NonZeroU32>>,
+    /// Split the image into tiles of this size, in pixels, leaving the tiles at the right and
+    /// bottom edges smaller if it does not divide evenly.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub tile_size: Option<Size<NonZeroU32>>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct SpritesheetArgs {
+    /// The images to pack, in order from left to right and then top to bottom, or a single
+    /// animated image to pack the frames of.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, required = true)]
+    pub input_paths: Vec<PathBuf>,
+
+    /// The output image path.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: PathBuf,
+
+    /// The number of sprites in each row - defaults to as close to a square grid as possible.
+    #[arg(long, value_name = "N")]
+    pub columns: Option<NonZeroU32>,
+    /// Pack the sprites as tightly as possible instead of in a grid, which wastes far less space
+    /// for sprites of different sizes.
+    #[arg(long, conflicts_with = "columns")]
+    pub pack: bool,
+    /// The transparent space around each sprite, in pixels, which keeps neighboring sprites from
+    /// bleeding into each other when the sheet is filtered.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub padding: u32,
+
+    /// Write the name, position, and size of each sprite to this file, as CSS classes if it ends
+    /// in `.css` and as JSON otherwise.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub layout: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct ConcatArgs {
+    /// The images to append, in order.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, required = true)]
+    pub input_paths: Vec<PathBuf>,
+
+    /// The output image path.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: PathBuf,
+
+    /// Which way the images are appended.
+    #[arg(long, value_enum, default_value_t = Direction::Horizontal)]
+    pub direction: Direction,
+    /// Where images smaller than the largest are placed across the direction they are appended.
+    #[arg(long, value_enum, default_value_t = Alignment::Start)]
+    pub align: Alignment,
+    /// The hex color of the space beside images smaller than the largest, such as `#fff` or
+    /// `#0000` for transparent.
+    #[arg(long, value_name = "COLOR", default_value = "#0000")]
+    pub fill: Color,
+    /// Scale each image to the height of the tallest, or the width of the widest when appending
+    /// vertically, keeping its aspect ratio, so the result has no ragged edges.
+    #[arg(long, conflicts_with = "align")]
+    pub match_size: bool,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct RunArgs {
+    /// The image to apply the operations to.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// The operations to apply, each following a `--`: `crop WIDTHxHEIGHT+X+Y`, `scale Nx`,
+    /// `scale WIDTHxHEIGHT`, `tile WIDTHxHEIGHT`, `blur SIGMA`, `border N [COLOR]`, or `rotate
+    /// 90|180|270`.
+    #[arg(value_name = "OPERATION", last = true, required = true)]
+    pub operations: Vec<String>,
+
+    /// Don't rotate and flip the input according to its EXIF orientation before applying the
+    /// operations.
+    #[arg(long)]
+    pub no_auto_orient: bool,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct BenchArgs {
+    /// The image to resize.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The scale (arbitrary units) of the image currently, or `auto` or `auto:reduced` as for
+    /// resizing.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub fit_scale: FitScale,
+    /// The scale to extend the image to, in relation to `fit_scale`.
+    #[arg(long, value_name = "WIDTH x HEIGHT", value_parser = Size::<Ratio>::parse_nonzero)]
+    pub output_scale: Size<Ratio>,
+    /// How fractional output dimensions are rounded.
+    #[arg(long, value_enum, default_value_t = Rounding::Nearest)]
+    pub round: Rounding,
+
+    /// The number of times to run each stage.
+    #[arg(short = 'n', long, value_name = "ITERATIONS", default_value_t = NonZeroUsize::MIN)]
+    pub iterations: NonZeroUsize,
+
+    /// The file extension of the format to encode the image in - the input's format if not
+    /// provided.
+    #[arg(long, value_name = "EXTENSION")]
+    pub format: Option<String>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[cfg(feature = "plugins")]
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct ApplyArgs {
+    /// The image to apply the operations to.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input_path: PathBuf,
+
+    /// The output image path - will overwrite the input if not provided.
+    #[arg(short, long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub output_path: Option<PathBuf>,
+
+    /// A WebAssembly module, in its binary or text format, whose operation to apply - can be
+    /// repeated.
+    #[arg(long = "plugin", value_name = "MODULE", value_hint = ValueHint::FilePath, required = true)]
+    pub plugin_paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[cfg(feature = "self-update")]
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct SelfUpdateArgs {
+    /// Only print whether a newer release is available, without installing it.
+    #[arg(long)]
+    pub check: bool,
+    /// Install the latest release without asking.
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct ServeArgs {
+    /// The address to listen on.
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+    pub listen: std::net::SocketAddr,
+    /// The largest upload accepted, in bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = 64 * 1024 * 1024)]
+    pub max_upload_size: u64,
+
+    #[command(flatten)]
+    pub encode_args: EncodeArgs,
+    #[command(flatten)]
+    pub limit_args: LimitArgs,
+}
+
+#[cfg(unix)]
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct DaemonArgs {
+    /// The path of the socket to listen on, which is replaced if no daemon is listening on it.
+    #[arg(value_name = "SOCKET", value_hint = ValueHint::FilePath)]
+    pub socket_path: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct LimitArgs {
+    /// The maximum number of pixels in an input or output image.
+    ///
+    /// Guards against decompression bombs and mistyped scales allocating huge amounts of memory.
+    #[arg(long, value_name = "PIXELS", default_value_t = 1 << 28)]
+    pub max_pixels: u64,
+
+    /// The largest width and height an output image may have.
+    #[arg(long, value_name = "WIDTH x HEIGHT")]
+    pub max_output_size: Option<Size<u32>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Parser)]
+pub struct EncodeArgs {
+    /// The quality of lossy JPEG, WebP, and AVIF outputs, from 0 to 100.
+    #[arg(long, value_name = "QUALITY", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub quality: Option<u8>,
+
+    /// Encode WebP outputs losslessly.
+    #[arg(long)]
+    pub webp_lossless: bool,
+
+    /// Reduce PNG outputs to a palette of at most this many colors, from 2 to 256, and encode
+    /// them as indexed colors.
+    ///
+    /// Images with few colors, such as most tiled patterns, become much smaller.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(2..=256))]
+    pub colors: Option<u16>,
+    /// How the colors between those in the `colors` palette are approximated, trading banding in
+    /// gradients against size and speed.
+    #[arg(long, value_enum, default_value_t = Dither::None, requires = "colors")]
+    pub dither: Dither,
+
+    /// Record this pixel density, in dots per inch, in PNG and JPEG outputs, replacing any the
+    /// input had.
+    ///
+    /// This only changes how large the output is printed, not its pixels.
+    #[arg(long, value_name = "N")]
+    pub set_dpi: Option<NonZeroU32>,
+
+    /// The speed of encoding AVIF outputs, from 1 (slowest, smallest) to 10 (fastest, largest).
+    #[cfg(feature = "avif")]
+    #[arg(long, value_name = "SPEED", default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub avif_speed: u8,
+}
+
+/// How good thumbnails look, traded against their size and how long they take.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum ThumbnailQuality {
+    /// Fast resampling and a quality of 50.
+    Low,
+    /// Fast resampling and a quality of 75.
+    Medium,
+    /// Lanczos resampling in linear light and a quality of 90.
+    High,
+}
+
+/// Which way images are appended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Direction {
+    /// From left to right.
+    Horizontal,
+    /// From top to bottom.
+    Vertical,
+}
+
+/// Where an image is placed along the length of a larger space.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Alignment {
+    /// Against the top or left edge.
+    #[value(alias = "top", alias = "left")]
+    Start,
+    /// In the middle.
+    Center,
+    /// Against the bottom or right edge.
+    #[value(alias = "bottom", alias = "right")]
+    End,
+}
+
+/// A measure of how similar two images are.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Metric {
+    /// Peak signal-to-noise ratio across every channel, in decibels - higher is more similar, and
+    /// identical images are infinitely similar.
+    Psnr,
+    /// Mean structural similarity of the images' luma, from -1 to 1 - 1 is identical.
+    Ssim,
+}
+
+/// How the colors of an image between those in its palette are approximated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Dither {
+    /// Map each pixel to the nearest color, which leaves visible bands in gradients.
+    None,
+    /// Offset each pixel by a repeating threshold pattern, which is fast and compresses well.
+    Ordered,
+    /// Spread the error of each pixel onto its neighbors, which looks smoothest but compresses
+    /// worst.
+    FloydSteinberg,
+}
+
+/// How an image's alpha channel is applied when it is repeated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Alpha {
+    /// Composite the image over what is already there.
+    Blend,
+    /// Copy the image verbatim, including its alpha channel.
+    Replace,
+}
+
+/// Where the repetitions of an image start from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, clap::ValueEnum)]
+pub enum TileOrigin {
+    /// The top left corner.
+    #[default]
+    Corner,
+    /// The center, with any space left over split evenly between each side.
+    Center,
+}
+
+/// How the rows of repetitions of an image are laid out.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum TileLayout {
+    /// Each repetition is directly below the one above it.
+    #[default]
+    Grid,
+    /// Every other row is offset to the right by this fraction of a repetition's width, which is
+    /// between 0 and 1.
+    Brick(f32),
+}
+
+// NaN is rejected when parsing, so every layout is equal to itself.
+impl Eq for TileLayout {}
+
+impl FromStr for TileLayout {
+    type Err = String;
+
+    /// Parses `grid`, `brick`, or `brick:OFFSET`.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.split_once(':') {
+            None if string == "grid" => Ok(TileLayout::Grid),
+            None if string == "brick" => Ok(TileLayout::Brick(0.5)),
+            Some(("brick", offset)) => match offset.parse() {
+                Ok(fraction) if fraction > 0.0 && fraction < 1.0 => Ok(TileLayout::Brick(fraction)),
+                Ok(_) => Err(format!("brick offset {offset} is not between 0 and 1")),
+
+                Err(error) => Err(format!("invalid brick offset {offset}: {error}")),
+            },
+
+            _ => Err(format!(
+                "unknown tile layout: {string} (expected grid or brick[:OFFSET])"
+            )),
+        }
+    }
+}
+
+/// The scale an image is at currently, in relation to the scale it is extended to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FitScale {
+    /// The given scale.
+    Size(Size<Ratio>),
+    /// The image's own dimensions, reduced to their simplest ratio if `reduced`.
+    Auto { reduced: bool },
+}
+
+impl FromStr for FitScale {
+    type Err = String;
+
+    /// Parses `auto`, `auto:reduced`, or a [`Size`].
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "auto" => Ok(FitScale::Auto { reduced: false }),
+            "auto:reduced" => Ok(FitScale::Auto { reduced: true }),
+
+            _ => Size::<Ratio>::parse_nonzero(string)
+                .map(FitScale::Size)
+                .map_err(|error| error.to_string()),
+        }
+    }
+}
+
+/// An attribute of an input file that can be copied onto its output.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Preserve {
+    /// The permission bits.
+    Mode,
+    /// The access and modification times.
+    Times,
+}
+
+/// A shell to generate completions for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Shell {
+    /// The Bourne Again shell.
+    Bash,
+    /// Elvish.
+    Elvish,
+    /// The friendly interactive shell.
+    Fish,
+    /// PowerShell.
+    Powershell,
+    /// The Z shell.
+    Zsh,
+    /// Nushell.
+    Nushell,
+    /// A completion spec for Fig, rather than a shell.
+    Fig,
+}
+
+impl Generator for Shell {
+    fn file_name(&self, name: &str) -> String {
+        match self {
+            Self::Bash => clap_complete::Shell::Bash.file_name(name),
+            Self::Elvish => clap_complete::Shell::Elvish.file_name(name),
+            Self::Fish => clap_complete::Shell::Fish.file_name(name),
+            Self::Powershell => clap_complete::Shell::PowerShell.file_name(name),
+            Self::Zsh => clap_complete::Shell::Zsh.file_name(name),
+            Self::Nushell => clap_complete_nushell::Nushell.file_name(name),
+            Self::Fig => clap_complete_fig::Fig.file_name(name),
+        }
+    }
+
+    fn generate(&self, command: &clap::Command, buffer: &mut dyn std::io::Write) {
+        match self {
+            Self::Bash => clap_complete::Shell::Bash.generate(command, buffer),
+            Self::Elvish => clap_complete::Shell::Elvish.generate(command, buffer),
+            Self::Fish => clap_complete::Shell::Fish.generate(command, buffer),
+            Self::Powershell => clap_complete::Shell::PowerShell.generate(command, buffer),
+            Self::Zsh => clap_complete::Shell::Zsh.generate(command, buffer),
+            Self::Nushell => clap_complete_nushell::Nushell.generate(command, buffer),
+            Self::Fig => clap_complete_fig::Fig.generate(command, buffer),
+        }
+    }
+}
+
+/// How a fractional dimension is rounded to a whole number of pixels.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Rounding {
+    /// Down.
+    Floor,
+    /// Up.
+    Ceil,
+    /// To the nearest whole number, with halves rounded up.
+    Nearest,
+    /// To the nearest even number, as required by many video encoders.
+    Even,
+}
+
+/// A filter used to resample an image.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Filter {
+    /// Nearest neighbor.
+    Nearest,
+    /// Linear.
+    Triangle,
+    /// Cubic.
+    CatmullRom,
+    /// Gaussian.
+    Gaussian,
+    /// Lanczos with a window of 3.
+    Lanczos3,
+}
+
+/// How the extended canvas of a resized image is filled.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fill {
+    /// Repeat the image across the canvas.
+    Tile,
+    /// Center the image over a scaled-up, blurred copy of itself.
+    Blur,
+    /// Repeat the image across a linear gradient.
+    Gradient(Gradient),
+}
+
+impl FromStr for Fill {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "tile" => Ok(Self::Tile),
+            "blur" => Ok(Self::Blur),
+
+            _ => match string.strip_prefix("gradient:") {
+                Some(gradient) => Ok(Self::Gradient(gradient.parse()?)),
+                None => Err(format!(
+                    "unknown fill: {string} (expected tile, blur, or gradient:COLOR..COLOR)"
+                )),
+            },
+        }
+    }
+}
+
+/// A linear gradient between two colors.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Gradient {
+    /// The color the gradient starts from.
+    pub from: Color,
+    /// The color the gradient ends at.
+    pub to: Color,
+    /// The direction of the gradient, in degrees clockwise from pointing right.
+    pub angle: u16,
+}
+
+impl FromStr for Gradient {
+    type Err = String;
+
+    /// Parses a gradient of the form `COLOR..COLOR[@ANGLE]`, which points down if no angle is
+    /// given.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (colors, angle) = match string.rsplit_once('@') {
+            Some((colors, angle)) => {
+                let angle: i32 = angle
+                    .parse()
+                    .map_err(|error| format!("invalid gradient angle {angle}: {error}"))?;
+
+                (colors, angle.rem_euclid(360) as u16)
+            }
+
+            None => (string, 90),
+        };
+
+        let (from, to) = colors
+            .split_once("..")
+            .ok_or_else(|| format!("invalid gradient: no '..' separator found in {string}"))?;
+
+        Ok(Gradient {
+            from: from.parse()?,
+            to: to.parse()?,
+            angle,
+        })
+    }
+}
+
+/// An 8-bit RGBA color.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Color(pub [u8; 4]);
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses a hex color of the form `#RGB`, `#RGBA`, `#RRGGBB`, or `#RRGGBBAA`.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid color: {string} (expected a hex color like #ff8800)");
+
+        let hex = string.strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        // Each digit is repeated in the short forms, so `#f80` is `#ff8800`.
+        let digits = match hex.len() {
+            3 | 4 => 1,
+            6 | 8 => 2,
+
+            _ => return Err(invalid()),
+        };
+
+        let mut color = [u8::MAX; 4];
+        for (sample, start) in color.iter_mut().zip((0..hex.len()).step_by(digits)) {
+            let channel =
+                u8::from_str_radix(&hex[start..start + digits], 16).map_err(|_| invalid())?;
+
+            *sample = if digits == 1 { channel * 0x11 } else { channel };
+        }
+
+        Ok(Color(color))
+    }
+}
+
+/// A drop shadow drawn behind an image.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Shadow {
+    /// The offset of the shadow from the image, in pixels.
+    pub offset: Size<i32>,
+    /// The standard deviation of the shadow's blur, or `None` for a hard shadow.
+    pub blur: Option<Sigma>,
+    /// The color of the shadow.
+    pub color: Color,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Shadow {
+            offset: Size(0, 4),
+            blur: Some(Sigma(8.0)),
+            color: Color([0, 0, 0, 0x80]),
+        }
+    }
+}
+
+impl FromStr for Shadow {
+    type Err = String;
+
+    /// Parses comma-separated `offset=XxY`, `blur=SIGMA`, and `color=COLOR` settings, using the
+    /// default for any left out.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut shadow = Shadow::default();
+
+        for setting in string.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("invalid shadow setting: no '=' found in {setting}"))?;
+
+            match key {
+                "offset" => shadow.offset = value.parse().map_err(|error| format!("{error}"))?,
+                "blur" if value.parse() == Ok(0.0) => shadow.blur = None,
+                "blur" => shadow.blur = Some(value.parse()?),
+                "color" => shadow.color = value.parse()?,
+
+                _ => {
+                    return Err(format!(
+                        "unknown shadow setting: {key} (expected offset, blur, or color)"
+                    ))
+                }
+            }
+        }
+
+        Ok(shadow)
+    }
+}
+
+/// How the repetitions of an image are randomly varied.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TileRandom {
+    /// Whether repetitions are rotated.
+    pub rotate: bool,
+    /// Whether repetitions are flipped.
+    pub flip: bool,
+    /// The seed of the random variations, or `None` for a different seed each time.
+    pub seed: Option<u64>,
+}
+
+impl FromStr for TileRandom {
+    type Err = String;
+
+    /// Parses comma-separated `rotate`, `flip`, and `seed=N` settings, at least one of `rotate`
+    /// and `flip` being required.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut random = TileRandom {
+            rotate: false,
+            flip: false,
+            seed: None,
+        };
+
+        for setting in string.split(',') {
+            match setting.split_once('=') {
+                None if setting == "rotate" => random.rotate = true,
+                None if setting == "flip" => random.flip = true,
+                Some(("seed", seed)) => {
+                    random.seed = Some(
+                        seed.parse()
+                            .map_err(|error| format!("invalid seed {seed}: {error}"))?,
+                    )
+                }
+
+                _ => {
+                    return Err(format!(
+                        "unknown tile variation: {setting} (expected rotate, flip, or seed=N)"
+                    ))
+                }
+            }
+        }
+
+        if !random.rotate && !random.flip {
+            return Err("at least one of rotate and flip must be given".to_owned());
+        }
+
+        Ok(random)
+    }
+}
+
+/// Where one image is placed within another: against one of its edges or corners, or in its
+/// center.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, clap::ValueEnum)]
+pub enum Gravity {
+    /// The top left corner.
+    NorthWest,
+    /// The middle of the top edge.
+    North,
+    /// The top right corner.
+    NorthEast,
+    /// The middle of the left edge.
+    West,
+    /// The center.
+    Center,
+    /// The middle of the right edge.
+    East,
+    /// The bottom left corner.
+    SouthWest,
+    /// The middle of the bottom edge.
+    South,
+    /// The bottom right corner.
+    SouthEast,
+}
+
+/// The standard deviation of a Gaussian blur, in pixels, which is positive and finite.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sigma(pub f32);
+
+// NaN is rejected when parsing, so every sigma is equal to itself.
+impl Eq for Sigma {}
+
+impl FromStr for Sigma {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.parse() {
+            Ok(sigma) if f32::is_finite(sigma) && sigma > 0.0 => Ok(Sigma(sigma)),
+            Ok(_) => Err(format!("{string} is not a positive number")),
+
+            Err(error) => Err(error.to_string()),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Size<T>(pub T, pub T);
 
+/// The characters that may separate the width and height of a [`Size`].
+const SIZE_SEPARATORS: [char; 5] = ['x', 'X', '*', ',', ':'];
+
+/// Splits the given size at its first separator, skipping the `x` of a `px` unit so that sizes
+/// like `1080pxx720px` split between the two lengths.
+fn split_dimensions(string: &str) -> Option<(&str, &str)> {
+    let (index, separator) = string.char_indices().find(|&(index, char)| {
+        SIZE_SEPARATORS.contains(&char) && !(char == 'x' && string[..index].ends_with('p'))
+    })?;
+
+    Some((&string[..index], &string[index + separator.len_utf8()..]))
+}
+
+/// One dimension of a scale, which may be fractional, such as the `2.5` in `2.5x1.78`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Ratio(pub f64);
+
+// NaN is rejected when parsing, so every ratio is equal to itself.
+impl Eq for Ratio {}
+
+/// One dimension of an output size, in the units it was given in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Length {
+    /// A number of pixels, which may be fractional.
+    Pixels(f64),
+    /// A percentage of the image's own length.
+    Percent(f64),
+    /// A physical length in inches, converted to pixels at the output's resolution.
+    Inches(f64),
+}
+
+// NaN is rejected when parsing, so every length is equal to itself.
+impl Eq for Length {}
+
+impl FromStr for Length {
+    type Err = RatioError;
+
+    /// Parses a number of pixels, optionally followed by `px`, or a number followed by `%`, `mm`,
+    /// `cm`, or `in`.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        if let Some(percent) = string.strip_suffix('%') {
+            return percent
+                .trim()
+                .parse()
+                .map(|Ratio(percent)| Length::Percent(percent));
+        }
+        if let Some(pixels) = string.strip_suffix("px") {
+            return pixels
+                .trim()
+                .parse()
+                .map(|Ratio(pixels)| Length::Pixels(pixels));
+        }
+
+        let physical = [
+            ("mm", MILLIMETERS_PER_INCH),
+            ("cm", MILLIMETERS_PER_INCH / 10.0),
+            ("in", 1.0),
+        ]
+        .into_iter()
+        .find_map(|(unit, per_inch)| Some((string.strip_suffix(unit)?, per_inch)));
+
+        match physical {
+            Some((length, per_inch)) => {
+                let Ratio(length) = length.trim().parse()?;
+                Ok(Length::Inches(length / per_inch))
+            }
+
+            None => string.parse().map(|Ratio(pixels)| Length::Pixels(pixels)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RatioError {
+    OutOfRange(String),
+    ParseError(ParseFloatError),
+}
+
+impl Display for RatioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfRange(string) => {
+                write!(
+                    f,
+                    "invalid scale: {string} is not a finite, non-negative number"
+                )
+            }
+
+            Self::ParseError(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl Error for RatioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::OutOfRange(_) => None,
+            Self::ParseError(error) => Some(error),
+        }
+    }
+}
+
+impl FromStr for Ratio {
+    type Err = RatioError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.parse::<f64>() {
+            Ok(ratio) if ratio.is_finite() && ratio >= 0.0 => Ok(Ratio(ratio)),
+            Ok(_) => Err(RatioError::OutOfRange(string.to_owned())),
+
+            Err(error) => Err(RatioError::ParseError(error)),
+        }
+    }
+}
+
+/// Sizes of common resolutions, which can be given by name in place of any size.
+const RESOLUTIONS: [(&str, &str); 12] = [
+    ("480p", "854x480"),
+    ("720p", "1280x720"),
+    ("hd", "1280x720"),
+    ("1080p", "1920x1080"),
+    ("fhd", "1920x1080"),
+    ("1440p", "2560x1440"),
+    ("qhd", "2560x1440"),
+    ("2160p", "3840x2160"),
+    ("4k", "3840x2160"),
+    ("uhd", "3840x2160"),
+    ("4320p", "7680x4320"),
+    ("8k", "7680x4320"),
+];
+
+/// Sizes of paper in millimeters, which can be given by name with a resolution in place of any
+/// size, such as `a4@300dpi`.
+const PAPER_SIZES: [(&str, f64, f64); 7] = [
+    ("a3", 297.0, 420.0),
+    ("a4", 210.0, 297.0),
+    ("a5", 148.0, 210.0),
+    ("a6", 105.0, 148.0),
+    ("letter", 215.9, 279.4),
+    ("legal", 215.9, 355.6),
+    ("tabloid", 279.4, 431.8),
+];
+
+/// The number of millimeters in an inch.
+const MILLIMETERS_PER_INCH: f64 = 25.4;
+
+/// Sizes named in the `sizes` table of the config file, by lowercase name, as the sizes they
+/// stand for - these take precedence over the built-in names.
+///
+/// This is set when the config file is read, before any arguments are parsed.
+pub static NAMED_SIZES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum SizeError<ParseErr> {
     MissingSeparator(String),
+    Zero(String),
+    MissingResolution(String),
+    InvalidResolution(String),
     DimensionParseError(ParseErr),
 }
 
@@ -57,7 +1661,28 @@ impl<ParseErr: Display> Display for SizeError<ParseErr> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::MissingSeparator(string) => {
-                write!(f, "invalid size: no 'x' separator found in {string}")
+                write!(
+                    f,
+                    "invalid size: no separator such as 'x' found in {string}"
+                )
+            }
+            Self::Zero(string) => {
+                write!(
+                    f,
+                    "invalid size: {string} has a dimension of 0, but sizes and scales cannot be 0"
+                )
+            }
+            Self::MissingResolution(name) => {
+                write!(
+                    f,
+                    "invalid size: {name} is a paper size, so needs a resolution, such as {name}@300dpi"
+                )
+            }
+            Self::InvalidResolution(string) => {
+                write!(
+                    f,
+                    "invalid size: {string} does not end in a positive resolution, such as @300dpi"
+                )
             }
 
             Self::DimensionParseError(error) => error.fmt(f),
@@ -68,7 +1693,10 @@ impl<ParseErr: Display> Display for SizeError<ParseErr> {
 impl<ParseErr: Error + 'static> Error for SizeError<ParseErr> {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::MissingSeparator(_) => None,
+            Self::MissingSeparator(_)
+            | Self::Zero(_)
+            | Self::MissingResolution(_)
+            | Self::InvalidResolution(_) => None,
             Self::DimensionParseError(error) => Some(error),
         }
     }
@@ -80,20 +1708,289 @@ where
 {
     type Err = SizeError<T::Err>;
 
+    /// Parses a width and height separated by `x`, `X`, `*`, `,`, or `:`, ignoring any
+    /// whitespace around either, or a single number as both.
+    ///
+    /// A size may also be given by name, either one from the config file, a resolution such as
+    /// `1080p` or `4k`, or a paper size at a resolution such as `a4@300dpi`.
     fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let name = string.trim().to_lowercase();
+
+        let named = NAMED_SIZES
+            .get()
+            .and_then(|sizes| sizes.get(&name))
+            .map(String::as_str)
+            .or_else(|| {
+                RESOLUTIONS
+                    .iter()
+                    .find(|(resolution, _)| *resolution == name)
+                    .map(|(_, size)| *size)
+            });
+        if let Some(size) = named {
+            return Self::parse_dimensions(size);
+        }
+
+        let (paper, resolution) = match name.split_once('@') {
+            Some((paper, resolution)) => (paper, Some(resolution)),
+            None => (name.as_str(), None),
+        };
+        if let Some(&(_, width, height)) = PAPER_SIZES.iter().find(|(size, ..)| *size == paper) {
+            let resolution =
+                resolution.ok_or_else(|| SizeError::MissingResolution(name.clone()))?;
+            let dpi = resolution
+                .strip_suffix("dpi")
+                .and_then(|dpi| dpi.parse::<f64>().ok())
+                .filter(|dpi| dpi.is_finite() && *dpi > 0.0)
+                .ok_or_else(|| SizeError::InvalidResolution(string.to_owned()))?;
+
+            let pixels = |millimeters: f64| (millimeters / MILLIMETERS_PER_INCH * dpi).round();
+            return Self::parse_dimensions(&format!("{}x{}", pixels(width), pixels(height)));
+        }
+
+        Self::parse_dimensions(string)
+    }
+}
+
+impl Size<Ratio> {
+    /// Parses a scale as a [`Size`], erroring if either of its dimensions is 0, since scales are
+    /// divided by.
+    pub fn parse_nonzero(string: &str) -> Result<Self, SizeError<RatioError>> {
+        match string.parse()? {
+            Size(Ratio(0.0), _) | Size(_, Ratio(0.0)) => Err(SizeError::Zero(string.to_owned())),
+
+            size => Ok(size),
+        }
+    }
+}
+
+impl Size<Length> {
+    /// Parses an output size as a [`Size`], erroring if either of its dimensions is 0, since no
+    /// image can be empty.
+    pub fn parse_nonzero(string: &str) -> Result<Self, SizeError<RatioError>> {
+        let is_zero = |length| {
+            matches!(
+                length,
+                Length::Pixels(0.0) | Length::Percent(0.0) | Length::Inches(0.0)
+            )
+        };
+
+        match string.parse()? {
+            Size(width, height) if is_zero(width) || is_zero(height) => {
+                Err(SizeError::Zero(string.to_owned()))
+            }
+
+            size => Ok(size),
+        }
+    }
+}
+
+impl<T: FromStr> Size<T> {
+    /// Parses a width and height separated by `x`, `X`, `*`, `,`, or `:`, ignoring any
+    /// whitespace around either, or a single number as both.
+    fn parse_dimensions(string: &str) -> Result<Self, SizeError<T::Err>> {
         // Find the separator.
-        let separator_pos = string
-            .find(|char| matches!(char, 'x'))
-            .ok_or_else(|| SizeError::MissingSeparator(string.to_owned()))?;
+        let Some((width, height)) = split_dimensions(string) else {
+            // Without one, a single number is a square.
+            return match (string.trim().parse(), string.trim().parse()) {
+                (Ok(width), Ok(height)) => Ok(Size(width, height)),
+                _ => Err(SizeError::MissingSeparator(string.to_owned())),
+            };
+        };
 
         // Parse the width and height.
-        let width = string[..separator_pos]
+        let width = width
+            .trim()
             .parse()
             .map_err(SizeError::DimensionParseError)?;
-        let height = string[separator_pos + 1..]
+        let height = height
+            .trim()
             .parse()
             .map_err(SizeError::DimensionParseError)?;
 
         Ok(Size(width, height))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the given output size, in pixels for any physical lengths.
+    fn length_size(string: &str) -> Size<Length> {
+        string.parse().unwrap()
+    }
+
+    /// Asserts that both dimensions of the size given by `string` parse as `expected`.
+    fn assert_lengths(string: &str, expected: Length) {
+        let Size(width, height) = length_size(string);
+
+        for length in [width, height] {
+            match (length, expected) {
+                (Length::Pixels(length), Length::Pixels(expected))
+                | (Length::Percent(length), Length::Percent(expected))
+                | (Length::Inches(length), Length::Inches(expected)) => {
+                    assert!((length - expected).abs() < 1e-9, "{string}: {length}");
+                }
+
+                _ => panic!("{string}: expected {expected:?}, got {length:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn units_parse_on_both_sides() {
+        assert_lengths("20x20", Length::Pixels(20.0));
+        assert_lengths("20pxx20px", Length::Pixels(20.0));
+        assert_lengths("20px x 20px", Length::Pixels(20.0));
+        assert_lengths("50%x50%", Length::Percent(50.0));
+        assert_lengths("50 % x 50 %", Length::Percent(50.0));
+        assert_lengths("2inx2in", Length::Inches(2.0));
+        assert_lengths("50.8mmx50.8mm", Length::Inches(2.0));
+        assert_lengths("5.08cm x 5.08cm", Length::Inches(2.0));
+    }
+
+    #[test]
+    fn units_can_be_mixed() {
+        assert_eq!(
+            length_size("1080pxx720px"),
+            Size(Length::Pixels(1080.0), Length::Pixels(720.0))
+        );
+        assert_eq!(
+            length_size("200%x1080px"),
+            Size(Length::Percent(200.0), Length::Pixels(1080.0))
+        );
+        assert_eq!(
+            length_size("1080px x 200%"),
+            Size(Length::Pixels(1080.0), Length::Percent(200.0))
+        );
+        assert_eq!(
+            length_size("1inx96px"),
+            Size(Length::Inches(1.0), Length::Pixels(96.0))
+        );
+        assert_eq!(
+            length_size("25.4mm*50%"),
+            Size(Length::Inches(1.0), Length::Percent(50.0))
+        );
+    }
+
+    #[test]
+    fn a_single_length_is_square() {
+        assert_eq!(
+            length_size("16px"),
+            Size(Length::Pixels(16.0), Length::Pixels(16.0))
+        );
+        assert_eq!(
+            length_size("50%"),
+            Size(Length::Percent(50.0), Length::Percent(50.0))
+        );
+    }
+
+    #[test]
+    fn fractional_scales_parse() {
+        assert_eq!("2.5x1.78".parse(), Ok(Size(Ratio(2.5), Ratio(1.78))));
+        assert_eq!("0.5".parse(), Ok(Size(Ratio(0.5), Ratio(0.5))));
+        assert_eq!("16 : 9".parse(), Ok(Size(Ratio(16.0), Ratio(9.0))));
+
+        for scale in ["-1x2", "infx1", "NaNx1", "2.5x"] {
+            assert!(scale.parse::<Size<Ratio>>().is_err(), "{scale}");
+        }
+    }
+
+    #[test]
+    fn named_resolutions_parse() {
+        assert_eq!("1080p".parse(), Ok(Size(1920_u32, 1080)));
+        assert_eq!(" 4K ".parse(), Ok(Size(3840_u32, 2160)));
+        assert_eq!("qhd".parse(), Ok(Size(Ratio(2560.0), Ratio(1440.0))));
+        assert_eq!(
+            length_size("720p"),
+            Size(Length::Pixels(1280.0), Length::Pixels(720.0))
+        );
+    }
+
+    #[test]
+    fn paper_sizes_parse_at_a_resolution() {
+        assert_eq!("a4@300dpi".parse(), Ok(Size(2480_u32, 3508)));
+        assert_eq!("Letter@72dpi".parse(), Ok(Size(612_u32, 792)));
+        assert_eq!("a5@150.5dpi".parse(), Ok(Size(877_u32, 1244)));
+    }
+
+    #[test]
+    fn paper_sizes_need_a_valid_resolution() {
+        assert!(matches!(
+            "a4".parse::<Size<u32>>(),
+            Err(SizeError::MissingResolution(_))
+        ));
+
+        for size in ["a4@300", "a4@0dpi", "a4@-300dpi", "a4@dpi"] {
+            assert!(
+                matches!(
+                    size.parse::<Size<u32>>(),
+                    Err(SizeError::InvalidResolution(_))
+                ),
+                "{size}"
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_are_named_in_the_config_file() {
+        // No other test names sizes, so these are the only ones.
+        NAMED_SIZES.get_or_init(|| HashMap::from([("banner".to_owned(), "1500x500".to_owned())]));
+
+        assert_eq!("Banner".parse(), Ok(Size(1500_u32, 500)));
+    }
+
+    #[test]
+    fn zero_scales_are_rejected() {
+        assert!("0x5".parse::<Size<NonZeroU32>>().is_err());
+        assert!(matches!(
+            Size::<Ratio>::parse_nonzero("0x5"),
+            Err(SizeError::Zero(_))
+        ));
+        assert!(matches!(
+            Size::<Ratio>::parse_nonzero("2.5x0"),
+            Err(SizeError::Zero(_))
+        ));
+        assert_eq!(
+            Size::<Ratio>::parse_nonzero("2.5x1.78"),
+            Ok(Size(Ratio(2.5), Ratio(1.78)))
+        );
+    }
+
+    #[test]
+    fn zero_output_sizes_are_rejected() {
+        for size in ["0x0", "0pxx720px", "50%x0%", "0inx1in", "1080px x 0mm"] {
+            assert!(
+                matches!(Size::<Length>::parse_nonzero(size), Err(SizeError::Zero(_))),
+                "{size}"
+            );
+        }
+
+        assert_eq!(
+            Size::<Length>::parse_nonzero("200%x1080px"),
+            Ok(Size(Length::Percent(200.0), Length::Pixels(1080.0)))
+        );
+    }
+
+    #[test]
+    fn unknown_units_are_rejected() {
+        assert!("20ptx20pt".parse::<Size<Length>>().is_err());
+        assert!("20px720".parse::<Size<Length>>().is_err());
+        assert!("-20pxx20px".parse::<Size<Length>>().is_err());
+    }
+
+    #[test]
+    fn streaming_conflicts_with_palettes() {
+        let args = [
+            "img-resize",
+            "in.png",
+            "--fit-scale",
+            "1x1",
+            "--output-scale",
+            "2x2",
+        ];
+
+        assert!(Args::try_parse_from(args.iter().chain(&["--stream"])).is_ok());
+        assert!(Args::try_parse_from(args.iter().chain(&["--stream", "--colors", "16"])).is_err());
+    }
+}
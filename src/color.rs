@@ -0,0 +1,53 @@
+use image::DynamicImage;
+use qcms::{DataType, Intent, Profile, Transform};
+use std::error::Error;
+
+/// Converts the given `image` from the color space described by the given `icc` profile to sRGB.
+///
+/// Only 8-bit RGB and RGBA images can be converted, so other images are converted to RGBA8 first.
+///
+/// Errors if the profile is invalid or cannot be applied to RGB images.
+pub fn convert_to_srgb(image: &mut DynamicImage, icc: &[u8]) -> Result<(), Box<dyn Error>> {
+    let profile = Profile::new_from_slice(icc, false).ok_or("invalid ICC profile")?;
+    let transform = |data_type| {
+        Transform::new(
+            &profile,
+            &Profile::new_sRGB(),
+            data_type,
+            Intent::Perceptual,
+        )
+        .ok_or("the ICC profile cannot be converted to sRGB")
+    };
+
+    match image {
+        DynamicImage::ImageRgb8(image) => transform(DataType::RGB8)?.apply(image),
+        DynamicImage::ImageRgba8(image) => transform(DataType::RGBA8)?.apply(image),
+
+        _ => {
+            let mut rgba = image.to_rgba8();
+            transform(DataType::RGBA8)?.apply(&mut rgba);
+
+            *image = rgba.into();
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the given gamma-encoded sRGB `value`, from 0 to 1, to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts the given linear light `value`, from 0 to 1, to gamma-encoded sRGB.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
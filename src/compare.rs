@@ -0,0 +1,195 @@
+use crate::cli::{CompareArgs, Metric};
+use crate::error::InvalidArguments;
+use crate::file;
+use crate::output::Output;
+use clap::ValueEnum;
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Luma};
+use log::debug;
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+
+/// The standard deviation of the Gaussian window that SSIM compares the images within.
+const SSIM_SIGMA: f32 = 1.5;
+/// The constant that keeps SSIM stable where the means of both windows are near zero, for luma
+/// from 0 to 1.
+const SSIM_C1: f64 = 0.01 * 0.01;
+/// The constant that keeps SSIM stable where the variances of both windows are near zero.
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+/// The score comparing two images printed by `compare`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    /// The path of the first image.
+    pub first: String,
+    /// The path of the second image.
+    pub second: String,
+    /// The name of the metric the images were compared with.
+    pub metric: String,
+    /// How similar the images are according to the metric, or `null` for identical images
+    /// compared by PSNR.
+    pub score: f64,
+}
+
+/// Compares the images according to the given `compare_args`, printing the score to `output`.
+pub fn run(compare_args: &CompareArgs, output: &Output) -> Result<(), Box<dyn Error>> {
+    let CompareArgs {
+        first_path,
+        second_path,
+        metric,
+        json,
+        limit_args,
+    } = compare_args;
+
+    let (first, second) = read_pair(first_path, second_path, limit_args.max_pixels)?;
+
+    debug!(
+        "comparing {} and {} by {metric:?}",
+        first_path.display(),
+        second_path.display()
+    );
+    let score = match metric {
+        Metric::Psnr => psnr(&first, &second),
+        Metric::Ssim => ssim(&first, &second),
+    };
+
+    if *json {
+        let comparison = Comparison {
+            first: first_path.display().to_string(),
+            second: second_path.display().to_string(),
+            metric: metric
+                .to_possible_value()
+                .expect("no metrics are skipped")
+                .get_name()
+                .to_owned(),
+            score,
+        };
+
+        // Serializing the comparison cannot fail: it contains no maps and no fallible
+        // `Serialize` implementations.
+        output.println(serde_json::to_string(&comparison).expect("comparisons are serializable"));
+    } else {
+        output.println(format_args!("{score:.4}"));
+    }
+
+    Ok(())
+}
+
+/// Decodes the images at `first_path` and `second_path` to compare them.
+///
+/// Errors if either has more than `max_pixels` pixels, or if their dimensions differ.
+pub fn read_pair(
+    first_path: &Path,
+    second_path: &Path,
+    max_pixels: u64,
+) -> Result<(DynamicImage, DynamicImage), Box<dyn Error>> {
+    let (first, _) = file::Input::read(first_path, true)?
+        .limit_pixels(max_pixels)?
+        .decode()?;
+    let (second, _) = file::Input::read(second_path, true)?
+        .limit_pixels(max_pixels)?
+        .decode()?;
+
+    if first.dimensions() != second.dimensions() {
+        let (first_width, first_height) = first.dimensions();
+        let (second_width, second_height) = second.dimensions();
+
+        return Err(InvalidArguments(format!(
+            "{} is {first_width}x{first_height} but {} is {second_width}x{second_height}, so they cannot be compared",
+            first_path.display(),
+            second_path.display(),
+        ))
+        .into());
+    }
+
+    Ok((first, second))
+}
+
+/// The peak signal-to-noise ratio between the given images of the same dimensions, in decibels,
+/// across every channel of their 8-bit RGBA samples.
+///
+/// Identical images have an infinite PSNR.
+pub fn psnr(first: &DynamicImage, second: &DynamicImage) -> f64 {
+    let (first, second) = (first.to_rgba8(), second.to_rgba8());
+
+    let squared_error: f64 = first
+        .iter()
+        .zip(second.iter())
+        .map(|(&first, &second)| (f64::from(first) - f64::from(second)).powi(2))
+        .sum();
+    let mean_squared_error = squared_error / first.len() as f64;
+
+    10.0 * (f64::from(u8::MAX).powi(2) / mean_squared_error).log10()
+}
+
+/// The mean structural similarity between the luma of the given images of the same dimensions,
+/// compared within a Gaussian window around each pixel.
+pub fn ssim(first: &DynamicImage, second: &DynamicImage) -> f64 {
+    let (first, second) = (first.to_luma32f(), second.to_luma32f());
+    let product = |first: &ImageBuffer<Luma<f32>, Vec<f32>>,
+                   second: &ImageBuffer<Luma<f32>, Vec<f32>>| {
+        ImageBuffer::from_fn(first.width(), first.height(), |x, y| {
+            Luma([first.get_pixel(x, y)[0] * second.get_pixel(x, y)[0]])
+        })
+    };
+
+    // The mean, variance, and covariance of the luma within the window around each pixel.
+    let first_mean = imageops::blur(&first, SSIM_SIGMA);
+    let second_mean = imageops::blur(&second, SSIM_SIGMA);
+    let first_squared_mean = imageops::blur(&product(&first, &first), SSIM_SIGMA);
+    let second_squared_mean = imageops::blur(&product(&second, &second), SSIM_SIGMA);
+    let product_mean = imageops::blur(&product(&first, &second), SSIM_SIGMA);
+
+    let total: f64 = first_mean
+        .iter()
+        .zip(second_mean.iter())
+        .zip(first_squared_mean.iter())
+        .zip(second_squared_mean.iter())
+        .zip(product_mean.iter())
+        .map(
+            |((((&first_mean, &second_mean), &first_squared), &second_squared), &product)| {
+                let (first_mean, second_mean) = (f64::from(first_mean), f64::from(second_mean));
+                let first_variance = f64::from(first_squared) - first_mean * first_mean;
+                let second_variance = f64::from(second_squared) - second_mean * second_mean;
+                let covariance = f64::from(product) - first_mean * second_mean;
+
+                ((2.0 * first_mean * second_mean + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                    / ((first_mean * first_mean + second_mean * second_mean + SSIM_C1)
+                        * (first_variance + second_variance + SSIM_C2))
+            },
+        )
+        .sum();
+
+    total / first_mean.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// A flat gray image.
+    fn gray() -> RgbaImage {
+        RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]))
+    }
+
+    #[test]
+    fn identical_images_are_perfectly_similar() {
+        let image = DynamicImage::from(gray());
+
+        assert_eq!(psnr(&image, &image), f64::INFINITY);
+        assert!((ssim(&image, &image) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn single_pixel_changes_give_the_expected_psnr() {
+        let mut changed = gray();
+        changed.get_pixel_mut(1, 2).0[0] += 10;
+
+        // A squared error of 100 in one of the 64 samples.
+        let expected = 10.0 * (255.0_f64.powi(2) / (100.0 / 64.0)).log10();
+        let psnr = psnr(&gray().into(), &changed.into());
+
+        assert!((psnr - expected).abs() < 1e-9, "{psnr}");
+    }
+}
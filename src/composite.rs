@@ -0,0 +1,62 @@
+use crate::cli::{CompositeArgs, Size};
+use crate::{file, scale};
+use image::{imageops, DynamicImage, GenericImageView};
+use log::{debug, info};
+use std::error::Error;
+
+/// Overlays one image onto another according to the given `composite_args`.
+pub fn run(composite_args: &CompositeArgs) -> Result<(), Box<dyn Error>> {
+    let CompositeArgs {
+        base_path,
+        overlay_path,
+        output_path,
+        position: Size(x, y),
+        gravity,
+        opacity,
+        encode_args,
+        limit_args,
+    } = composite_args;
+
+    let read = |path| file::Input::read(path, true)?.limit_pixels(limit_args.max_pixels);
+    let (base, metadata) = read(base_path)?.decode()?;
+    let (overlay, _) = read(overlay_path)?.decode()?;
+    let new_path = output_path.as_deref().unwrap_or(base_path);
+
+    let (anchor_x, anchor_y) = gravity.position(base.dimensions(), overlay.dimensions());
+    let position = (anchor_x + x, anchor_y + y);
+    debug!(
+        "{}: overlaying onto {} at {position:?}",
+        overlay_path.display(),
+        base_path.display()
+    );
+
+    let new_image = composite(&base, &overlay, position, *opacity);
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image, &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
+
+/// Overlays the `overlay` image onto the `base` image with its top left corner at the given
+/// `position`, keeping the sample format of the `base`.
+///
+/// The overlay's alpha channel is scaled by `opacity`, a percentage, before it is blended.
+pub fn composite(
+    base: &DynamicImage,
+    overlay: &DynamicImage,
+    (x, y): (i64, i64),
+    opacity: u8,
+) -> DynamicImage {
+    let mut new_image = base.to_rgba32f();
+    let mut overlay = overlay.to_rgba32f();
+
+    let opacity = f32::from(opacity) / 100.0;
+    for pixel in overlay.pixels_mut() {
+        pixel.0[3] *= opacity;
+    }
+
+    imageops::overlay(&mut new_image, &overlay, x, y);
+
+    scale::with_color(new_image.into(), base.color())
+}
@@ -0,0 +1,142 @@
+use crate::cli::{Alignment, Color, ConcatArgs, Direction};
+use crate::error::InvalidArguments;
+use crate::metadata::Metadata;
+use crate::{file, scale};
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use log::{debug, info};
+use std::error::Error;
+
+/// Appends the images given in the `concat_args`.
+pub fn run(concat_args: &ConcatArgs) -> Result<(), Box<dyn Error>> {
+    let ConcatArgs {
+        input_paths,
+        output_path,
+        direction,
+        align,
+        fill,
+        match_size,
+        encode_args,
+        limit_args,
+    } = concat_args;
+
+    let mut images = Vec::with_capacity(input_paths.len());
+    for input_path in input_paths {
+        let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+        let (image, _) = input.decode()?;
+
+        images.push(image);
+    }
+
+    if *match_size {
+        let breadth = images
+            .iter()
+            .map(|image| along(image.dimensions(), *direction).1)
+            .max()
+            .unwrap_or(0);
+
+        debug!("scaling each image to {breadth} pixels across");
+        images = images
+            .iter()
+            .map(|image| match_breadth(image, breadth, *direction))
+            .collect();
+    }
+
+    let dimensions = dimensions(&images, *direction)?;
+    crate::check_output_dimensions(dimensions, limit_args)?;
+
+    debug!("appending {} images {direction:?}ly", images.len());
+    let new_image = concat(&images, dimensions, *direction, *align, *fill);
+
+    info!("writing {}", output_path.display());
+    file::write(
+        &new_image.into(),
+        &Metadata::default(),
+        output_path,
+        encode_args,
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// The dimensions of the given `images` appended in the given `direction`.
+///
+/// Errors if the result would be too large for any image.
+fn dimensions(
+    images: &[DynamicImage],
+    direction: Direction,
+) -> Result<(u32, u32), InvalidArguments> {
+    let (length, breadth) = images.iter().fold((0_u64, 0), |(length, breadth), image| {
+        let (image_length, image_breadth) = along(image.dimensions(), direction);
+        (length + u64::from(image_length), breadth.max(image_breadth))
+    });
+
+    match u32::try_from(length) {
+        Ok(length) => Ok(along((length, breadth), direction)),
+
+        Err(_) => Err(InvalidArguments(format!(
+            "the appended images would be {length} pixels long, which is too large for any image"
+        ))),
+    }
+}
+
+/// The length and breadth of the given dimensions in the given `direction`, or the dimensions of
+/// the given length and breadth - swapping them is its own inverse.
+fn along((width, height): (u32, u32), direction: Direction) -> (u32, u32) {
+    match direction {
+        Direction::Horizontal => (width, height),
+        Direction::Vertical => (height, width),
+    }
+}
+
+/// Scales the given `image` to be `breadth` pixels across the given `direction`, keeping its
+/// aspect ratio.
+fn match_breadth(image: &DynamicImage, breadth: u32, direction: Direction) -> DynamicImage {
+    let (length, image_breadth) = along(image.dimensions(), direction);
+    if image_breadth == breadth {
+        return image.clone();
+    }
+
+    let ratio = f64::from(breadth) / f64::from(image_breadth);
+    let length = ((f64::from(length) * ratio).round() as u32).max(1);
+
+    scale::scale(
+        image,
+        along((length, breadth), direction),
+        FilterType::Lanczos3,
+        true,
+    )
+}
+
+/// Appends the given `images` in the given `direction` onto a new image of the given
+/// dimensions filled with the `fill` color.
+///
+/// Images narrower than the new image across the direction are placed according to `align`.
+pub fn concat(
+    images: &[DynamicImage],
+    dimensions: (u32, u32),
+    direction: Direction,
+    align: Alignment,
+    Color(fill): Color,
+) -> RgbaImage {
+    let (width, height) = dimensions;
+    let mut new_image = RgbaImage::from_pixel(width, height, Rgba(fill));
+    let (_, breadth) = along(dimensions, direction);
+
+    let mut position = 0;
+    for image in images {
+        let (length, image_breadth) = along(image.dimensions(), direction);
+        let offset = align.offset(breadth, image_breadth);
+
+        let (x, y) = match direction {
+            Direction::Horizontal => (position, offset),
+            Direction::Vertical => (offset, position),
+        };
+        imageops::overlay(&mut new_image, &image.to_rgba8(), x, y);
+
+        position += i64::from(length);
+    }
+
+    new_image
+}
@@ -0,0 +1,260 @@
+use crate::cli;
+use crate::error::InvalidArguments;
+use clap::builder::BoolishValueParser;
+use clap::{Arg, ArgAction, Command};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+/// The key of the table of presets in the configuration file.
+pub const PRESETS: &str = "presets";
+/// The key of the table of named sizes in the configuration file.
+const SIZES: &str = "sizes";
+/// The prefix of the environment variables that options can be given by.
+const ENV_PREFIX: &str = "IMG_RESIZE_";
+
+/// The path of the configuration file: `$XDG_CONFIG_HOME/img-resize/config.toml`, or
+/// `~/.config/img-resize/config.toml` if `XDG_CONFIG_HOME` is not set.
+pub fn path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|config_home| !config_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_home.join(cli::NAME).join("config.toml"))
+}
+
+/// Reads the configuration file, returning an empty table if there is none.
+pub fn read() -> Result<toml::Table, Box<dyn Error>> {
+    let Some(path) = path() else {
+        return Ok(toml::Table::new());
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(config) => config.parse().map_err(|error| {
+            InvalidArguments(format!("invalid config file {}: {error}", path.display())).into()
+        }),
+
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(toml::Table::new()),
+        Err(error) => Err(format!("failed to read config file {}: {error}", path.display()).into()),
+    }
+}
+
+/// Writes the given `config` to the configuration file, replacing it.
+pub fn write(config: &toml::Table) -> Result<(), Box<dyn Error>> {
+    let path = path().ok_or("cannot find the config directory - set XDG_CONFIG_HOME or HOME")?;
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(&path, toml::to_string(config)?)
+        .map_err(|error| format!("failed to write config file {}: {error}", path.display()))?;
+
+    Ok(())
+}
+
+/// The table of presets in the given `config`, each of which is a table of options.
+pub fn presets(config: &toml::Table) -> Result<toml::Table, Box<dyn Error>> {
+    match config.get(PRESETS) {
+        Some(toml::Value::Table(presets)) => Ok(presets.clone()),
+        Some(_) => {
+            Err(InvalidArguments(format!("{PRESETS} must be a table in the config file")).into())
+        }
+
+        None => Ok(toml::Table::new()),
+    }
+}
+
+/// The table of named sizes in the given `config`, each of which is the size it stands for, by
+/// lowercase name.
+fn sizes(config: &toml::Table) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let sizes = match config.get(SIZES) {
+        Some(toml::Value::Table(sizes)) => sizes,
+        Some(_) => {
+            return Err(
+                InvalidArguments(format!("{SIZES} must be a table in the config file")).into(),
+            )
+        }
+
+        None => return Ok(HashMap::new()),
+    };
+
+    sizes
+        .iter()
+        .map(|(name, size)| match size {
+            toml::Value::String(size) => Ok((name.to_lowercase(), size.clone())),
+
+            _ => Err(
+                InvalidArguments(format!("size {name} must be a string in the config file")).into(),
+            ),
+        })
+        .collect()
+}
+
+/// Sets the default value of each option of the given `command` and its subcommands to its value
+/// in the configuration file, so that only options given on the command line override it.
+///
+/// Each key in the configuration file is the long name of an option, such as `quality` for
+/// `--quality`. If a `preset` is given, its options are applied over the rest.
+///
+/// Errors if a key is not the name of any option, or if the preset does not exist.
+pub fn apply(command: Command, preset: Option<&str>) -> Result<Command, Box<dyn Error>> {
+    let mut config = read()?;
+    let presets = presets(&config)?;
+    config.remove(PRESETS);
+
+    // The config file is read again once the preset is known, but is the same both times.
+    let _ = cli::NAMED_SIZES.set(sizes(&config)?);
+    config.remove(SIZES);
+
+    let command = apply_options(command, config)?;
+
+    match preset {
+        Some(name) => match presets.get(name) {
+            Some(toml::Value::Table(options)) => apply_options(command, options.clone()),
+            Some(_) => Err(InvalidArguments(format!(
+                "preset {name} must be a table in the config file"
+            ))
+            .into()),
+
+            None => Err(InvalidArguments(format!(
+                "unknown preset: {name} (see `{} preset list`)",
+                cli::NAME
+            ))
+            .into()),
+        },
+
+        None => Ok(command),
+    }
+}
+
+/// Lets each option of the given `command` and its subcommands be given by an environment variable
+/// named after it, such as `IMG_RESIZE_QUALITY` for `--quality`.
+///
+/// Environment variables override the configuration file, and options given on the command line
+/// override both.
+pub fn with_env(command: Command) -> Command {
+    command
+        .mut_args(|arg| {
+            let Some(name) = arg.get_long().map(env_name) else {
+                return arg;
+            };
+
+            // Flags accept values like `1` and `yes` as well as `true` from the environment.
+            match arg.get_action() {
+                ArgAction::SetTrue => arg.env(name).value_parser(BoolishValueParser::new()),
+                _ => arg.env(name),
+            }
+        })
+        .mut_subcommands(with_env)
+}
+
+/// The name of the environment variable for the option with the given long `name`.
+fn env_name(name: &str) -> String {
+    format!("{ENV_PREFIX}{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// Finds the option of the given `command` or its subcommands with the given long `name`.
+pub fn find_option<'a>(command: &'a Command, name: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(name))
+        .or_else(|| {
+            command
+                .get_subcommands()
+                .find_map(|subcommand| find_option(subcommand, name))
+        })
+}
+
+/// Sets the default value of each option of the given `command` named in `options` to its value.
+fn apply_options(command: Command, options: toml::Table) -> Result<Command, Box<dyn Error>> {
+    options
+        .into_iter()
+        .try_fold(command, |command, (key, value)| {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+
+                _ => {
+                    return Err(InvalidArguments(format!(
+                        "{key} must be a string, number, or boolean in the config file"
+                    ))
+                    .into())
+                }
+            };
+
+            match set_default(command, &key, &value) {
+                (command, true) => Ok(command),
+                (_, false) => Err(InvalidArguments(format!(
+                    "unknown option in the config file: {key}"
+                ))
+                .into()),
+            }
+        })
+}
+
+/// Sets the default value of any option of the given `command` or its subcommands with the given
+/// long `name` to `value`, returning whether there were any.
+///
+/// Options with a default value are no longer required.
+fn set_default(mut command: Command, name: &str, value: &str) -> (Command, bool) {
+    let ids: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| arg.get_long() == Some(name))
+        .map(|arg| arg.get_id().clone())
+        .collect();
+    let mut found = !ids.is_empty();
+
+    for id in ids {
+        command = command.mut_arg(id, |arg| {
+            arg.default_value(value.to_owned()).required(false)
+        });
+    }
+
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_owned())
+        .collect();
+
+    for subcommand in subcommands {
+        command = command.mut_subcommand(subcommand, |subcommand| {
+            let (subcommand, found_in_subcommand) = set_default(subcommand, name, value);
+            found |= found_in_subcommand;
+
+            subcommand
+        });
+    }
+
+    (command, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_read_by_lowercase_name() {
+        let config: toml::Table = "[sizes]\nBanner = \"1500x500\"\nsquare = \"1080\"\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            sizes(&config).unwrap(),
+            HashMap::from([
+                ("banner".to_owned(), "1500x500".to_owned()),
+                ("square".to_owned(), "1080".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn sizes_must_be_strings() {
+        let config: toml::Table = "[sizes]\nbanner = 1500\n".parse().unwrap();
+
+        assert!(sizes(&config).is_err());
+        assert!(sizes(&"sizes = 1".parse().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::cli::{self, DaemonArgs};
+use crate::error::{self, FileError, InvalidArguments};
+use crate::output::Output;
+use crate::progress;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+/// The reply to a command sent to the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Reply {
+    /// The code the command would have exited with if it were run on its own.
+    exit_code: u8,
+    /// Why the command failed, or the help or version it asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// What the command printed, such as its JSON reports, which would have gone to stdout.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    output: String,
+}
+
+/// Listens on the socket given by the `daemon_args` for commands to run until the process is
+/// stopped, running those sent on different connections in parallel.
+pub fn run(daemon_args: &DaemonArgs) -> Result<(), Box<dyn Error>> {
+    let DaemonArgs { socket_path } = daemon_args;
+
+    let listener = bind(socket_path)?;
+    info!("listening on {}", socket_path.display());
+
+    // There is no terminal to show the progress of commands on.
+    progress::hide();
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(error) = serve(stream) {
+                        warn!("failed to reply to a command: {error}");
+                    }
+                });
+            }
+
+            Err(error) => warn!("failed to accept a connection: {error}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Listens on the socket at `socket_path`, replacing it if it was left behind by a daemon that
+/// is no longer listening.
+///
+/// Errors if a daemon is still listening on it.
+fn bind(socket_path: &Path) -> Result<UnixListener, Box<dyn Error>> {
+    let bind_error = |source| FileError::Write {
+        path: socket_path.to_owned(),
+        source,
+    };
+
+    match UnixListener::bind(socket_path) {
+        Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(socket_path).is_ok() {
+                return Err(InvalidArguments(format!(
+                    "a daemon is already listening on {}",
+                    socket_path.display()
+                ))
+                .into());
+            }
+
+            debug!("replacing the stale socket {}", socket_path.display());
+            fs::remove_file(socket_path).map_err(bind_error)?;
+
+            Ok(UnixListener::bind(socket_path).map_err(bind_error)?)
+        }
+
+        result => Ok(result.map_err(bind_error)?),
+    }
+}
+
+/// Runs each command sent on the given `stream`, one per line, replying to each in turn.
+fn serve(stream: UnixStream) -> io::Result<()> {
+    let mut replies = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        debug!("running {line}");
+        let reply = execute(&line);
+
+        // Serializing the reply cannot fail: it contains no maps and no fallible `Serialize`
+        // implementations.
+        let reply = serde_json::to_string(&reply).expect("replies are serializable");
+        writeln!(replies, "{reply}")?;
+    }
+
+    Ok(())
+}
+
+/// Runs the command given by the JSON array of arguments on the given `line`, as if they were
+/// given on the command line with `--batch`, capturing what it prints for the reply.
+fn execute(line: &str) -> Reply {
+    let output = Output::captured();
+
+    let result = serde_json::from_str::<Vec<String>>(line)
+        .map_err(|error| InvalidArguments(format!("invalid command {line:?}: {error}")).into())
+        .and_then(|args| {
+            let args = iter::once(OsString::from(cli::NAME))
+                .chain(args.into_iter().map(OsString::from))
+                .collect();
+
+            crate::try_parse_args(args)
+        })
+        .and_then(|args| {
+            check(&args)?;
+
+            crate::execute(
+                cli::Args {
+                    batch: true,
+                    ..args
+                },
+                &output,
+            )
+        });
+
+    let (exit_code, message) = match result {
+        Ok(()) => (0, None),
+
+        Err(error) => match error.downcast::<clap::Error>() {
+            Ok(error) => (
+                u8::try_from(error.exit_code()).unwrap_or(u8::MAX),
+                Some(error.to_string()),
+            ),
+
+            Err(error) => (error::exit_code(&*error), Some(error.to_string())),
+        },
+    };
+
+    Reply {
+        exit_code,
+        message,
+        output: output.into_captured(),
+    }
+}
+
+/// Errors if the given `args` cannot be run by the daemon: those starting another daemon or
+/// watching an image, which never finish, and those setting `--jobs` to other than the daemon's
+/// own, as every command shares the daemon's threads.
+fn check(args: &cli::Args) -> Result<(), InvalidArguments> {
+    let unsupported = if let Some(cli::Subcommands::Daemon(_)) = args.subcommand {
+        Some("the daemon subcommand")
+    } else if args
+        .jobs
+        .is_some_and(|jobs| jobs.get() != rayon::current_num_threads())
+    {
+        Some("a different --jobs")
+    } else if args.resize_args.as_ref().is_some_and(|args| args.watch) {
+        Some("--watch")
+    } else {
+        None
+    };
+
+    match unsupported {
+        Some(unsupported) => Err(InvalidArguments(format!(
+            "{unsupported} cannot be used with the daemon"
+        ))),
+
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,110 @@
+use crate::cli::{Color, DiffArgs};
+use crate::metadata::Metadata;
+use crate::{compare, file};
+use image::{DynamicImage, Rgb, RgbImage};
+use log::info;
+use std::error::Error;
+
+/// How much of the first image's luma shows through beneath the highlights out of 255, with the
+/// rest made white so that the highlights stand out.
+const BACKGROUND_OPACITY: u16 = 64;
+
+/// Writes an image highlighting where the images given in the `diff_args` differ.
+pub fn run(diff_args: &DiffArgs) -> Result<(), Box<dyn Error>> {
+    let DiffArgs {
+        first_path,
+        second_path,
+        output_path,
+        threshold,
+        color,
+        encode_args,
+        limit_args,
+    } = diff_args;
+
+    let (first, second) = compare::read_pair(first_path, second_path, limit_args.max_pixels)?;
+
+    let (new_image, differing) = diff(&first, &second, *threshold, *color);
+    let pixels = u64::from(new_image.width()) * u64::from(new_image.height());
+    info!("{differing} of {pixels} pixels differ by more than {threshold}");
+
+    info!("writing {}", output_path.display());
+    file::write(
+        &new_image.into(),
+        &Metadata::default(),
+        output_path,
+        encode_args,
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// Highlights with the given `color` the pixels where a channel of the `first` and `second`
+/// images, which have the same dimensions, differs by more than `threshold`, returning the image
+/// along with the number of highlighted pixels.
+///
+/// Each highlight is half opaque for the smallest differences and fully opaque for the largest,
+/// over a faded grayscale copy of the `first` image.
+pub fn diff(
+    first: &DynamicImage,
+    second: &DynamicImage,
+    threshold: u8,
+    Color([red, green, blue, alpha]): Color,
+) -> (RgbImage, u64) {
+    let (first_luma, first, second) = (first.to_luma8(), first.to_rgba8(), second.to_rgba8());
+    let mut differing = 0;
+
+    let new_image = RgbImage::from_fn(first.width(), first.height(), |x, y| {
+        let faded = u8::MAX
+            - ((u16::from(u8::MAX - first_luma.get_pixel(x, y)[0]) * BACKGROUND_OPACITY)
+                / u16::from(u8::MAX)) as u8;
+
+        let difference = first
+            .get_pixel(x, y)
+            .0
+            .into_iter()
+            .zip(second.get_pixel(x, y).0)
+            .map(|(first, second)| first.abs_diff(second))
+            .max()
+            .unwrap_or_default();
+        if difference <= threshold {
+            return Rgb([faded; 3]);
+        }
+        differing += 1;
+
+        // From half to full opacity, scaled by the highlight's own alpha.
+        let opacity = (u32::from(u8::MAX) + u32::from(difference)) * u32::from(alpha)
+            / (2 * u32::from(u8::MAX));
+        let mix = |channel: u8| {
+            ((u32::from(channel) * opacity + u32::from(faded) * (u32::from(u8::MAX) - opacity))
+                / u32::from(u8::MAX)) as u8
+        };
+
+        Rgb([mix(red), mix(green), mix(blue)])
+    });
+
+    (new_image, differing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn only_differences_over_the_threshold_are_highlighted() {
+        let first = RgbaImage::from_pixel(3, 1, Rgba([100, 100, 100, 255]));
+        let mut second = first.clone();
+        second.get_pixel_mut(1, 0).0[1] += 5;
+        second.get_pixel_mut(2, 0).0[2] += 20;
+
+        let (new_image, differing) =
+            diff(&first.into(), &second.into(), 10, Color([255, 0, 0, 255]));
+
+        assert_eq!(differing, 1);
+        // The faded gray of the first image shows beneath pixels that don't differ by enough.
+        assert_eq!(new_image.get_pixel(0, 0), &Rgb([217; 3]));
+        assert_eq!(new_image.get_pixel(1, 0), &Rgb([217; 3]));
+        assert_ne!(new_image.get_pixel(2, 0), &Rgb([217; 3]));
+    }
+}
@@ -0,0 +1,384 @@
+use crate::cli::{Dither, EncodeArgs};
+use crate::quantize;
+#[cfg(feature = "avif")]
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{
+    ColorType, DynamicImage, ImageEncoder, ImageError, ImageFormat, ImageResult, Rgba, RgbaImage,
+};
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Cursor, Write};
+use webp::WebPConfig;
+
+/// The maximum number of colors in a PNG palette.
+const PALETTE_SIZE: usize = 256;
+/// The quality used for lossy WebP images if none is given.
+const DEFAULT_WEBP_QUALITY: u8 = 80;
+/// The quality used for AVIF images if none is given.
+#[cfg(feature = "avif")]
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+
+/// Encodes the given `image` in the given `format` according to the given `encode_args`.
+///
+/// The image is encoded in its own sample format if the format supports it, or as RGBA8 otherwise.
+/// If `indexed` is true and the image is a PNG with few enough colors, it is encoded as indexed
+/// colors with a palette instead.
+pub fn encode(
+    image: &DynamicImage,
+    format: ImageFormat,
+    encode_args: &EncodeArgs,
+    indexed: bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if format == ImageFormat::WebP {
+        return encode_webp(image, encode_args);
+    }
+
+    if let Some(colors) = encode_args.colors {
+        if format == ImageFormat::Png {
+            return Ok(encode_quantized_png(
+                image,
+                usize::from(colors),
+                encode_args.dither,
+            )?);
+        }
+
+        warn!("{format:?} images cannot be reduced to a palette, so --colors has been ignored");
+    }
+
+    if indexed && format == ImageFormat::Png {
+        match encode_indexed_png(image)? {
+            Some(encoded) => return Ok(encoded),
+            None => debug!(
+                "the image has more than {PALETTE_SIZE} colors, so it cannot be kept indexed"
+            ),
+        }
+    }
+
+    match encode_with_image(image, format, encode_args) {
+        Err(ImageError::Unsupported(_)) if image.color() != ColorType::Rgba8 => {
+            debug!(
+                "{format:?} images cannot be encoded as {:?}, so encoding as RGBA8 instead",
+                image.color(),
+            );
+
+            let image = DynamicImage::from(image.to_rgba8());
+            Ok(encode_with_image(&image, format, encode_args)?)
+        }
+
+        result => Ok(result?),
+    }
+}
+
+/// Encodes the given `image` in the given `format` using the `image` crate's encoders.
+fn encode_with_image(
+    image: &DynamicImage,
+    format: ImageFormat,
+    encode_args: &EncodeArgs,
+) -> ImageResult<Vec<u8>> {
+    let mut encoded = Vec::new();
+    let (width, height) = (image.width(), image.height());
+
+    match format {
+        ImageFormat::Jpeg if let Some(quality) = encode_args.quality => {
+            JpegEncoder::new_with_quality(&mut encoded, quality).write_image(
+                image.as_bytes(),
+                width,
+                height,
+                image.color(),
+            )?;
+        }
+
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => {
+            let quality = encode_args.quality.unwrap_or(DEFAULT_AVIF_QUALITY);
+
+            AvifEncoder::new_with_speed_quality(&mut encoded, encode_args.avif_speed, quality)
+                .write_image(image.as_bytes(), width, height, image.color())?;
+        }
+
+        _ => image.write_to(&mut Cursor::new(&mut encoded), format)?,
+    }
+
+    Ok(encoded)
+}
+
+/// Encodes the given `image` as an indexed PNG.
+///
+/// Returns [`None`] if the image has more colors than fit in a palette, or has more than 8 bits
+/// per sample.
+fn encode_indexed_png(image: &DynamicImage) -> Result<Option<Vec<u8>>, png::EncodingError> {
+    if !matches!(image.color(), ColorType::Rgb8 | ColorType::Rgba8) {
+        return Ok(None);
+    }
+    let image = image.to_rgba8();
+
+    match palette(&image, PALETTE_SIZE) {
+        Some((palette, indices)) => Ok(Some(write_indexed_png(&image, &palette, &indices)?)),
+        None => Ok(None),
+    }
+}
+
+/// Encodes the given `image` as an indexed PNG with a palette of at most `colors` colors.
+///
+/// The image's own colors are kept exactly if there are few enough of them, and it is
+/// [quantized] with the given `dither` otherwise.
+///
+/// [quantized]: quantize::quantize
+fn encode_quantized_png(
+    image: &DynamicImage,
+    colors: usize,
+    dither: Dither,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let image = image.to_rgba8();
+
+    let (palette, indices) = palette(&image, colors).unwrap_or_else(|| {
+        debug!("quantizing the image to {colors} colors");
+        quantize::quantize(&image, colors, dither)
+    });
+
+    write_indexed_png(&image, &palette, &indices)
+}
+
+/// The palette of colors in the given `image`, along with the index of each pixel's color in it.
+///
+/// Returns [`None`] if the image has more than `max_colors` colors.
+fn palette(image: &RgbaImage, max_colors: usize) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.pixels().len());
+
+    for Rgba(color) in image.pixels() {
+        let index = match lookup.get(color) {
+            Some(&index) => index,
+
+            None if palette.len() == max_colors => return None,
+            None => {
+                let index = palette.len() as u8;
+                palette.push(*color);
+                lookup.insert(*color, index);
+
+                index
+            }
+        };
+
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+/// Encodes an indexed PNG with the dimensions of the given `image`, made up of the given
+/// `indices` into the given `palette`.
+fn write_indexed_png(
+    image: &RgbaImage,
+    palette: &[[u8; 4]],
+    indices: &[u8],
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, image.width(), image.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|&[red, green, blue, _]| [red, green, blue])
+            .collect::<Vec<_>>(),
+    );
+
+    // The alpha of each palette entry - entries after the last are opaque.
+    if palette.iter().any(|&[.., alpha]| alpha != u8::MAX) {
+        encoder.set_trns(palette.iter().map(|&[.., alpha]| alpha).collect::<Vec<_>>());
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(indices)?;
+    writer.finish()?;
+
+    Ok(encoded)
+}
+
+/// Encodes the given `image` as a PNG with the given metadata `chunks`, writing it to `writer` one
+/// row at a time instead of into a buffer of its own, so that large images are not held in memory
+/// twice over.
+///
+/// The image is encoded in its own sample format if PNG supports it, or as RGBA8 otherwise, with
+/// the same settings as [`encode`].
+pub fn write_png(
+    image: &DynamicImage,
+    chunks: &[([u8; 4], Vec<u8>)],
+    writer: impl Write,
+) -> Result<(), png::EncodingError> {
+    let image = match png_color(image.color()) {
+        Some(_) => Cow::Borrowed(image),
+
+        None => {
+            debug!(
+                "PNG images cannot be encoded as {:?}, so encoding as RGBA8 instead",
+                image.color(),
+            );
+
+            Cow::Owned(DynamicImage::from(image.to_rgba8()))
+        }
+    };
+    let (color, depth) = png_color(image.color()).expect("RGBA8 is a PNG color type");
+
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    // The settings `image` 0.24's `PngEncoder::new` encodes with: its default `CompressionType` is
+    // `Fast`, and its default `FilterType::Adaptive` is written as `Sub` chosen adaptively.
+    encoder.set_compression(png::Compression::Fast);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+
+    let mut writer = encoder.write_header()?;
+    for (kind, data) in chunks {
+        writer.write_chunk(png::chunk::ChunkType(*kind), data)?;
+    }
+    let mut writer = writer.stream_writer()?;
+
+    let row_length = image.width() as usize * usize::from(image.color().bytes_per_pixel());
+    let mut big_endian_row = Vec::new();
+    for row in image.as_bytes().chunks_exact(row_length) {
+        if depth == png::BitDepth::Sixteen {
+            // PNG samples are big endian, whereas the image's are native endian.
+            big_endian_row.clear();
+            big_endian_row.extend(
+                row.chunks_exact(2)
+                    .flat_map(|sample| u16::from_ne_bytes([sample[0], sample[1]]).to_be_bytes()),
+            );
+
+            writer.write_all(&big_endian_row)?;
+        } else {
+            writer.write_all(row)?;
+        }
+    }
+
+    writer.finish()
+}
+
+/// The PNG color type and bit depth of the given `color`, if PNG supports it.
+fn png_color(color: ColorType) -> Option<(png::ColorType, png::BitDepth)> {
+    use png::{BitDepth, ColorType as PngColor};
+
+    match color {
+        ColorType::L8 => Some((PngColor::Grayscale, BitDepth::Eight)),
+        ColorType::L16 => Some((PngColor::Grayscale, BitDepth::Sixteen)),
+        ColorType::La8 => Some((PngColor::GrayscaleAlpha, BitDepth::Eight)),
+        ColorType::La16 => Some((PngColor::GrayscaleAlpha, BitDepth::Sixteen)),
+        ColorType::Rgb8 => Some((PngColor::Rgb, BitDepth::Eight)),
+        ColorType::Rgb16 => Some((PngColor::Rgb, BitDepth::Sixteen)),
+        ColorType::Rgba8 => Some((PngColor::Rgba, BitDepth::Eight)),
+        ColorType::Rgba16 => Some((PngColor::Rgba, BitDepth::Sixteen)),
+
+        _ => None,
+    }
+}
+
+/// Encodes the given `image` as WebP according to the given `encode_args`.
+///
+/// WebP images only support RGBA8, so the image is always converted.
+fn encode_webp(image: &DynamicImage, encode_args: &EncodeArgs) -> Result<Vec<u8>, Box<dyn Error>> {
+    let image = image.to_rgba8();
+    let config = webp_config(encode_args)?;
+
+    let encoded = webp::Encoder::from_rgba(&image, image.width(), image.height())
+        .encode_advanced(&config)
+        .map_err(|error| format!("failed to encode WebP image: {error:?}"))?;
+
+    Ok(encoded.to_vec())
+}
+
+/// The configuration for encoding WebP images according to the given `encode_args`.
+pub fn webp_config(encode_args: &EncodeArgs) -> Result<WebPConfig, Box<dyn Error>> {
+    let mut config = WebPConfig::new().map_err(|()| "failed to configure WebP encoder")?;
+
+    config.lossless = i32::from(encode_args.webp_lossless);
+    config.quality = f32::from(encode_args.quality.unwrap_or(DEFAULT_WEBP_QUALITY));
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use flate2::read::ZlibDecoder;
+    use image::{ImageBuffer, Rgba};
+    use std::io::Read;
+
+    /// The zlib stream of the image data in the given encoded PNG.
+    fn image_data(png: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut rest = &png[8..];
+
+        while let [l0, l1, l2, l3, a, b, c, d, chunk @ ..] = rest {
+            let length = u32::from_be_bytes([*l0, *l1, *l2, *l3]) as usize;
+            if [*a, *b, *c, *d] == *b"IDAT" {
+                data.extend_from_slice(&chunk[..length]);
+            }
+
+            // Chunk data, CRC.
+            rest = &chunk[length + 4..];
+        }
+
+        data
+    }
+
+    fn filtered_rows(png: &[u8]) -> Vec<u8> {
+        let mut rows = Vec::new();
+        ZlibDecoder::new(&image_data(png)[..])
+            .read_to_end(&mut rows)
+            .unwrap();
+
+        rows
+    }
+
+    fn assert_encoded_alike(image: &DynamicImage) {
+        let encoded = encode(
+            image,
+            ImageFormat::Png,
+            &EncodeArgs::parse_from([""]),
+            false,
+        )
+        .unwrap();
+        let mut written = Vec::new();
+        write_png(image, &[], &mut written).unwrap();
+
+        // The zlib header records the compression level, and the same rows are only filtered
+        // alike with the same filters.
+        assert_eq!(image_data(&written)[..2], image_data(&encoded)[..2]);
+        assert_eq!(filtered_rows(&written), filtered_rows(&encoded));
+    }
+
+    #[test]
+    fn streamed_pngs_are_encoded_like_buffered_ones() {
+        let image = ImageBuffer::from_fn(64, 48, |x, y| {
+            Rgba([
+                (x * 4) as u8,
+                (y * 5) as u8,
+                (x * y) as u8,
+                255 - (x + y) as u8,
+            ])
+        });
+
+        assert_encoded_alike(&DynamicImage::from(image));
+    }
+
+    #[test]
+    fn streamed_16_bit_pngs_are_encoded_like_buffered_ones() {
+        let image = ImageBuffer::from_fn(64, 48, |x, y| {
+            Rgba([
+                (x * 1000) as u16,
+                (y * 1300) as u16,
+                (x * y * 20) as u16,
+                u16::MAX,
+            ])
+        });
+
+        assert_encoded_alike(&DynamicImage::from(image));
+    }
+}
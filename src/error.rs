@@ -0,0 +1,229 @@
+use image::ImageError;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The exit code for failures without a more specific code.
+pub const FAILURE: u8 = 1;
+/// The exit code for invalid arguments, which is also used by clap.
+pub const INVALID_ARGUMENTS: u8 = 2;
+/// The exit code for failing to decode an image.
+pub const DECODING: u8 = 3;
+/// The exit code for failing to encode an image.
+pub const ENCODING: u8 = 4;
+/// The exit code for failing to read or write a file.
+pub const IO: u8 = 5;
+
+/// An error caused by invalid arguments, or an invalid config file or preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidArguments(pub String);
+
+impl Display for InvalidArguments {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for InvalidArguments {}
+
+/// An error reading, decoding, encoding, or writing an image, naming the file it failed on.
+#[derive(Debug)]
+pub enum FileError {
+    /// The input file could not be read.
+    Read { path: PathBuf, source: io::Error },
+    /// The input image has more pixels than allowed by `--max-pixels`.
+    TooLarge {
+        path: PathBuf,
+        dimensions: (u32, u32),
+        max_pixels: u64,
+    },
+    /// The input image could not be decoded.
+    Decode { path: PathBuf, source: ImageError },
+    /// The output image could not be encoded.
+    Encode {
+        path: PathBuf,
+        source: Box<dyn Error>,
+    },
+    /// The output file could not be written.
+    Write { path: PathBuf, source: io::Error },
+    /// The output file already exists, and `--no-clobber` was used.
+    Exists { path: PathBuf },
+}
+
+impl FileError {
+    /// A suggestion of how to fix the error, if there is one.
+    fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Read { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => Some("check that the path is correct"),
+                io::ErrorKind::PermissionDenied => {
+                    Some("check that you have permission to read it")
+                }
+
+                _ => None,
+            },
+
+            Self::Decode { source, .. } => match source {
+                ImageError::Unsupported(_) => Some("check that its extension matches its format"),
+                ImageError::Limits(_) => {
+                    Some("it needs too much memory to decode (see --max-pixels)")
+                }
+
+                _ => None,
+            },
+
+            Self::Encode { source, .. } => match source.downcast_ref() {
+                Some(ImageError::Unsupported(_)) => {
+                    Some("check that the output path has the extension of a supported format")
+                }
+
+                _ => None,
+            },
+
+            Self::Write { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => Some("check that the output directory exists"),
+                io::ErrorKind::PermissionDenied => {
+                    Some("check that you have permission to write to it")
+                }
+
+                _ => None,
+            },
+
+            Self::Exists { .. } => Some("remove --no-clobber to overwrite it"),
+            Self::TooLarge { .. } => None,
+        }
+    }
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())?
+            }
+            Self::Decode { path, source } => {
+                write!(f, "failed to decode {}: {source}", path.display())?
+            }
+            Self::Encode { path, source } => {
+                write!(f, "failed to encode {}: {source}", path.display())?
+            }
+            Self::Write { path, source } => {
+                write!(f, "failed to write {}: {source}", path.display())?
+            }
+            Self::Exists { path } => write!(f, "{} already exists", path.display())?,
+
+            Self::TooLarge {
+                path,
+                dimensions: (width, height),
+                max_pixels,
+            } => write!(f, "{} is {width}x{height}, which is more than the maximum of {max_pixels} pixels (see --max-pixels)", path.display())?,
+        }
+
+        match self.hint() {
+            Some(hint) => write!(f, " - {hint}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Read { source, .. } | Self::Write { source, .. } => Some(source),
+            Self::Decode { source, .. } => Some(source),
+            Self::Encode { source, .. } => Some(&**source),
+
+            Self::TooLarge { .. } | Self::Exists { .. } => None,
+        }
+    }
+}
+
+/// An error summarizing the failures of some of a number of images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failures {
+    /// What was being done to the images, such as `resize`.
+    pub action: &'static str,
+    /// The exit code of each failure.
+    pub exit_codes: Vec<u8>,
+    /// The total number of images.
+    pub total: usize,
+}
+
+impl Display for Failures {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            action,
+            exit_codes,
+            total,
+        } = self;
+
+        write!(
+            f,
+            "failed to {action} {} of {total} images",
+            exit_codes.len()
+        )
+    }
+}
+
+impl Error for Failures {}
+
+/// Describes the given `error` in handling the image at `path`, naming the path unless the error
+/// already does.
+pub fn describe(path: &Path, error: &(dyn Error + 'static)) -> String {
+    if error.is::<FileError>() {
+        error.to_string()
+    } else {
+        format!("{}: {error}", path.display())
+    }
+}
+
+/// The exit code for the given `error`, determined by the first error in its chain of sources
+/// with a specific exit code.
+pub fn exit_code(error: &(dyn Error + 'static)) -> u8 {
+    let mut source = Some(error);
+
+    while let Some(error) = source {
+        if let Some(code) = specific_exit_code(error) {
+            return code;
+        }
+
+        source = error.source();
+    }
+
+    FAILURE
+}
+
+fn specific_exit_code(error: &(dyn Error + 'static)) -> Option<u8> {
+    if error.is::<InvalidArguments>() {
+        Some(INVALID_ARGUMENTS)
+    } else if let Some(error) = error.downcast_ref::<FileError>() {
+        match error {
+            FileError::Read { .. } | FileError::Write { .. } | FileError::Exists { .. } => Some(IO),
+            FileError::TooLarge { .. } | FileError::Decode { .. } => Some(DECODING),
+            FileError::Encode { .. } => Some(ENCODING),
+        }
+    } else if let Some(Failures { exit_codes, .. }) = error.downcast_ref() {
+        // The failures only share an exit code if they are all the same.
+        match exit_codes[..] {
+            [first, ref rest @ ..] if rest.iter().all(|&code| code == first) => Some(first),
+            _ => Some(FAILURE),
+        }
+    } else if let Some(error) = error.downcast_ref::<ImageError>() {
+        match error {
+            ImageError::Decoding(_) | ImageError::Limits(_) => Some(DECODING),
+            ImageError::Encoding(_) => Some(ENCODING),
+            ImageError::IoError(_) => Some(IO),
+
+            _ => None,
+        }
+    } else if error.is::<png::DecodingError>() {
+        Some(DECODING)
+    } else if error.is::<png::EncodingError>() {
+        Some(ENCODING)
+    } else if error.is::<io::Error>() {
+        Some(IO)
+    } else {
+        None
+    }
+}
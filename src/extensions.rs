@@ -123,7 +123,7 @@ where
 
         // Minimum size is the first iterator's minimum size multiplied by the second iterator's
         // minimum size, with a maximum size of usize::MAX.
-        let min = first_min.checked_mul(second_min).unwrap_or(usize::MAX);
+        let min = first_min.saturating_mul(second_min);
         let max = match (first_max, second_max) {
             // If either iterator has a maximum size of 0 then we cannot mix them, even if the
             // other's maximum size is more than usize::MAX.
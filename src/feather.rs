@@ -0,0 +1,224 @@
+use crate::error::InvalidArguments;
+use crate::scale;
+use image::{DynamicImage, GenericImageView, Rgba32FImage};
+use indicatif::ProgressBar;
+use log::debug;
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+/// The number of horizontal and vertical repetitions of an image with `repeated` dimensions that
+/// fit within the `base` dimensions when adjacent repetitions overlap by `feather` pixels.
+pub fn repetitions(
+    (base_width, base_height): (u32, u32),
+    (repeated_width, repeated_height): (u32, u32),
+    feather: u32,
+) -> (u32, u32) {
+    let fitting = |base: u32, repeated: u32| {
+        base.saturating_sub(feather)
+            .checked_div(repeated.saturating_sub(feather))
+            .unwrap_or(0)
+    };
+
+    (
+        fitting(base_width, repeated_width),
+        fitting(base_height, repeated_height),
+    )
+}
+
+/// Errors if the given `feather` is more than half the width or height of an image with the given
+/// `dimensions`, in which case it cannot be [feathered].
+pub fn check((width, height): (u32, u32), feather: u32) -> Result<(), InvalidArguments> {
+    if feather > width.min(height) / 2 {
+        return Err(InvalidArguments(format!(
+            "--feather {feather} is more than half of the {width}x{height} image"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Repeats the given `image` across a new transparent image with the given dimensions, as
+/// [`crate::repeat`] does, but with adjacent repetitions overlapping by `feather` pixels.
+///
+/// Within each overlap, one repetition fades linearly into the next, which hides the seams
+/// between them for images that are not quite tileable.
+///
+/// The `feather` must have been [checked] against the `image`'s dimensions.
+///
+/// [checked]: check
+pub fn feathered(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    feather: u32,
+    progress: &ProgressBar,
+) -> DynamicImage {
+    let (repeated_width, repeated_height) = image.dimensions();
+    debug_assert!(check(image.dimensions(), feather).is_ok());
+
+    let (horizontal, vertical) = repetitions((width, height), image.dimensions(), feather);
+    let tiles = u64::from(horizontal) * u64::from(vertical);
+    progress.set_length(tiles);
+    debug!("overlaying {horizontal}x{vertical} tiles, feathered by {feather} pixels");
+
+    // Any part of the new image left uncovered is transparent, which needs an alpha channel.
+    let covered = (
+        horizontal * (repeated_width - feather) + feather,
+        vertical * (repeated_height - feather) + feather,
+    ) == (width, height);
+    let image = if covered || image.color().has_alpha() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(crate::with_alpha(image))
+    };
+
+    let repeated = image.to_rgba32f();
+    let mut new_image = Rgba32FImage::new(width, height);
+
+    let columns = Axis::new(repeated_width, horizontal, feather);
+    let rows = Axis::new(repeated_height, vertical, feather);
+
+    new_image
+        .par_chunks_mut(width as usize * 4)
+        .zip(0..height)
+        .for_each(|(row, y)| {
+            for (pixel, x) in row.chunks_mut(4).zip(0..width) {
+                for (v, row_weight) in rows.covering(y) {
+                    for (u, column_weight) in columns.covering(x) {
+                        let weight = row_weight * column_weight;
+
+                        for (sample, repeated_sample) in
+                            pixel.iter_mut().zip(repeated.get_pixel(u, v).0)
+                        {
+                            *sample += repeated_sample * weight;
+                        }
+                    }
+                }
+            }
+        });
+
+    progress.inc(tiles);
+
+    scale::with_color(new_image.into(), image.color())
+}
+
+/// The repetitions of an image along one axis, such as its columns.
+struct Axis {
+    /// The length of the image along this axis.
+    length: u32,
+    /// The number of repetitions.
+    count: u32,
+    /// The number of pixels adjacent repetitions overlap by.
+    feather: u32,
+}
+
+impl Axis {
+    fn new(length: u32, count: u32, feather: u32) -> Self {
+        Self {
+            length,
+            count,
+            feather,
+        }
+    }
+
+    /// The distance between the starts of adjacent repetitions.
+    fn step(&self) -> u32 {
+        self.length - self.feather
+    }
+
+    /// The position within the image and weight of each repetition covering the given
+    /// `position`, whose weights add up to 1.
+    ///
+    /// As `feather` is at most half the `length`, at most two repetitions cover any position.
+    fn covering(&self, position: u32) -> impl Iterator<Item = (u32, f32)> + '_ {
+        let last = (position / self.step()).min(self.count.saturating_sub(1));
+
+        (last.saturating_sub(1)..=last)
+            .filter(move |&index| index < self.count)
+            .filter_map(move |index| {
+                let offset = position.checked_sub(index * self.step())?;
+                (offset < self.length).then(|| (offset, self.weight(index, offset)))
+            })
+    }
+
+    /// The weight of the `index`th repetition at `offset` pixels into it.
+    fn weight(&self, index: u32, offset: u32) -> f32 {
+        let feather = self.feather as f32;
+
+        if index > 0 && offset < self.feather {
+            (offset as f32 + 0.5) / feather
+        } else if index + 1 < self.count && offset >= self.length - self.feather {
+            ((self.length - offset) as f32 - 0.5) / feather
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that the weights of the repetitions covering each position that any repetition
+    /// covers add up to 1.
+    fn assert_weights_sum_to_one(axis: &Axis) {
+        let covered = axis.count * axis.step() + axis.feather;
+
+        for position in 0..covered {
+            let total: f32 = axis.covering(position).map(|(_, weight)| weight).sum();
+
+            assert!((total - 1.0).abs() < 1e-6, "{total} at {position}");
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one() {
+        for feather in 0..=4 {
+            assert_weights_sum_to_one(&Axis::new(8, 3, feather));
+        }
+    }
+
+    #[test]
+    fn half_feathers_overlap_every_pixel() {
+        let axis = Axis::new(8, 3, 4);
+        assert_weights_sum_to_one(&axis);
+
+        // Past the first half of the first repetition, every position is covered by two.
+        for position in 4..12 {
+            assert_eq!(axis.covering(position).count(), 2, "{position}");
+        }
+        assert_eq!(axis.covering(12).collect::<Vec<_>>(), [(8 - 4, 1.0)]);
+    }
+
+    #[test]
+    fn unfeathered_repetitions_do_not_overlap() {
+        let axis = Axis::new(8, 3, 0);
+
+        for position in 0..24 {
+            assert_eq!(
+                axis.covering(position).collect::<Vec<_>>(),
+                [(position % 8, 1.0)]
+            );
+        }
+        assert_eq!(axis.covering(24).count(), 0);
+    }
+
+    #[test]
+    fn repetitions_overlap_by_the_feather() {
+        assert_eq!(repetitions((20, 8), (8, 8), 0), (2, 1));
+        assert_eq!(repetitions((20, 8), (8, 8), 2), (3, 1));
+        assert_eq!(repetitions((20, 8), (8, 8), 4), (4, 1));
+    }
+
+    #[test]
+    fn images_larger_than_the_base_repeat_no_times() {
+        assert_eq!(repetitions((4, 4), (8, 8), 0), (0, 0));
+        assert_eq!(repetitions((4, 4), (8, 8), 2), (0, 0));
+    }
+
+    #[test]
+    fn feathers_over_half_the_image_are_rejected() {
+        assert!(check((8, 6), 3).is_ok());
+        assert!(check((8, 6), 4).is_err());
+        assert!(check((1, 1), 0).is_ok());
+    }
+}
@@ -0,0 +1,660 @@
+use crate::animation::Animation;
+use crate::cli::EncodeArgs;
+use crate::encode;
+use crate::error::FileError;
+#[cfg(feature = "heif")]
+use crate::heif;
+use crate::metadata::Metadata;
+#[cfg(feature = "svg")]
+use crate::svg;
+use image::codecs::gif::GifDecoder;
+use image::codecs::jpeg::JpegDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+#[cfg(any(feature = "heif", feature = "svg"))]
+use image::error::{DecodingError, ImageFormatHint};
+use image::io::{Limits, Reader};
+use image::{ColorType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageResult};
+use log::{debug, info};
+use std::borrow::Cow;
+#[cfg(any(feature = "heif", feature = "svg"))]
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// The offset of the color type in a PNG file's `IHDR` chunk.
+const PNG_COLOR_TYPE_OFFSET: usize = 25;
+/// The PNG color type of indexed images.
+const PNG_INDEXED: u8 = 3;
+
+/// The most bytes used by a decoded pixel, in 32-bit floating point RGBA.
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+/// How many temporary files have been written to so far, which tells apart those of writes to the
+/// same path at once.
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// An encoded image read from a file, along with its metadata.
+#[derive(Debug, Clone)]
+pub struct Input {
+    path: PathBuf,
+    bytes: Vec<u8>,
+    /// The maximum number of pixels the image may have to be decoded.
+    max_pixels: Option<u64>,
+
+    /// The format of the image, determined by the extension of its path.
+    pub format: Option<ImageFormat>,
+    /// The image's metadata.
+    pub metadata: Metadata,
+    /// The EXIF orientation to apply to the image when it is decoded, if any.
+    pub orientation: Option<u16>,
+    /// Whether the image is stored as indexed colors with a palette.
+    pub indexed: bool,
+    /// When the file the image was read from was last modified, if known.
+    pub modified: Option<SystemTime>,
+}
+
+/// The contents of a file read ahead of being wrapped as an [`Input`].
+#[derive(Debug)]
+pub struct Contents {
+    bytes: io::Result<Vec<u8>>,
+    modified: Option<SystemTime>,
+}
+
+impl Contents {
+    /// Reads the file at `path`, keeping any error until it is wrapped.
+    pub fn read(path: &Path) -> Self {
+        info!("reading {}", path.display());
+
+        Self {
+            bytes: fs::read(path),
+            modified: fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        }
+    }
+}
+
+impl Input {
+    /// Reads the encoded image at `path`.
+    ///
+    /// If `auto_orient` is true, the image's EXIF orientation will be applied when it is decoded.
+    pub fn read(path: &Path, auto_orient: bool) -> Result<Self, FileError> {
+        Self::from_contents(path, Contents::read(path), auto_orient)
+    }
+
+    /// Wraps the `contents` of the file at `path`, which were read ahead of time, such as on
+    /// another thread.
+    ///
+    /// If `auto_orient` is true, the image's EXIF orientation will be applied when it is decoded.
+    pub fn from_contents(
+        path: &Path,
+        contents: Contents,
+        auto_orient: bool,
+    ) -> Result<Self, FileError> {
+        let Contents { bytes, modified } = contents;
+        let bytes = bytes.map_err(|source| FileError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        Ok(Self {
+            modified,
+            ..Self::new(path, bytes, ImageFormat::from_path(path).ok(), auto_orient)
+        })
+    }
+
+    /// Wraps an encoded image that was not read from a file, such as an upload, guessing its
+    /// format from its contents. The `name` is used in place of its path.
+    ///
+    /// If `auto_orient` is true, the image's EXIF orientation will be applied when it is decoded.
+    #[cfg(feature = "server")]
+    pub fn from_bytes(name: &Path, bytes: Vec<u8>, auto_orient: bool) -> Self {
+        let format = image::guess_format(&bytes).ok();
+
+        Self::new(name, bytes, format, auto_orient)
+    }
+
+    fn new(path: &Path, bytes: Vec<u8>, format: Option<ImageFormat>, auto_orient: bool) -> Self {
+        let metadata =
+            format.map_or_else(Metadata::default, |format| Metadata::read(&bytes, format));
+        #[cfg(feature = "heif")]
+        let metadata = if heif::is_heif(path) {
+            heif::read_metadata(&bytes)
+        } else {
+            metadata
+        };
+        let orientation = metadata.orientation().filter(|_| auto_orient);
+        let indexed = format == Some(ImageFormat::Png)
+            && bytes.get(PNG_COLOR_TYPE_OFFSET) == Some(&PNG_INDEXED);
+
+        Self {
+            path: path.to_owned(),
+            bytes,
+            max_pixels: None,
+
+            format,
+            metadata,
+            orientation,
+            indexed,
+            modified: None,
+        }
+    }
+
+    /// Errors if the image has more than `max_pixels` pixels according to its header, and
+    /// otherwise limits the decoder's memory use accordingly.
+    pub fn limit_pixels(self, max_pixels: u64) -> Result<Self, FileError> {
+        let (width, height) = self.dimensions()?;
+
+        if u64::from(width) * u64::from(height) > max_pixels {
+            return Err(FileError::TooLarge {
+                path: self.path,
+                dimensions: (width, height),
+                max_pixels,
+            });
+        }
+
+        Ok(Self {
+            max_pixels: Some(max_pixels),
+            ..self
+        })
+    }
+
+    /// The path the image was read from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The encoded image.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Reads the dimensions of the image from its header, accounting for its orientation.
+    pub fn dimensions(&self) -> Result<(u32, u32), FileError> {
+        self.read_dimensions()
+            .map_err(|error| self.decoding_error(error))
+    }
+
+    /// Reads the color type of the image, from its header if possible.
+    pub fn color_type(&self) -> Result<ColorType, FileError> {
+        self.read_color_type()
+            .map_err(|error| self.decoding_error(error))
+    }
+
+    /// Decodes the image, returning it along with its metadata.
+    ///
+    /// If the image has an [orientation] to apply, it is applied, and the orientation in the
+    /// returned metadata is reset.
+    ///
+    /// [orientation]: Self::orientation
+    pub fn decode(self) -> Result<(DynamicImage, Metadata), FileError> {
+        let path = self.path.display();
+
+        debug!("{path}: decoding as {:?}", self.format);
+        let mut image = self
+            .decode_pixels()
+            .map_err(|error| self.decoding_error(error))?;
+        let mut metadata = self.metadata;
+
+        if let Some(orientation) = self.orientation {
+            debug!("{path}: applying EXIF orientation {orientation}");
+            image = orient(image, orientation);
+            metadata.reset_orientation();
+        }
+
+        Ok((image, metadata))
+    }
+
+    /// Decodes the image as an animation if it is animated, returning it along with its metadata.
+    ///
+    /// As with [`decode`], the image's [orientation] is applied to every frame.
+    ///
+    /// [`decode`]: Self::decode
+    /// [orientation]: Self::orientation
+    pub fn decode_animation(&self) -> Result<Option<(Animation, Metadata)>, FileError> {
+        self.read_animation()
+            .map_err(|error| self.decoding_error(error))
+    }
+
+    fn read_dimensions(&self) -> ImageResult<(u32, u32)> {
+        #[cfg(feature = "heif")]
+        if heif::is_heif(&self.path) {
+            return heif::dimensions(&self.bytes).map_err(heif_decoding_error);
+        }
+        #[cfg(feature = "svg")]
+        if self.is_svg() {
+            return svg::dimensions(&self.bytes).map_err(svg_decoding_error);
+        }
+
+        match (self.reader().into_dimensions()?, self.orientation) {
+            // Orientations 5 to 8 are rotated by 90 or 270 degrees.
+            ((width, height), Some(5..=8)) => Ok((height, width)),
+            (dimensions, _) => Ok(dimensions),
+        }
+    }
+
+    fn read_color_type(&self) -> ImageResult<ColorType> {
+        let bytes = &self.bytes[..];
+
+        match self.format {
+            Some(ImageFormat::Png) => Ok(PngDecoder::new(bytes)?.color_type()),
+            Some(ImageFormat::Jpeg) => Ok(JpegDecoder::new(bytes)?.color_type()),
+            Some(ImageFormat::Gif) => Ok(GifDecoder::new(bytes)?.color_type()),
+            Some(ImageFormat::WebP) => Ok(WebPDecoder::new(bytes)?.color_type()),
+
+            _ => Ok(self.decode_pixels()?.color()),
+        }
+    }
+
+    fn read_animation(&self) -> ImageResult<Option<(Animation, Metadata)>> {
+        let Some(format) = self.format else {
+            return Ok(None);
+        };
+        let Some(animation) = Animation::decode(&self.bytes, format, self.limits())? else {
+            return Ok(None);
+        };
+
+        debug!(
+            "{}: decoded {} frames as {format:?}",
+            self.path.display(),
+            animation.frames.len(),
+        );
+        let mut metadata = self.metadata.clone();
+
+        let animation = match self.orientation {
+            Some(orientation) => {
+                metadata.reset_orientation();
+                animation.map_frames(|frame| orient(frame.clone().into(), orientation).into_rgba8())
+            }
+
+            None => animation,
+        };
+
+        Ok(Some((animation, metadata)))
+    }
+
+    /// Whether the image is an SVG image, which can be [rasterized] at any dimensions.
+    ///
+    /// [rasterized]: Self::rasterize
+    #[cfg(feature = "svg")]
+    pub fn is_svg(&self) -> bool {
+        svg::is_svg(&self.path)
+    }
+
+    /// Rasterizes the SVG image at the given `dimensions`, returning it along with its metadata.
+    #[cfg(feature = "svg")]
+    pub fn rasterize(self, dimensions: (u32, u32)) -> Result<(DynamicImage, Metadata), FileError> {
+        debug!("{}: rasterizing at {dimensions:?}", self.path.display());
+        let image = svg::rasterize(&self.bytes, Some(dimensions))
+            .map_err(|error| self.decoding_error(svg_decoding_error(error)))?;
+
+        Ok((image.into(), self.metadata))
+    }
+
+    fn decode_pixels(&self) -> ImageResult<DynamicImage> {
+        #[cfg(feature = "heif")]
+        if heif::is_heif(&self.path) {
+            return heif::decode(&self.bytes)
+                .map(DynamicImage::from)
+                .map_err(heif_decoding_error);
+        }
+        #[cfg(feature = "svg")]
+        if self.is_svg() {
+            return svg::rasterize(&self.bytes, None)
+                .map(DynamicImage::from)
+                .map_err(svg_decoding_error);
+        }
+
+        self.reader().decode()
+    }
+
+    fn decoding_error(&self, source: ImageError) -> FileError {
+        FileError::Decode {
+            path: self.path.clone(),
+            source,
+        }
+    }
+
+    fn reader(&self) -> Reader<Cursor<&[u8]>> {
+        let mut reader = Reader::new(Cursor::new(&self.bytes[..]));
+        if let Some(format) = self.format {
+            reader.set_format(format);
+        }
+        reader.limits(self.limits());
+
+        reader
+    }
+
+    /// The limits on decoding the image, which bound its memory use if it has a maximum number of
+    /// pixels.
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        if let Some(max_pixels) = self.max_pixels {
+            limits.max_alloc = Some(max_pixels.saturating_mul(MAX_BYTES_PER_PIXEL));
+        }
+
+        limits
+    }
+}
+
+/// Encodes the given `image` along with its `metadata` according to the given `encode_args`, and
+/// writes it to `path`.
+///
+/// The format is determined by the extension of `path`. If `indexed` is true, the image is kept
+/// as indexed colors where possible.
+///
+/// PNG outputs that are not reduced to a palette are [encoded straight to the file], rather than
+/// into memory first.
+///
+/// [encoded straight to the file]: encode::write_png
+pub fn write(
+    image: &DynamicImage,
+    metadata: &Metadata,
+    path: &Path,
+    encode_args: &EncodeArgs,
+    indexed: bool,
+) -> Result<(), FileError> {
+    let encoding_error = |source| FileError::Encode {
+        path: path.to_owned(),
+        source,
+    };
+
+    let format = output_format(path)?;
+    debug!("{}: encoding as {format:?}", path.display());
+
+    if format == ImageFormat::Png && encode_args.colors.is_none() && !indexed {
+        let chunks = with_density(metadata, encode_args).png_chunks();
+
+        return write_atomically(path, |temporary_path| {
+            let write_error = |source| FileError::Write {
+                path: path.to_owned(),
+                source,
+            };
+
+            let mut file = BufWriter::new(File::create(temporary_path).map_err(write_error)?);
+            encode::write_png(image, &chunks, &mut file).map_err(|error| match error {
+                png::EncodingError::IoError(source) => write_error(source),
+                error => encoding_error(error.into()),
+            })?;
+
+            file.flush().map_err(write_error)
+        });
+    }
+
+    let encoded = encode::encode(image, format, encode_args, indexed).map_err(encoding_error)?;
+    write_file(
+        path,
+        with_density(metadata, encode_args).write(encoded, format),
+    )
+}
+
+/// Encodes the given `animation` along with its `metadata` according to the given `encode_args`,
+/// and writes it to `path`.
+///
+/// The format is determined by the extension of `path`, and must support animation.
+pub fn write_animation(
+    animation: Animation,
+    metadata: &Metadata,
+    path: &Path,
+    encode_args: &EncodeArgs,
+) -> Result<(), FileError> {
+    let encoding_error = |source| FileError::Encode {
+        path: path.to_owned(),
+        source,
+    };
+
+    let format = output_format(path)?;
+    debug!("{}: encoding as an animated {format:?}", path.display());
+
+    let encoded = animation
+        .encode(format, encode_args)
+        .map_err(encoding_error)?
+        .ok_or_else(|| encoding_error(format!("{format:?} images cannot be animated").into()))?;
+    write_file(
+        path,
+        with_density(metadata, encode_args).write(encoded, format),
+    )
+}
+
+/// The given `metadata` with the pixel density given by `--set-dpi` in the `encode_args`, if any.
+pub fn with_density<'a>(metadata: &'a Metadata, encode_args: &EncodeArgs) -> Cow<'a, Metadata> {
+    match encode_args.set_dpi {
+        Some(dpi) => Cow::Owned(Metadata {
+            dpi: Some(dpi.get()),
+            ..metadata.clone()
+        }),
+
+        None => Cow::Borrowed(metadata),
+    }
+}
+
+/// The format of an output image written to `path`, determined by its extension.
+pub fn output_format(path: &Path) -> Result<ImageFormat, FileError> {
+    ImageFormat::from_path(path).map_err(|error| FileError::Encode {
+        path: path.to_owned(),
+        source: error.into(),
+    })
+}
+
+/// Writes the given encoded image `bytes` to `path`.
+fn write_file(path: &Path, bytes: Vec<u8>) -> Result<(), FileError> {
+    write_atomically(path, |temporary_path| {
+        fs::write(temporary_path, bytes).map_err(|source| FileError::Write {
+            path: path.to_owned(),
+            source,
+        })
+    })
+}
+
+/// Writes a file to `path` with the given `write` function, which is given a temporary path in
+/// the same directory to write to instead. The temporary file is only renamed over `path` once it
+/// has been written, so a failure partway through never leaves an incomplete file at `path` - if
+/// the input is being overwritten, it is kept intact.
+///
+/// If `path` is a symbolic link, the file it points to is replaced instead. The permissions of a
+/// file being replaced are kept, as is its owner where allowed, though other hard links to it
+/// still refer to the old file.
+pub fn write_atomically<E: From<FileError>>(
+    path: &Path,
+    write: impl FnOnce(&Path) -> Result<(), E>,
+) -> Result<(), E> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let temporary_path = temporary_path(&target);
+
+    let result = write(&temporary_path).and_then(|()| {
+        keep_permissions(&target, &temporary_path)
+            .and_then(|()| fs::rename(&temporary_path, &target))
+            .map_err(|source| {
+                FileError::Write {
+                    path: path.to_owned(),
+                    source,
+                }
+                .into()
+            })
+    });
+    if result.is_err() {
+        // The temporary file may not have been created, in which case there is nothing to
+        // remove.
+        let _ = fs::remove_file(&temporary_path);
+    }
+
+    result
+}
+
+/// Gives the file at `temporary_path` the permissions of the file at `target` that it replaces, if
+/// there is one, and on Unix its owner and group too where allowed.
+fn keep_permissions(target: &Path, temporary_path: &Path) -> io::Result<()> {
+    let metadata = match fs::metadata(target) {
+        Ok(metadata) => metadata,
+
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    // Only the superuser can give a file away, so anyone else keeps the owner only if it is
+    // theirs. The owner is changed first, as changing it can clear the setuid and setgid bits.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let owner = (metadata.uid(), metadata.gid());
+        if let Err(error) = std::os::unix::fs::chown(temporary_path, Some(owner.0), Some(owner.1)) {
+            debug!("{}: cannot keep the owner: {error}", target.display());
+        }
+    }
+
+    fs::set_permissions(temporary_path, metadata.permissions())
+}
+
+/// A hidden temporary path that a file at `path` is written to before it is renamed over it.
+///
+/// Each is unique to its process and call, so that writes to the same path at once, such as by
+/// the daemon's connections, never write to the same temporary file.
+fn temporary_path(path: &Path) -> PathBuf {
+    let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".{}.{count}.tmp", process::id()));
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(feature = "heif")]
+fn heif_decoding_error(error: Box<dyn Error + Send + Sync>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("HEIF".to_owned()),
+        error,
+    ))
+}
+
+#[cfg(feature = "svg")]
+fn svg_decoding_error(error: Box<dyn Error + Send + Sync>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("SVG".to_owned()),
+        error,
+    ))
+}
+
+/// Rotates and flips the given `image` according to the given EXIF `orientation` so that it is
+/// the right way up.
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+
+        // 1 is already the right way up, and anything else is invalid.
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestDirectory, UNUSUAL_NAMES};
+
+    #[test]
+    fn temporary_paths_are_unique() {
+        let path = Path::new("out/image.png");
+        let (first, second) = (temporary_path(path), temporary_path(path));
+
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), path.parent());
+        assert!(first
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(".image.png."));
+    }
+
+    #[test]
+    fn concurrent_writes_to_one_path_are_not_mixed() {
+        let directory = TestDirectory::new("concurrent");
+        let path = directory.join("image.png");
+        let contents: Vec<_> = (0..4u8).map(|byte| vec![byte; 1 << 16]).collect();
+        // Every write has its temporary file open before any of them writes to it.
+        let barrier = std::sync::Barrier::new(contents.len());
+
+        std::thread::scope(|scope| {
+            for bytes in &contents {
+                let (path, barrier) = (&path, &barrier);
+
+                scope.spawn(move || {
+                    write_atomically(path, |temporary_path| {
+                        let mut file = File::create(temporary_path).unwrap();
+                        barrier.wait();
+                        file.write_all(bytes).unwrap();
+
+                        Ok::<_, FileError>(())
+                    })
+                    .unwrap();
+                });
+            }
+        });
+
+        // Whichever write finished last, it replaced the file whole.
+        assert!(contents.contains(&fs::read(&path).unwrap()));
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn unusual_file_names_are_written() {
+        let directory = TestDirectory::new("names");
+
+        for (name, _) in UNUSUAL_NAMES {
+            write_file(&directory.join(name), name.as_bytes().to_vec()).unwrap();
+        }
+
+        for (name, _) in UNUSUAL_NAMES {
+            assert_eq!(fs::read(directory.join(name)).unwrap(), name.as_bytes());
+        }
+        // No temporary files are left behind.
+        assert_eq!(
+            fs::read_dir(&*directory).unwrap().count(),
+            UNUSUAL_NAMES.len()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names_are_written() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let directory = TestDirectory::new("non-utf8");
+        let path = directory.join(std::ffi::OsStr::from_bytes(b"\xffimage.png"));
+
+        write_file(&path, b"image".to_vec()).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"image");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replacing_a_file_keeps_its_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = TestDirectory::new("permissions");
+        let path = directory.join("private.png");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_file(&path, b"new".to_vec()).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::cli::{Color, Gradient, Gravity};
+use crate::scale;
+use crate::tiling::Extent;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, Rgba32FImage};
+use indicatif::ProgressBar;
+use log::debug;
+
+/// How many times smaller than the output the background is blurred at, which is much faster than
+/// blurring it at full size and looks no different once it is scaled up.
+const BLUR_SCALE: u32 = 8;
+/// How many times smaller than the longer side of the scaled-down background the blur's standard
+/// deviation is.
+const BLUR_SIGMA_SCALE: f32 = 16.0;
+
+/// Centers the given `image` on a new image with the given dimensions, over a blurred copy of
+/// itself scaled up to cover it, keeping the sample format of the `image`.
+///
+/// The length of the given `progress` bar is set to 1, and it is advanced once the image is
+/// placed.
+pub fn blurred(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    progress: &ProgressBar,
+) -> DynamicImage {
+    progress.set_length(1);
+
+    // Scale the image to cover the small background, cropping whatever overhangs it.
+    let (background_width, background_height) =
+        ((width / BLUR_SCALE).max(1), (height / BLUR_SCALE).max(1));
+    let factor = f64::max(
+        f64::from(background_width) / f64::from(image.width()),
+        f64::from(background_height) / f64::from(image.height()),
+    );
+    let (covering_width, covering_height) = (
+        ((f64::from(image.width()) * factor).ceil() as u32).max(background_width),
+        ((f64::from(image.height()) * factor).ceil() as u32).max(background_height),
+    );
+    debug!("blurring the background at {background_width}x{background_height}");
+
+    let background = scale::scale(
+        image,
+        (covering_width, covering_height),
+        FilterType::Triangle,
+        true,
+    )
+    .crop_imm(
+        (covering_width - background_width) / 2,
+        (covering_height - background_height) / 2,
+        background_width,
+        background_height,
+    );
+
+    let sigma = f32::max(
+        background_width.max(background_height) as f32 / BLUR_SIGMA_SCALE,
+        1.0,
+    );
+    let mut new_image = background
+        .blur(sigma)
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_rgba32f();
+
+    let (x, y) = Gravity::Center.position((width, height), image.dimensions());
+    imageops::overlay(&mut new_image, &image.to_rgba32f(), x, y);
+    progress.inc(1);
+
+    scale::with_color(new_image.into(), image.color())
+}
+
+/// The background that an image is repeated over within the given dimensions: the given linear
+/// `gradient`, with the `gap` color over it within the given extent of the repetitions.
+///
+/// Returns [`None`] if there is neither, as the background is then left transparent.
+pub fn background(
+    (width, height): (u32, u32),
+    gradient: Option<Gradient>,
+    gap: Option<(Extent, Color)>,
+) -> Option<Rgba32FImage> {
+    if gradient.is_none() && gap.is_none() {
+        return None;
+    }
+
+    let mut background = match gradient {
+        Some(gradient) => gradient_image((width, height), gradient),
+        None => Rgba32FImage::new(width, height),
+    };
+
+    if let Some((extent, Color(color))) = gap {
+        let color = Rgba(color.map(|sample| f32::from(sample) / 255.0));
+        imageops::overlay(
+            &mut background,
+            &Rgba32FImage::from_pixel(extent.width, extent.height, color),
+            i64::from(extent.x),
+            i64::from(extent.y),
+        );
+    }
+
+    Some(background)
+}
+
+/// Places the given `image` over the given linear `gradient`, keeping its sample format.
+pub fn over_gradient(image: &DynamicImage, gradient: Gradient) -> DynamicImage {
+    let mut new_image = gradient_image(image.dimensions(), gradient);
+    imageops::overlay(&mut new_image, &image.to_rgba32f(), 0, 0);
+
+    scale::with_color(new_image.into(), image.color())
+}
+
+/// A new image with the given dimensions painted with the given linear `gradient`.
+///
+/// The gradient runs between the corners of the image furthest along its direction, so the whole
+/// of it is visible.
+fn gradient_image((width, height): (u32, u32), gradient: Gradient) -> Rgba32FImage {
+    let Gradient {
+        from: Color(from),
+        to: Color(to),
+        angle,
+    } = gradient;
+
+    let (sin, cos) = f32::from(angle).to_radians().sin_cos();
+    let (width_f, height_f) = (width as f32, height as f32);
+    // Half the length of the gradient, from the center to either end.
+    let half_length = ((width_f * cos).abs() + (height_f * sin).abs()) / 2.0;
+
+    Rgba32FImage::from_fn(width, height, |x, y| {
+        // The distance of the pixel's center along the gradient from its center.
+        let distance =
+            (x as f32 + 0.5 - width_f / 2.0) * cos + (y as f32 + 0.5 - height_f / 2.0) * sin;
+        let t = if half_length > 0.0 {
+            ((distance / half_length + 1.0) / 2.0).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let mut pixel = [0.0; 4];
+        for ((sample, from), to) in pixel.iter_mut().zip(from).zip(to) {
+            *sample = (f32::from(from) + (f32::from(to) - f32::from(from)) * t) / 255.0;
+        }
+
+        Rgba(pixel)
+    })
+}
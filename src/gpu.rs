@@ -0,0 +1,257 @@
+use image::{
+    ColorType, DynamicImage, GenericImageView, GrayAlphaImage, GrayImage, RgbImage, RgbaImage,
+};
+use indicatif::ProgressBar;
+use log::debug;
+use std::error::Error;
+use std::sync::mpsc;
+use wgpu::util::DeviceExt;
+
+/// The fewest pixels an output image must have to be repeated on the GPU - copying smaller images
+/// to and from the GPU takes longer than repeating them on the CPU.
+pub const MIN_PIXELS: u64 = 1 << 24;
+
+/// The number of invocations in each workgroup of the shader, which must match [`SHADER`].
+const WORKGROUP_SIZE: u32 = 256;
+/// The most workgroups that can be dispatched in one dimension.
+const MAX_WORKGROUPS: u32 = 65535;
+
+/// Writes each 4-byte word of the output image, with each byte copied from the repeated image or
+/// left transparent if it is not covered by a repetition.
+///
+/// Rows are treated as bytes rather than pixels so that any 8-bit color type can be repeated.
+const SHADER: &str = r"
+struct Params {
+    // The number of bytes in each row of the repeated image and of the output image.
+    repeated_row_length: u32,
+    row_length: u32,
+    // The number of bytes in each row covered by repetitions, and the number of rows covered.
+    covered_row_length: u32,
+    covered_rows: u32,
+    repeated_height: u32,
+    // The index of the first word written by this dispatch, and the number of words it writes.
+    offset: u32,
+    length: u32,
+    padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> repeated: array<u32>;
+@group(0) @binding(2) var<storage, read_write> output: array<u32>;
+
+fn output_byte(index: u32) -> u32 {
+    let row = index / params.row_length;
+    let column = index % params.row_length;
+    if (row >= params.covered_rows || column >= params.covered_row_length) {
+        return 0u;
+    }
+
+    let source = (row % params.repeated_height) * params.repeated_row_length
+        + column % params.repeated_row_length;
+    return (repeated[source / 4u] >> ((source % 4u) * 8u)) & 0xffu;
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.length) {
+        return;
+    }
+
+    let first = (params.offset + id.x) * 4u;
+    output[id.x] = output_byte(first)
+        | (output_byte(first + 1u) << 8u)
+        | (output_byte(first + 2u) << 16u)
+        | (output_byte(first + 3u) << 24u);
+}
+";
+
+/// Repeats the given `image` across a new transparent image with the given dimensions on the GPU,
+/// as [`crate::repeat`] does.
+///
+/// As the new image starts out transparent, blending each repetition over it is the same as
+/// copying it.
+///
+/// Errors if there is no GPU available, the image is too large for it, or the image does not
+/// have 8-bit samples, in which case it should be repeated on the CPU instead.
+pub fn repeated(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    progress: &ProgressBar,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    let color = image.color();
+    if !matches!(
+        color,
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8
+    ) {
+        return Err(format!("{color:?} images cannot be repeated on the GPU").into());
+    }
+
+    let (horizontal, vertical) = crate::repetitions((width, height), image.dimensions());
+    let channels = u64::from(color.channel_count());
+
+    let repeated_row_length = u64::from(image.width()) * channels;
+    let row_length = u64::from(width) * channels;
+    let length = usize::try_from(row_length * u64::from(height))?;
+    // The shader indexes bytes with 32-bit integers.
+    let too_large = |_| "the image is too large to repeat on the GPU";
+    let params = [
+        u32::try_from(repeated_row_length).map_err(too_large)?,
+        u32::try_from(row_length).map_err(too_large)?,
+        u32::try_from(repeated_row_length * u64::from(horizontal)).map_err(too_large)?,
+        image.height() * vertical,
+        image.height(),
+    ];
+    u32::try_from(length).map_err(too_large)?;
+
+    let (device, queue) = pollster::block_on(device())?;
+    let limits = device.limits();
+    let max_binding_size = limits
+        .max_storage_buffer_binding_size
+        .min(limits.max_buffer_size)
+        .saturating_sub(3);
+
+    // Storage buffers are made of 4-byte words.
+    let mut repeated = image.as_bytes().to_vec();
+    repeated.resize(repeated.len().next_multiple_of(4), 0);
+    if repeated.len() as u64 > max_binding_size {
+        return Err("the image is too large to copy to the GPU".into());
+    }
+
+    debug!("overlaying {horizontal}x{vertical} tiles on the GPU");
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: None,
+        module: &module,
+        entry_point: Some("main"),
+        compilation_options: Default::default(),
+        cache: None,
+    });
+
+    let repeated = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: &repeated,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 32,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // The output is written in chunks that fit within a storage buffer and a single dispatch.
+    let words = length.div_ceil(4) as u64;
+    let chunk_words = (max_binding_size / 4)
+        .min(u64::from(MAX_WORKGROUPS * WORKGROUP_SIZE))
+        .min(words);
+
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: chunk_words * 4,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: chunk_words * 4,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: repeated.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: output.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut new_image = Vec::with_capacity(length);
+    for offset in (0..words).step_by(chunk_words as usize) {
+        let chunk_length = chunk_words.min(words - offset);
+
+        let uniform_data: Vec<u8> = params
+            .into_iter()
+            .chain([offset as u32, chunk_length as u32, 0])
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        queue.write_buffer(&uniforms, 0, &uniform_data);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((chunk_length as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, chunk_length * 4);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging.slice(..chunk_length * 4);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // The receiver is only dropped once the result has been received.
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::PollType::wait_indefinitely())?;
+        receiver.recv()??;
+
+        let chunk = slice.get_mapped_range()?;
+        let remaining = length - new_image.len();
+        new_image.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        drop(chunk);
+        staging.unmap();
+    }
+
+    let tiles = u64::from(horizontal) * u64::from(vertical);
+    progress.set_length(tiles);
+    progress.inc(tiles);
+
+    let new_image = match color {
+        ColorType::L8 => GrayImage::from_raw(width, height, new_image).map(DynamicImage::from),
+        ColorType::La8 => {
+            GrayAlphaImage::from_raw(width, height, new_image).map(DynamicImage::from)
+        }
+        ColorType::Rgb8 => RgbImage::from_raw(width, height, new_image).map(DynamicImage::from),
+        _ => RgbaImage::from_raw(width, height, new_image).map(DynamicImage::from),
+    };
+
+    Ok(new_image.expect("the output is the length of its pixels"))
+}
+
+/// Requests a device from the default adapter.
+async fn device() -> Result<(wgpu::Device, wgpu::Queue), Box<dyn Error>> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })
+        .await?;
+    debug!("using {} for the GPU", adapter.get_info().name);
+
+    let device = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            required_limits: adapter.limits(),
+            ..Default::default()
+        })
+        .await?;
+
+    Ok(device)
+}
@@ -0,0 +1,50 @@
+use crate::cli::{Alignment, Gravity};
+
+impl Alignment {
+    /// The offset of an image of the given `length` placed along an axis of `base_length`.
+    pub fn offset(self, base_length: u32, length: u32) -> i64 {
+        let space = i64::from(base_length) - i64::from(length);
+
+        match self {
+            Self::Start => 0,
+            Self::Center => space / 2,
+            Self::End => space,
+        }
+    }
+}
+
+impl Gravity {
+    /// The horizontal and vertical alignment of this gravity.
+    fn alignment(self) -> (Alignment, Alignment) {
+        use Alignment::*;
+
+        match self {
+            Self::NorthWest => (Start, Start),
+            Self::North => (Center, Start),
+            Self::NorthEast => (End, Start),
+            Self::West => (Start, Center),
+            Self::Center => (Center, Center),
+            Self::East => (End, Center),
+            Self::SouthWest => (Start, End),
+            Self::South => (Center, End),
+            Self::SouthEast => (End, End),
+        }
+    }
+
+    /// The position of the top left corner of an image with the given `dimensions` placed within
+    /// an image with the `base` dimensions according to this gravity.
+    ///
+    /// The position is negative if the image is larger than the base.
+    pub fn position(
+        self,
+        (base_width, base_height): (u32, u32),
+        (width, height): (u32, u32),
+    ) -> (i64, i64) {
+        let (horizontal, vertical) = self.alignment();
+
+        (
+            horizontal.offset(base_width, width),
+            vertical.offset(base_height, height),
+        )
+    }
+}
@@ -0,0 +1,95 @@
+use crate::metadata::Metadata;
+use image::RgbaImage;
+use libheif_rs::{ColorSpace, HeifContext, ImageHandle, ItemId, LibHeif, RgbChroma};
+use std::error::Error;
+use std::path::Path;
+
+/// The content type of HEIF metadata blocks containing XMP.
+const XMP_CONTENT_TYPE: &str = "application/rdf+xml";
+
+/// Whether the image at `path` is a HEIC or HEIF image, determined by its extension.
+pub fn is_heif(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("heic") || extension.eq_ignore_ascii_case("heif")
+        })
+}
+
+/// Reads the dimensions of the primary image in the given encoded HEIF `bytes`.
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+    let context = HeifContext::read_from_bytes(bytes)?;
+    let handle = context.primary_image_handle()?;
+
+    Ok((handle.width(), handle.height()))
+}
+
+/// Decodes the primary image in the given encoded HEIF `bytes`.
+///
+/// The rotation, mirroring, and cropping stored in the file are applied while decoding.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage, Box<dyn Error + Send + Sync>> {
+    let context = HeifContext::read_from_bytes(bytes)?;
+    let handle = context.primary_image_handle()?;
+
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or("the HEIF image was not decoded as RGBA")?;
+
+    // Rows may be padded beyond the width of the image.
+    let row_length = plane.width as usize * 4;
+    let pixels = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|row| &row[..row_length])
+        .copied()
+        .collect();
+
+    RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or_else(|| "the decoded HEIF image is too short".into())
+}
+
+/// Reads the metadata of the primary image in the given encoded HEIF `bytes`.
+///
+/// Malformed metadata is ignored. Since the orientation is applied while [decoding], the
+/// orientation in the returned EXIF data is reset.
+///
+/// [decoding]: decode
+pub fn read_metadata(bytes: &[u8]) -> Metadata {
+    let Ok(context) = HeifContext::read_from_bytes(bytes) else {
+        return Metadata::default();
+    };
+    let Ok(handle) = context.primary_image_handle() else {
+        return Metadata::default();
+    };
+
+    let mut metadata = Metadata {
+        // HEIF EXIF data starts with the offset of its TIFF header.
+        exif: metadata_ids(&handle, b"Exif").into_iter().find_map(|id| {
+            let exif = handle.metadata(id).ok()?;
+            let offset = u32::from_be_bytes(exif.get(..4)?.try_into().ok()?) as usize;
+
+            Some(exif.get(4 + offset..)?.to_vec())
+        }),
+        xmp: metadata_ids(&handle, b"mime")
+            .into_iter()
+            .find(|&id| handle.metadata_content_type(id) == Some(XMP_CONTENT_TYPE))
+            .and_then(|id| handle.metadata(id).ok()),
+        icc: handle.color_profile_raw().map(|profile| profile.data),
+        dpi: None,
+    };
+
+    metadata.reset_orientation();
+    metadata
+}
+
+/// The IDs of the metadata blocks of the given `handle` with the given `item_type`.
+fn metadata_ids(handle: &ImageHandle, item_type: &[u8; 4]) -> Vec<ItemId> {
+    let mut ids = vec![0; handle.number_of_metadata_blocks(item_type).max(0) as usize];
+    let count = handle.metadata_block_ids(&mut ids, item_type);
+    ids.truncate(count);
+
+    ids
+}
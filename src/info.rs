@@ -0,0 +1,146 @@
+use crate::animation;
+use crate::cli::InfoArgs;
+use crate::error::{self, Failures};
+use crate::file;
+use crate::output::Output;
+use crate::report::Dimensions;
+use log::error;
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+
+/// The facts about an image printed by `info`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Info {
+    /// The path of the image.
+    pub path: String,
+    /// The format of the image, determined by the extension of its path.
+    pub format: Option<String>,
+
+    /// The dimensions of the image, before any EXIF orientation is applied.
+    pub dimensions: Dimensions,
+    /// The color type of the image's pixels once decoded.
+    pub color_type: String,
+    /// The number of bits in each sample of the image's pixels once decoded.
+    pub bit_depth: u16,
+    /// Whether the image is stored as indexed colors with a palette.
+    pub indexed: bool,
+    /// The number of frames in the image, which is more than one if it is animated.
+    pub frames: usize,
+
+    /// The image's EXIF orientation, if it has one.
+    pub orientation: Option<u16>,
+    /// Whether the image has EXIF data.
+    pub exif: bool,
+    /// Whether the image has an XMP packet.
+    pub xmp: bool,
+    /// Whether the image has an ICC color profile.
+    pub icc: bool,
+    /// The image's pixel density in dots per inch, if it records one.
+    pub dpi: Option<u32>,
+}
+
+/// Prints the facts about each image given in the `info_args` to `output`.
+///
+/// A failure to read one of the images is reported without stopping the others.
+pub fn run(info_args: &InfoArgs, output: &Output) -> Result<(), Box<dyn Error>> {
+    let mut exit_codes = Vec::new();
+
+    for input_path in &info_args.input_paths {
+        match read(input_path) {
+            Ok(info) => print_info(&info, info_args, output),
+
+            Err(error) => {
+                error!("{}", error::describe(input_path, &*error));
+                exit_codes.push(error::exit_code(&*error));
+            }
+        }
+    }
+
+    if !exit_codes.is_empty() {
+        return Err(Failures {
+            action: "read",
+            exit_codes,
+            total: info_args.input_paths.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Reads the facts about the image at `input_path`, only reading its header where possible.
+fn read(input_path: &Path) -> Result<Info, Box<dyn Error>> {
+    let input = file::Input::read(input_path, false)?;
+    let color_type = input.color_type()?;
+    let metadata = &input.metadata;
+
+    Ok(Info {
+        path: input.path().display().to_string(),
+        format: input.format.map(|format| format!("{format:?}")),
+
+        dimensions: input.dimensions()?.into(),
+        color_type: format!("{color_type:?}"),
+        bit_depth: color_type.bits_per_pixel() / u16::from(color_type.channel_count()),
+        indexed: input.indexed,
+        frames: input
+            .format
+            .map_or(1, |format| animation::frame_count(input.bytes(), format)),
+
+        orientation: metadata.orientation(),
+        exif: metadata.exif.is_some(),
+        xmp: metadata.xmp.is_some(),
+        icc: metadata.icc.is_some(),
+        dpi: metadata.dpi,
+    })
+}
+
+/// Prints the given `info` to `output` as JSON if `--json` is used, or as a summary otherwise.
+fn print_info(info: &Info, info_args: &InfoArgs, output: &Output) {
+    if info_args.json {
+        // Serializing the info cannot fail: it contains no maps and no fallible `Serialize`
+        // implementations.
+        output.println(serde_json::to_string(info).expect("info is serializable"));
+
+        return;
+    }
+
+    let Info {
+        path,
+        format,
+        dimensions: Dimensions { width, height },
+        color_type,
+        bit_depth,
+        indexed,
+        frames,
+        orientation,
+        exif,
+        xmp,
+        icc,
+        dpi,
+    } = info;
+
+    let format = format.as_deref().unwrap_or("unknown format");
+    let indexed = if *indexed { ", indexed" } else { "" };
+    let frames = if *frames == 1 {
+        "1 frame".to_owned()
+    } else {
+        format!("{frames} frames")
+    };
+
+    let metadata: Vec<_> = [(*exif, "EXIF"), (*xmp, "XMP"), (*icc, "ICC profile")]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+        .collect();
+    let metadata = if metadata.is_empty() {
+        "no metadata".to_owned()
+    } else {
+        metadata.join(", ")
+    };
+    let orientation = orientation.map_or_else(String::new, |orientation| {
+        format!(", orientation {orientation}")
+    });
+    let dpi = dpi.map_or_else(String::new, |dpi| format!(", {dpi} dpi"));
+
+    output.println(format_args!("{path}: {width}x{height} {format}, {color_type} ({bit_depth}-bit{indexed}), {frames}, {metadata}{orientation}{dpi}"));
+}
@@ -0,0 +1,244 @@
+use crate::cache::Cache;
+use crate::cli::ResizeArgs;
+use crate::error::{self, Failures, FileError, InvalidArguments};
+use crate::output::Output;
+use crate::{file, pipeline, progress};
+use log::{debug, error, info};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A job file given by `--job`, describing images to process like a build file for assets.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    /// The images to process, each with its own output and operations.
+    jobs: Vec<Job>,
+}
+
+/// An image to process, as described by a job file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    /// The image to process.
+    input: PathBuf,
+    /// Where to write the result - the input if not given.
+    output: Option<PathBuf>,
+    /// The operations to apply to the image in order, each its name followed by its arguments
+    /// separated by whitespace.
+    #[serde(default)]
+    operations: Vec<String>,
+}
+
+/// Processes each image described by the job file at `job_path`, in parallel, encoding and
+/// writing them according to the `resize_args`, or printing what would be written to `output`
+/// with `--dry-run`.
+///
+/// Every job is checked before any image is processed, and existing outputs are only overwritten
+/// once confirmed, as when resizing - unless `batch` is true. A failure to process one of them is
+/// reported without stopping the others.
+pub fn run(
+    job_path: &Path,
+    resize_args: &ResizeArgs,
+    batch: bool,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let job_file = fs::read_to_string(job_path).map_err(|source| FileError::Read {
+        path: job_path.to_owned(),
+        source,
+    })?;
+    let JobFile { jobs } = serde_yaml::from_str(&job_file).map_err(|error| {
+        InvalidArguments(format!("invalid job file {}: {error}", job_path.display()))
+    })?;
+
+    // Relative paths are relative to the job file, as in a build file.
+    let directory = job_path.parent().unwrap_or(Path::new(""));
+
+    let jobs = jobs
+        .into_iter()
+        .map(|job| {
+            let steps = job
+                .operations
+                .iter()
+                .map(|operation| {
+                    let words: Vec<_> = operation.split_whitespace().map(str::to_owned).collect();
+
+                    pipeline::parse(&words)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let input = directory.join(job.input);
+            let output = job
+                .output
+                .map_or_else(|| input.clone(), |output| directory.join(output));
+
+            Ok((input, output, steps))
+        })
+        .collect::<Result<Vec<_>, InvalidArguments>>()?;
+
+    let confirmed = crate::confirm_overwriting(resize_args, batch, || {
+        jobs.iter()
+            .filter(|(input, output, _)| {
+                !(resize_args.only_newer && crate::up_to_date(input, output))
+            })
+            .map(|(_, output, _)| output.clone())
+            .collect()
+    })?;
+    if !confirmed {
+        return Err("no images were overwritten".into());
+    }
+
+    // Images made into several outputs are only decoded once.
+    let cache = Cache::new(jobs.iter().map(|(input, ..)| &**input));
+    let progress = progress::images(
+        jobs.len(),
+        crate::show_progress(resize_args.progress, resize_args.no_progress),
+    );
+
+    let exit_codes: Vec<_> = jobs
+        .par_iter()
+        .filter_map(|(input_path, output_path, steps)| {
+            let result = process(input_path, output_path, steps, resize_args, &cache, output);
+            progress.inc(1);
+
+            match result {
+                Ok(()) => None,
+
+                Err(error) => {
+                    progress.suspend(|| error!("{}", error::describe(input_path, &*error)));
+                    Some(error::exit_code(&*error))
+                }
+            }
+        })
+        .collect();
+
+    progress.finish_and_clear();
+
+    if !exit_codes.is_empty() {
+        return Err(Failures {
+            action: "process",
+            exit_codes,
+            total: jobs.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Applies the given `steps` to the image at `input_path`, decoded through the given `cache`,
+/// writing the result to `output_path` after creating its directory if needed.
+///
+/// With `--dry-run`, the input is only read, and what would be written is printed to `output`.
+fn process(
+    input_path: &Path,
+    output_path: &Path,
+    steps: &[pipeline::Step],
+    resize_args: &ResizeArgs,
+    cache: &Cache,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    // The use is finished when it decodes the image, or when the image is skipped.
+    let cache_use = cache.start_use(input_path);
+
+    if resize_args.only_newer && crate::up_to_date(input_path, output_path) {
+        info!(
+            "skipping {}, as {} is up to date",
+            input_path.display(),
+            output_path.display()
+        );
+
+        return Ok(());
+    }
+
+    if resize_args.no_clobber && !resize_args.dry_run && output_path.exists() {
+        return Err(FileError::Exists {
+            path: output_path.to_owned(),
+        }
+        .into());
+    }
+
+    let input = file::Input::read(input_path, !resize_args.no_auto_orient)?
+        .limit_pixels(resize_args.limit_args.max_pixels)?;
+
+    if resize_args.dry_run {
+        output.println(format_args!(
+            "{} -> {}",
+            input_path.display(),
+            output_path.display()
+        ));
+
+        return Ok(());
+    }
+
+    // The input's attributes are read before anything is written, as the output may replace it.
+    let attributes = crate::preserved_attributes(input_path, resize_args)?;
+    let (mut image, mut metadata) = cache_use.decode(input)?;
+    crate::convert_to_srgb(&mut image, &mut metadata, input_path, resize_args)?;
+
+    for step in steps {
+        debug!("{}: applying {step:?}", input_path.display());
+        image = Arc::new(pipeline::apply(&image, *step, &resize_args.limit_args)?);
+    }
+
+    // Outputs are often collected into their own directories, as in a build.
+    if let Some(directory) = output_path.parent() {
+        fs::create_dir_all(directory).map_err(|source| FileError::Write {
+            path: directory.to_owned(),
+            source,
+        })?;
+    }
+
+    info!("writing {}", output_path.display());
+    crate::back_up(output_path, resize_args)?;
+    file::write(
+        &image,
+        &crate::output_metadata(metadata, resize_args),
+        output_path,
+        &resize_args.encode_args,
+        false,
+    )?;
+    crate::preserve(attributes.as_ref(), output_path, resize_args)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Args;
+    use crate::testing::TestDirectory;
+    use clap::Parser;
+    use image::RgbaImage;
+
+    #[test]
+    fn dry_runs_write_nothing() {
+        let directory = TestDirectory::new("job-dry-run");
+        let input_path = directory.join("a.png");
+        RgbaImage::new(4, 4).save(&input_path).unwrap();
+        let input = fs::read(&input_path).unwrap();
+
+        let job_path = directory.join("job.yaml");
+        fs::write(
+            &job_path,
+            "jobs:\n  - input: a.png\n    operations: [scale 2x]\n  - input: a.png\n    output: out/b.png\n",
+        )
+        .unwrap();
+        let job_arg = job_path.to_string_lossy();
+        let args = Args::parse_from(["img-resize", "--job", &job_arg, "--dry-run"]);
+        let resize_args = args.resize_args.unwrap();
+
+        let output = Output::captured();
+        run(&job_path, &resize_args, true, &output).unwrap();
+
+        assert_eq!(fs::read(&input_path).unwrap(), input);
+        assert!(!directory.join("out").exists());
+        assert_eq!(
+            output.into_captured().lines().count(),
+            2,
+            "each output is printed"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// A logger which prints every message from this crate to stderr, prefixed with its level.
+///
+/// Messages from dependencies are left out, as they are meaningless to most users.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let target = metadata.target();
+        let from_crate = target.split("::").next() == Some(env!("CARGO_CRATE_NAME"));
+
+        from_crate && metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level().as_str().to_lowercase();
+
+            eprintln!("{level}: {}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, only printing messages at or above the level determined by the number of
+/// `verbose` and `quiet` flags given.
+///
+/// By default, warnings and errors are printed. Each `verbose` flag enables the next more verbose
+/// level, and each `quiet` flag disables the least verbose level still enabled.
+pub fn init(verbose: u8, quiet: u8) {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    // The index of `LevelFilter::Warn` in `LEVELS`.
+    const DEFAULT: usize = 2;
+
+    let index = (DEFAULT + usize::from(verbose))
+        .saturating_sub(usize::from(quiet))
+        .min(LEVELS.len() - 1);
+
+    // The logger can only fail to be set if it has already been set.
+    if log::set_logger(&Logger).is_ok() {
+        log::set_max_level(LEVELS[index]);
+    }
+}
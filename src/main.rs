@@ -1,87 +1,2001 @@
 #![recursion_limit = "256"]
-#![feature(if_let_guard)]
 
+mod animation;
+mod bench;
+mod blur;
+mod border;
+mod cache;
 mod cli;
+mod color;
+mod compare;
+mod composite;
+mod concat;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod diff;
+mod encode;
+mod error;
 mod extensions;
+mod feather;
+mod file;
+mod fill;
+mod font;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod gravity;
+#[cfg(feature = "heif")]
+mod heif;
+mod info;
+mod job;
+mod logger;
+mod manifest;
+mod mask;
+mod metadata;
+mod montage;
+mod output;
+mod pipeline;
+#[cfg(feature = "plugins")]
+mod plugin;
+mod preset;
+mod progress;
+mod quantize;
+mod report;
+mod scale;
+#[cfg(feature = "scripting")]
+mod script;
+mod seamless;
+#[cfg(feature = "server")]
+mod server;
+mod shadow;
+mod split;
+mod spritesheet;
+mod stream;
+#[cfg(feature = "svg")]
+mod svg;
+mod template;
+#[cfg(test)]
+mod testing;
+mod thumbnail;
+mod tiling;
+#[cfg(feature = "self-update")]
+mod update;
+mod version;
+mod walk;
+mod watch;
 
 pub use extensions::IteratorExtensions;
 
-use clap::{CommandFactory, Parser};
+use animation::Animation;
+use cache::Cache;
+use clap::{CommandFactory, FromArgMatches, ValueEnum};
 use clap_complete as completion;
-use cli::Size;
-use image::{imageops, GenericImage, GenericImageView, RgbaImage};
+use cli::{Ratio, Shell, Size};
+use error::{Failures, FileError, InvalidArguments};
+use image::error::{DecodingError, ImageFormatHint};
+use image::{
+    imageops, ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageError, ImageFormat,
+    Pixel, Primitive, Rgba32FImage,
+};
+use indicatif::ProgressBar;
+use log::{debug, error, info, warn};
+use manifest::{Fingerprint, Manifest};
+use metadata::Metadata;
+use output::Output;
+use rayon::prelude::*;
+use report::{Dimensions, Report, Status};
+use std::borrow::Cow;
 use std::error::Error;
-use std::io;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, FileTimes};
+use std::io::{self, IsTerminal};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::time::Instant;
+use std::{env, thread};
+use template::Placeholders;
+use tiling::Layout;
 
-fn main() -> Result<(), Box<dyn Error>> {
+/// How many inputs are read, and how many decoded, ahead for each thread resizing them.
+const READ_AHEAD: usize = 2;
+
+fn main() -> ExitCode {
+    let result = run();
+    // The logger is set by `run`, unless the arguments could not be parsed.
+    logger::init(0, 0);
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+
+        Err(error) => {
+            error!("{error}");
+            ExitCode::from(error::exit_code(&*error))
+        }
+    }
+}
+
+/// Runs the subcommand given by the command line arguments, or resizes the given images if there
+/// is none.
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+
+    logger::init(args.verbose, args.quiet);
+    if args.batch {
+        progress::hide();
+    }
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.get())
+            .build_global()?;
+    }
+
+    execute(args, &Output::Stdout)
+}
+
+/// Runs the subcommand given by the `args`, or resizes the given images if there is none,
+/// printing any results to `output`.
+fn execute(args: cli::Args, output: &Output) -> Result<(), Box<dyn Error>> {
     let cli::Args {
         subcommand,
         resize_args,
-    } = cli::Args::parse();
 
-    if let Some(cli::Subcommands::Generate { shell }) = subcommand {
+        verbose,
+        batch,
+        ..
+    } = args;
+
+    // A subcommand can only be matched on in the chain below if it always exists.
+    #[cfg(feature = "plugins")]
+    if let Some(cli::Subcommands::Apply(apply_args)) = &subcommand {
+        return plugin::run(apply_args);
+    }
+    #[cfg(feature = "self-update")]
+    if let Some(cli::Subcommands::SelfUpdate(self_update_args)) = &subcommand {
+        return update::run(self_update_args, batch, output);
+    }
+    #[cfg(feature = "server")]
+    if let Some(cli::Subcommands::Serve(serve_args)) = &subcommand {
+        return server::run(serve_args);
+    }
+    #[cfg(unix)]
+    if let Some(cli::Subcommands::Daemon(daemon_args)) = &subcommand {
+        return daemon::run(daemon_args);
+    }
+
+    if let Some(cli::Subcommands::Generate {
+        shell,
+        all,
+        output: path,
+    }) = subcommand
+    {
         // Generate completions for the given `shell` if it is used.
 
-        completion::generate(
-            shell,
-            &mut cli::Args::command(),
-            cli::NAME,
-            &mut io::stdout(),
+        generate_completions(shell, all, path.as_deref(), output)?;
+    } else if let Some(cli::Subcommands::GenerateMan) = subcommand {
+        output.write(|writer| clap_mangen::Man::new(cli::Args::command()).render(writer))?;
+    } else if let Some(cli::Subcommands::Version) = subcommand {
+        version::print(verbose > 0, output);
+    } else if let Some(cli::Subcommands::Info(info_args)) = subcommand {
+        info::run(&info_args, output)?;
+    } else if let Some(cli::Subcommands::Scale(scale_args)) = subcommand {
+        scale::run(&scale_args)?;
+    } else if let Some(cli::Subcommands::Seamless(seamless_args)) = subcommand {
+        seamless::run(&seamless_args)?;
+    } else if let Some(cli::Subcommands::Composite(composite_args)) = subcommand {
+        composite::run(&composite_args)?;
+    } else if let Some(cli::Subcommands::Blur(blur_args)) = subcommand {
+        blur::run(&blur_args)?;
+    } else if let Some(cli::Subcommands::Border(border_args)) = subcommand {
+        border::run(&border_args)?;
+    } else if let Some(cli::Subcommands::Compare(compare_args)) = subcommand {
+        compare::run(&compare_args, output)?;
+    } else if let Some(cli::Subcommands::Diff(diff_args)) = subcommand {
+        diff::run(&diff_args)?;
+    } else if let Some(cli::Subcommands::Thumbnail(thumbnail_args)) = subcommand {
+        thumbnail::run(&thumbnail_args)?;
+    } else if let Some(cli::Subcommands::Montage(montage_args)) = subcommand {
+        montage::run(&montage_args)?;
+    } else if let Some(cli::Subcommands::Split(split_args)) = subcommand {
+        split::run(&split_args)?;
+    } else if let Some(cli::Subcommands::Spritesheet(spritesheet_args)) = subcommand {
+        spritesheet::run(&spritesheet_args)?;
+    } else if let Some(cli::Subcommands::Concat(concat_args)) = subcommand {
+        concat::run(&concat_args)?;
+    } else if let Some(cli::Subcommands::Run(run_args)) = subcommand {
+        pipeline::run(&run_args)?;
+    } else if let Some(cli::Subcommands::Preset { action }) = subcommand {
+        preset::run(&action, output)?;
+    } else if let Some(cli::Subcommands::Bench(bench_args)) = subcommand {
+        bench::run(&bench_args, output)?;
+    } else if let Some(mut resize_args) = resize_args {
+        // If no subcommand is used, then do the image resize instead.
+
+        if let Some(random) = &mut resize_args.tile_random {
+            let seed = *random.seed.get_or_insert_with(tiling::random_seed);
+            info!("varying tiles with --tile-random seed={seed}");
+        }
+
+        if let Some(job_path) = &resize_args.job {
+            return job::run(job_path, &resize_args, batch, output);
+        }
+
+        if let Some(list) = &resize_args.files_from {
+            let listed = walk::list(list, resize_args.null)?;
+            resize_args.input_paths.extend(listed);
+        }
+
+        let inputs = walk::inputs(
+            &resize_args.input_paths,
+            resize_args.recursive,
+            &resize_args.extensions,
+        )?;
+
+        if inputs.is_empty() {
+            return Err(
+                InvalidArguments("no images were found in the input directories".into()).into(),
+            );
+        }
+
+        if inputs.len() != 1 && resize_args.output_path.is_some() {
+            return Err(InvalidArguments(
+                "--output-path can only be used with a single input".into(),
+            )
+            .into());
+        }
+
+        if resize_args.stream && resize_args.fill != cli::Fill::Tile {
+            return Err(
+                InvalidArguments("--stream can only be used with --fill tile".into()).into(),
+            );
+        }
+
+        if let Some(format) = &resize_args.format {
+            if ImageFormat::from_extension(format).is_none() {
+                return Err(InvalidArguments(format!("unknown image format: {format}")).into());
+            }
+        }
+
+        if let Some(template) = &resize_args.output_template {
+            // Check the template is valid before resizing anything.
+            template::render(template, &Placeholders::new(Path::new(""), (0, 0), 0))?;
+        }
+
+        if !confirm_overwrite(&inputs, &resize_args, batch)? {
+            return Err("no images were overwritten".into());
+        }
+
+        if resize_args.watch {
+            let input = &inputs[0];
+            let input_path = &input.path;
+
+            // Writing the output would change the input again, triggering another resize.
+            if resize_args.output_path.as_ref() == Some(input_path)
+                || resize_args.output_path.is_none()
+            {
+                return Err(InvalidArguments(
+                    "--watch requires an --output-path different to the input".into(),
+                )
+                .into());
+            }
+
+            watch::watch(input_path, || {
+                resize_one(input, 0, &resize_args, &Cache::default(), None, output)
+            })?;
+        } else {
+            resize_all(&inputs, &resize_args, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Generates completions for the given `shell`, or for every shell if `all` is true, writing them
+/// to `output` if it is given, or printing them to `printed` otherwise.
+///
+/// If `output` is a directory, each shell's completions are written into it under the file name
+/// that shell expects.
+fn generate_completions(
+    shell: Option<Shell>,
+    all: bool,
+    output: Option<&Path>,
+    printed: &Output,
+) -> Result<(), FileError> {
+    let mut command = cli::Args::command();
+    // Without a `shell`, `--all` must have been given.
+    let shells = shell.map_or_else(|| Shell::value_variants().to_vec(), |shell| vec![shell]);
+
+    let Some(output) = output else {
+        printed.write(|writer| {
+            for shell in shells {
+                completion::generate(shell, &mut command, cli::NAME, writer);
+            }
+        });
+
+        return Ok(());
+    };
+    let write_error = |source| FileError::Write {
+        path: output.to_owned(),
+        source,
+    };
+
+    if all {
+        fs::create_dir_all(output).map_err(write_error)?;
+    }
+    if output.is_dir() {
+        for shell in shells {
+            let path = completion::generate_to(shell, &mut command, cli::NAME, output)
+                .map_err(write_error)?;
+            info!("wrote completions to {}", path.display());
+        }
+    } else {
+        let mut file = File::create(output).map_err(write_error)?;
+        for shell in shells {
+            completion::generate(shell, &mut command, cli::NAME, &mut file);
+        }
+        info!("wrote completions to {}", output.display());
+    }
+
+    Ok(())
+}
+
+/// Parses the command line arguments, using the options in the configuration file and the given
+/// `--preset` as defaults.
+///
+/// Exits with the usage if they are invalid, or prints the help or version if asked for.
+fn parse_args() -> Result<cli::Args, Box<dyn Error>> {
+    try_parse_args(env::args_os().collect()).or_else(|error| {
+        match error.downcast::<clap::Error>() {
+            Ok(error) => error.exit(),
+            Err(error) => Err(error),
+        }
+    })
+}
+
+/// Parses the given command line `args`, starting with the program name, as for [`parse_args`],
+/// returning a [`clap::Error`] rather than exiting if they are invalid or ask for the help or
+/// version.
+fn try_parse_args(args: Vec<OsString>) -> Result<cli::Args, Box<dyn Error>> {
+    let command = || config::with_env(cli::Args::command());
+
+    // The preset is found first, as the rest of the arguments depend on it.
+    let preset = config::apply(command(), None)?
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+        .ok()
+        .and_then(|mut matches| matches.remove_one::<String>("preset"));
+
+    let matches = config::apply(command(), preset.as_deref())?.try_get_matches_from(args)?;
+
+    Ok(cli::Args::from_arg_matches(&matches)?)
+}
+
+/// Asks whether to overwrite any existing files that the outputs for the given `inputs` would be
+/// written to if stdin is a terminal, listing them, and returns whether to go ahead.
+///
+/// There is no need to ask with `--yes`, `--dry-run`, `--backup`, or `--no-clobber`, and nothing
+/// is ever asked with `--batch`.
+fn confirm_overwrite(
+    inputs: &[walk::Entry],
+    resize_args: &cli::ResizeArgs,
+    batch: bool,
+) -> Result<bool, Box<dyn Error>> {
+    confirm_overwriting(resize_args, batch, || {
+        (0..)
+            .zip(inputs)
+            .filter_map(|(index, entry)| {
+                // The header is only read for an `--output-template`, which may need the
+                // dimensions. Inputs that cannot be read are left to fail when they are resized
+                // instead.
+                new_path(entry, index, resize_args, || {
+                    let input = file::Input::read(&entry.path, !resize_args.no_auto_orient)?;
+                    Ok(input.dimensions()?)
+                })
+                .ok()
+                .filter(|new_path| !(resize_args.only_newer && up_to_date(&entry.path, new_path)))
+            })
+            .collect()
+    })
+}
+
+/// Asks whether to overwrite whichever of the files at the paths given by `new_paths` exist if
+/// stdin is a terminal, listing them, and returns whether to go ahead.
+///
+/// As with [`confirm_overwrite`], the paths are only found if there is a need to ask.
+fn confirm_overwriting(
+    resize_args: &cli::ResizeArgs,
+    batch: bool,
+    new_paths: impl FnOnce() -> Vec<PathBuf>,
+) -> Result<bool, Box<dyn Error>> {
+    if resize_args.yes
+        || resize_args.dry_run
+        || resize_args.backup.is_some()
+        || resize_args.no_clobber
+        || batch
+        || !io::stdin().is_terminal()
+    {
+        return Ok(true);
+    }
+
+    let existing: Vec<_> = new_paths()
+        .into_iter()
+        .filter(|new_path| new_path.exists())
+        .collect();
+
+    match &*existing {
+        [] => return Ok(true),
+        [new_path] => eprint!("overwrite {}? [y/N] ", new_path.display()),
+
+        existing => {
+            eprintln!("{} files already exist:", existing.len());
+            for new_path in existing {
+                eprintln!("  {}", new_path.display());
+            }
+            eprint!("overwrite them? [y/N] ");
+        }
+    }
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Resizes each of the given `inputs` according to the given `resize_args`, printing any reports
+/// to `output`.
+///
+/// When there are multiple inputs, they are resized concurrently. A failure to resize one of them
+/// is reported without stopping the others.
+fn resize_all(
+    inputs: &[walk::Entry],
+    resize_args: &cli::ResizeArgs,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let manifest = resize_args
+        .manifest
+        .as_deref()
+        .map(Manifest::read)
+        .transpose()?;
+
+    let result = resize_each(inputs, resize_args, manifest.as_ref(), output);
+
+    // The outputs that were made are recorded even if others failed.
+    if let Some(manifest) = &manifest {
+        manifest.write()?;
+    }
+
+    result
+}
+
+/// Resizes each of the given `inputs` in parallel, recording the outputs in the `manifest` if
+/// one is given and printing any reports to `output`.
+///
+/// Each image is read and decoded ahead of being extended and encoded, on threads of their own.
+///
+/// A failure to resize one of the images is reported without stopping the others.
+fn resize_each(
+    inputs: &[walk::Entry],
+    resize_args: &cli::ResizeArgs,
+    manifest: Option<&Manifest>,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    // Inputs given more than once are only decoded once.
+    let cache = Cache::new(inputs.iter().map(|input| &*input.path));
+
+    if let [input] = inputs {
+        return resize_one(input, 0, resize_args, &cache, manifest, output);
+    }
+
+    let progress = progress::images(
+        inputs.len(),
+        show_progress(resize_args.progress, resize_args.no_progress),
+    );
+
+    // Reports the outcome of resizing each input, returning the exit code of any failure.
+    let conclude = |input: &walk::Entry, report: Report, result: Result<(), Box<dyn Error>>| {
+        progress.inc(1);
+        progress.suspend(|| print_report(&report, resize_args, output));
+
+        match result {
+            Ok(()) => None,
+
+            Err(error) => {
+                progress.suspend(|| error!("{}", error::describe(&input.path, &*error)));
+                Some(error::exit_code(&*error))
+            }
+        }
+    };
+
+    // The next inputs are read from disk on one thread, and decoded on as many others as there
+    // are threads resizing them, so that decoding overlaps with extending and encoding those
+    // already decoded. Enough are kept ahead at each stage to keep every resizing thread busy.
+    let ahead = rayon::current_num_threads() * READ_AHEAD;
+    let (read_sender, read_receiver) = mpsc::sync_channel(ahead);
+    let read_receiver = Mutex::new(read_receiver);
+
+    let exit_codes: Vec<_> = thread::scope(|scope| {
+        scope.spawn(move || {
+            for (index, input) in inputs.iter().enumerate() {
+                let contents = file::Contents::read(&input.path);
+
+                // Every input is only left unread if the resizing has stopped.
+                if read_sender.send((index, input, contents)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (decoded_sender, decoded_receiver) = mpsc::sync_channel(ahead);
+        let decoders: Vec<_> = (0..rayon::current_num_threads())
+            .map(|_| {
+                let (read_receiver, decoded_sender) = (&read_receiver, decoded_sender.clone());
+                let (cache, conclude) = (&cache, &conclude);
+
+                scope.spawn(move || {
+                    let mut exit_codes = Vec::new();
+
+                    loop {
+                        let next = read_receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        let Ok((index, input, contents)) = next else {
+                            break;
+                        };
+
+                        let start = Instant::now();
+                        let read = file::Input::from_contents(
+                            &input.path,
+                            contents,
+                            !resize_args.no_auto_orient,
+                        );
+
+                        // Inputs left with nothing to resize are reported straight away.
+                        let (report, result) =
+                            match decode(input, index, read, resize_args, cache, manifest) {
+                                Ok(Prepared::Decoded(decoded)) => {
+                                    let elapsed = start.elapsed();
+                                    if decoded_sender.send((input, decoded, elapsed)).is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                Ok(Prepared::Finished(report)) => (report, Ok(())),
+                                Err(error) => (Report::failed(&input.path, &*error), Err(error)),
+                            };
+
+                        exit_codes.extend(conclude(
+                            input,
+                            report.with_elapsed(start.elapsed()),
+                            result,
+                        ));
+                    }
+
+                    exit_codes
+                })
+            })
+            .collect();
+        drop(decoded_sender);
+
+        let mut exit_codes: Vec<_> = decoded_receiver
+            .into_iter()
+            .par_bridge()
+            .filter_map(|(input, decoded, elapsed)| {
+                let start = Instant::now();
+                let (report, result) = match finish(&input.path, *decoded, &ProgressBar::hidden()) {
+                    Ok(report) => (report, Ok(())),
+                    Err(error) => (Report::failed(&input.path, &*error), Err(error)),
+                };
+
+                conclude(
+                    input,
+                    report.with_elapsed(elapsed + start.elapsed()),
+                    result,
+                )
+            })
+            .collect();
+
+        for decoder in decoders {
+            exit_codes.extend(decoder.join().expect("decoding does not panic"));
+        }
+
+        exit_codes
+    });
+
+    progress.finish_and_clear();
+
+    if !exit_codes.is_empty() {
+        return Err(Failures {
+            action: "resize",
+            exit_codes,
+            total: inputs.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Resizes a single image, the `index`th input, showing the progress of tiling it if enabled and
+/// printing its report to `output`.
+fn resize_one(
+    input: &walk::Entry,
+    index: usize,
+    resize_args: &cli::ResizeArgs,
+    cache: &Cache,
+    manifest: Option<&Manifest>,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let read = file::Input::read(&input.path, !resize_args.no_auto_orient);
+
+    let progress = progress::tiles(show_progress(resize_args.progress, resize_args.no_progress));
+    let (report, result) =
+        resize_timed(input, index, read, resize_args, cache, manifest, &progress);
+    progress.finish_and_clear();
+
+    print_report(&report, resize_args, output);
+
+    result
+}
+
+/// Whether the output at `new_path` exists and was modified no earlier than the input at
+/// `input_path`, so that `--only-newer` skips it.
+///
+/// An input resized in place is never up to date.
+fn up_to_date(input_path: &Path, new_path: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+
+    match (modified(input_path), modified(new_path)) {
+        (Ok(input), Ok(output)) => input_path != new_path && output >= input,
+
+        _ => false,
+    }
+}
+
+/// Whether progress should be shown, given whether `--progress` or `--no-progress` was used.
+fn show_progress(progress: bool, no_progress: bool) -> bool {
+    if progress {
+        true
+    } else if no_progress {
+        false
+    } else {
+        io::stdout().is_terminal()
+    }
+}
+
+/// Prints the given `report` to `output` as JSON if `--json` is used, or a summary if `--dry-run`
+/// is used.
+fn print_report(report: &Report, resize_args: &cli::ResizeArgs, output: &Output) {
+    if resize_args.json {
+        // Serializing the report cannot fail: it contains no maps and no fallible `Serialize`
+        // implementations.
+        output.println(serde_json::to_string(report).expect("reports are serializable"));
+    } else if let Report {
+        input,
+        output: Some(new_path),
+        new_dimensions: Some(Dimensions { width, height }),
+        tiles: Some(tiles),
+        status: Status::Skipped,
+        ..
+    } = report
+    {
+        let (horizontal, vertical) = (tiles.width, tiles.height);
+
+        output.println(format_args!(
+            "{input} -> {new_path}: {width}x{height} ({horizontal}x{vertical} tiles)"
+        ));
+    }
+}
+
+/// [Resizes] the given `input` image, once `read`, returning a [`Report`] of the outcome.
+///
+/// [Resizes]: resize
+fn resize_timed(
+    input: &walk::Entry,
+    index: usize,
+    read: Result<file::Input, FileError>,
+    resize_args: &cli::ResizeArgs,
+    cache: &Cache,
+    manifest: Option<&Manifest>,
+    progress: &ProgressBar,
+) -> (Report, Result<(), Box<dyn Error>>) {
+    let start = Instant::now();
+
+    let (report, result) = match resize(input, index, read, resize_args, cache, manifest, progress)
+    {
+        Ok(report) => (report, Ok(())),
+        Err(error) => (Report::failed(&input.path, &*error), Err(error)),
+    };
+
+    (report.with_elapsed(start.elapsed()), result)
+}
+
+/// Resizes the image of the given `entry`, the `index`th input, once `read`, according to the
+/// given `resize_args`, decoding it through the given `cache` and recording its output in the
+/// `manifest` if one is given.
+///
+/// The given `progress` bar is advanced for each tile overlaid.
+fn resize(
+    entry: &walk::Entry,
+    index: usize,
+    read: Result<file::Input, FileError>,
+    resize_args: &cli::ResizeArgs,
+    cache: &Cache,
+    manifest: Option<&Manifest>,
+    progress: &ProgressBar,
+) -> Result<Report, Box<dyn Error>> {
+    match decode(entry, index, read, resize_args, cache, manifest)? {
+        Prepared::Finished(report) => Ok(report),
+        Prepared::Decoded(decoded) => finish(&entry.path, *decoded, progress),
+    }
+}
+
+/// What is left of resizing an input once it has been [decoded].
+///
+/// [decoded]: decode
+enum Prepared<'a> {
+    /// Nothing, as the input was skipped or only previewed with `--dry-run`.
+    Finished(Report),
+    /// [Finishing] the decoded input.
+    ///
+    /// [Finishing]: finish
+    Decoded(Box<Decoded<'a>>),
+}
+
+/// An input decoded ahead of being resized, along with what is needed to finish resizing it.
+struct Decoded<'a> {
+    /// The arguments to resize it with, including any overridden by its `--script`.
+    resize_args: Cow<'a, cli::ResizeArgs>,
+    /// The path to write the output to.
+    new_path: PathBuf,
+    /// The manifest to record the output in, along with its fingerprint.
+    fingerprint: Option<(&'a Manifest, Fingerprint)>,
+    /// The attributes of the input to copy onto its output with `--preserve`.
+    attributes: Option<fs::Metadata>,
+    /// The decoded image.
+    image: DecodedImage,
+}
+
+/// An image decoded ahead of being resized.
+enum DecodedImage {
+    /// An animation, each frame of which is resized.
+    Animation(Animation, Metadata),
+    /// An image which is only decoded a row at a time as it is resized, with `--stream`.
+    Streamed(file::Input),
+    /// A still image.
+    Still {
+        image: Arc<DynamicImage>,
+        metadata: Metadata,
+        /// Whether the image was stored as indexed colors.
+        indexed: bool,
+    },
+}
+
+/// Decodes the image of the given `entry`, the `index`th input, once `read`, through the given
+/// `cache`, returning what is left of resizing it according to the given `resize_args`.
+///
+/// Inputs that are skipped, such as by `--only-newer` or because their output in the `manifest` is
+/// up to date, are never decoded.
+fn decode<'a>(
+    entry: &walk::Entry,
+    index: usize,
+    read: Result<file::Input, FileError>,
+    resize_args: &'a cli::ResizeArgs,
+    cache: &Cache,
+    manifest: Option<&'a Manifest>,
+) -> Result<Prepared<'a>, Box<dyn Error>> {
+    let input_path = &*entry.path;
+    let cli::ResizeArgs {
+        output_dir,
+        dry_run,
+        limit_args,
+        ..
+    } = resize_args;
+    // The use is finished when it decodes the image, or when the image is skipped.
+    let cache_use = cache.start_use(input_path);
+
+    let input = read?.limit_pixels(limit_args.max_pixels)?;
+
+    #[cfg(feature = "scripting")]
+    let resize_args = script::apply(resize_args, &input, index)?;
+    #[cfg(not(feature = "scripting"))]
+    let resize_args = Cow::Borrowed(resize_args);
+
+    let new_path = new_path(entry, index, &resize_args, || Ok(input.dimensions()?))?;
+
+    if resize_args.only_newer && up_to_date(input_path, &new_path) {
+        info!(
+            "skipping {}, as {} is up to date",
+            input_path.display(),
+            new_path.display()
         );
-    } else if let Some(resize_args) = resize_args {
-        // If `generate` is not used, then do the image resize instead.
 
-        let cli::ResizeArgs {
+        return Ok(Prepared::Finished(
+            Report::new(input_path, Status::Skipped).with_output(&new_path),
+        ));
+    }
+
+    let fingerprint = manifest
+        .map(|manifest| {
+            Ok::<_, InvalidArguments>((manifest, Fingerprint::new(&input, &resize_args)?))
+        })
+        .transpose()?;
+    if let Some((manifest, fingerprint)) = &fingerprint {
+        if manifest.is_unchanged(&new_path, fingerprint)? {
+            info!(
+                "skipping {}, as {} was made from it with the same settings",
+                input_path.display(),
+                new_path.display()
+            );
+
+            return Ok(Prepared::Finished(
+                Report::new(input_path, Status::Skipped).with_output(&new_path),
+            ));
+        }
+    }
+
+    if resize_args.no_clobber && !dry_run && new_path.exists() {
+        return Err(FileError::Exists { path: new_path }.into());
+    }
+
+    feather::check(input.dimensions()?, resize_args.feather)?;
+
+    if *dry_run {
+        // Only read the dimensions from the image's header.
+        let dimensions = input.dimensions()?;
+        let new_dimensions = output_dimensions(dimensions, &resize_args)?;
+        debug!(
+            "{}: {dimensions:?} would be resized to {new_dimensions:?}",
+            input_path.display()
+        );
+
+        return Ok(Prepared::Finished(Report {
+            old_dimensions: Some(dimensions.into()),
+            new_dimensions: Some(finished_dimensions(new_dimensions, &resize_args).into()),
+            tiles: Some(tiles(new_dimensions, dimensions, &resize_args).into()),
+
+            ..Report::new(input_path, Status::Skipped).with_output(&new_path)
+        }));
+    }
+
+    let output_format = file::output_format(&new_path)?;
+    // The input's attributes are read before anything is written, as the output may replace it.
+    let attributes = preserved_attributes(input_path, &resize_args)?;
+
+    if let Some(directory) = new_path.parent().filter(|_| output_dir.is_some()) {
+        fs::create_dir_all(directory).map_err(|source| FileError::Write {
+            path: directory.to_owned(),
+            source,
+        })?;
+    }
+
+    let image = match input.decode_animation()? {
+        Some((animation, metadata)) if Animation::can_encode(output_format) => {
+            DecodedImage::Animation(animation, metadata)
+        }
+
+        animation => {
+            if animation.is_some() {
+                warn!("{}: {output_format:?} images cannot be animated, so only the first frame of {} will be used", new_path.display(), input_path.display());
+            }
+
+            if resize_args.stream {
+                DecodedImage::Streamed(input)
+            } else {
+                let indexed = input.indexed;
+                let (mut image, mut metadata) = cache_use.decode(input)?;
+                convert_to_srgb(&mut image, &mut metadata, input_path, &resize_args)?;
+
+                DecodedImage::Still {
+                    image,
+                    metadata,
+                    indexed,
+                }
+            }
+        }
+    };
+
+    Ok(Prepared::Decoded(Box::new(Decoded {
+        resize_args,
+        new_path,
+        fingerprint,
+        attributes,
+        image,
+    })))
+}
+
+/// Finishes resizing the given `decoded` image read from `input_path`, writing it to its output
+/// and returning a [`Report`] of the outcome.
+///
+/// The given `progress` bar is advanced for each tile overlaid.
+fn finish(
+    input_path: &Path,
+    decoded: Decoded,
+    progress: &ProgressBar,
+) -> Result<Report, Box<dyn Error>> {
+    let Decoded {
+        resize_args,
+        new_path,
+        fingerprint,
+        attributes,
+        image,
+    } = decoded;
+    let (resize_args, new_path) = (&*resize_args, &*new_path);
+
+    let report = match image {
+        DecodedImage::Animation(animation, metadata) => resize_animation(
             input_path,
-            output_path,
+            new_path,
+            animation,
+            metadata,
+            resize_args,
+            progress,
+        )?,
+        DecodedImage::Streamed(input) => {
+            resize_streaming(input_path, new_path, input, resize_args, progress)?
+        }
+        DecodedImage::Still {
+            image,
+            metadata,
+            indexed,
+        } => resize_still(
+            input_path,
+            new_path,
+            &image,
+            metadata,
+            indexed,
+            resize_args,
+            progress,
+        )?,
+    };
+    preserve(attributes.as_ref(), new_path, resize_args)?;
+
+    if let Some((manifest, fingerprint)) = fingerprint {
+        manifest.record(new_path, fingerprint)?;
+    }
+
+    Ok(report)
+}
+
+/// Resizes the given still `image` read from `input_path` along with its `metadata`, writing it
+/// to `new_path`, as indexed colors where possible if `indexed` is true.
+///
+/// The given `progress` bar is advanced for each tile overlaid.
+fn resize_still(
+    input_path: &Path,
+    new_path: &Path,
+    image: &DynamicImage,
+    metadata: Metadata,
+    indexed: bool,
+    resize_args: &cli::ResizeArgs,
+    progress: &ProgressBar,
+) -> Result<Report, Box<dyn Error>> {
+    let metadata = output_metadata(metadata, resize_args);
 
-            fit_scale: Size(fit_width, fit_height),
-            output_scale: Size(output_width, output_height),
-        } = resize_args;
+    // Determine the scaled dimensions for the new image.
+    let (width, height) = output_dimensions(image.dimensions(), resize_args)?;
+    debug!(
+        "{}: resizing from {:?} to {:?}",
+        input_path.display(),
+        image.dimensions(),
+        (width, height),
+    );
 
-        // Read the image in.
-        let image = image::io::Reader::open(&input_path)?.decode()?.into_rgba8();
+    // Create the new image with the desired dimensions and copy the old one onto it.
+    let new_image = extended(image, (width, height), resize_args, progress);
+    let new_image = finished(new_image, resize_args);
 
-        // Determine the scaled dimensions for the new image.
-        let (width, height) = (
-            (image.width() * output_width) / fit_width,
-            (image.height() * output_height) / fit_height,
+    // Save the image.
+    if new_path == input_path {
+        info!(
+            "overwriting {}, as no --output-path was given",
+            input_path.display()
         );
+    } else {
+        info!("writing {}", new_path.display());
+    }
+    back_up(new_path, resize_args)?;
+    file::write(
+        &new_image,
+        &metadata,
+        new_path,
+        &resize_args.encode_args,
+        indexed,
+    )?;
+
+    Ok(Report {
+        old_dimensions: Some(image.dimensions().into()),
+        new_dimensions: Some(new_image.dimensions().into()),
+        tiles: Some(tiles((width, height), image.dimensions(), resize_args).into()),
 
-        // Create the new image with the desired dimensions and copy the old one onto it.
-        let mut new_image = RgbaImage::new(width, height);
-        repeat(&mut new_image, &image);
+        ..Report::new(input_path, Status::Resized).with_output(new_path)
+    })
+}
+
+/// Converts the given `image` read from `input_path` to sRGB from the ICC profile in its
+/// `metadata` if `--convert-to-srgb` is given, removing the profile.
+fn convert_to_srgb(
+    image: &mut Arc<DynamicImage>,
+    metadata: &mut Metadata,
+    input_path: &Path,
+    resize_args: &cli::ResizeArgs,
+) -> Result<(), Box<dyn Error>> {
+    if !resize_args.convert_to_srgb {
+        return Ok(());
+    }
 
-        // Save the image.
-        let new_path = output_path.unwrap_or(input_path);
-        new_image.save(new_path)?;
+    // Once converted, the profile no longer applies to the image - untagged images are assumed to
+    // be sRGB.
+    if let Some(icc) = metadata.icc.take() {
+        debug!("{}: converting to sRGB", input_path.display());
+        color::convert_to_srgb(Arc::make_mut(image), &icc)?;
     }
 
     Ok(())
 }
 
+/// The path to write the output for the image of the given `entry`, the `index`th input, to
+/// according to the given `resize_args`.
+///
+/// The image's `dimensions` are only needed to render an `--output-template`.
+fn new_path(
+    entry: &walk::Entry,
+    index: usize,
+    resize_args: &cli::ResizeArgs,
+    dimensions: impl FnOnce() -> Result<(u32, u32), Box<dyn Error>>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let cli::ResizeArgs {
+        output_path,
+        output_template,
+        output_dir,
+        format,
+        ..
+    } = resize_args;
+
+    let file_name = match output_template {
+        Some(template) => {
+            let new_dimensions =
+                finished_dimensions(output_dimensions(dimensions()?, resize_args)?, resize_args);
+            let placeholders = Placeholders::new(&entry.path, new_dimensions, index);
+            Some(template::render(
+                template,
+                &Placeholders {
+                    format: format.as_deref().map_or(placeholders.format, OsStr::new),
+                    ..placeholders
+                },
+            )?)
+        }
+
+        None => None,
+    };
+
+    let new_path = match (output_path, output_dir, file_name) {
+        (Some(output_path), ..) => output_path.clone(),
+        (None, Some(output_dir), file_name) => entry.output_path(output_dir, file_name.as_deref()),
+        (None, None, Some(file_name)) => file_name,
+
+        (None, None, None) => entry.path.clone(),
+    };
+
+    // The template gives the output's extension itself, with `{format}` for the `--format`.
+    Ok(match format {
+        Some(format) if output_template.is_none() => new_path.with_extension(format),
+        _ => new_path,
+    })
+}
+
+/// The metadata to write to the output alongside the input's `metadata`: none with
+/// `--strip-metadata`, and with the pixel density given by `--dpi`.
+fn output_metadata(metadata: Metadata, resize_args: &cli::ResizeArgs) -> Metadata {
+    let mut metadata = if resize_args.strip_metadata {
+        Metadata::default()
+    } else {
+        metadata
+    };
+
+    if let Some(dpi) = resize_args.dpi {
+        metadata.dpi = Some(dpi.get());
+    }
+
+    metadata
+}
+
+/// Resizes every frame of the given `animation` read from `input_path`, writing it to `new_path`.
+///
+/// The given `progress` bar is advanced for each frame.
+fn resize_animation(
+    input_path: &Path,
+    new_path: &Path,
+    animation: Animation,
+    metadata: Metadata,
+    resize_args: &cli::ResizeArgs,
+    progress: &ProgressBar,
+) -> Result<Report, Box<dyn Error>> {
+    let dimensions = animation.dimensions();
+    let new_dimensions = output_dimensions(dimensions, resize_args)?;
+    debug!(
+        "{}: resizing from {dimensions:?} to {new_dimensions:?}",
+        input_path.display()
+    );
+
+    let metadata = output_metadata(metadata, resize_args);
+
+    progress.set_length(animation.frames.len() as u64);
+    progress.set_message("frames");
+
+    let new_animation = animation.map_frames(|frame| {
+        let frame = DynamicImage::ImageRgba8(frame.clone());
+        let new_frame = extended(&frame, new_dimensions, resize_args, &ProgressBar::hidden());
+        let new_frame = finished(new_frame, resize_args).into_rgba8();
+        progress.inc(1);
+
+        new_frame
+    });
+
+    info!("writing {}", new_path.display());
+    back_up(new_path, resize_args)?;
+    file::write_animation(new_animation, &metadata, new_path, &resize_args.encode_args)?;
+
+    Ok(Report {
+        old_dimensions: Some(dimensions.into()),
+        new_dimensions: Some(finished_dimensions(new_dimensions, resize_args).into()),
+        tiles: Some(tiles(new_dimensions, dimensions, resize_args).into()),
+
+        ..Report::new(input_path, Status::Resized).with_output(new_path)
+    })
+}
+
+/// Resizes the PNG image read from `input_path` one row at a time, writing it to `new_path`.
+///
+/// The given `progress` bar is advanced for each row written.
+fn resize_streaming(
+    input_path: &Path,
+    new_path: &Path,
+    input: file::Input,
+    resize_args: &cli::ResizeArgs,
+    progress: &ProgressBar,
+) -> Result<Report, Box<dyn Error>> {
+    if input.format != Some(ImageFormat::Png) || file::output_format(new_path)? != ImageFormat::Png
+    {
+        return Err(InvalidArguments("--stream requires PNG inputs and outputs".into()).into());
+    }
+    if input
+        .orientation
+        .is_some_and(|orientation| orientation != 1)
+    {
+        return Err(InvalidArguments("--stream cannot apply EXIF orientations - use --no-auto-orient to keep them in the metadata instead".into()).into());
+    }
+
+    let dimensions = input.dimensions()?;
+    let new_dimensions = output_dimensions(dimensions, resize_args)?;
+    debug!(
+        "{}: streaming from {dimensions:?} to {new_dimensions:?}",
+        input_path.display()
+    );
+
+    let metadata = output_metadata(input.metadata.clone(), resize_args);
+    let metadata = file::with_density(&metadata, &resize_args.encode_args);
+
+    info!("writing {}", new_path.display());
+    back_up(new_path, resize_args)?;
+    file::write_atomically(new_path, |temporary_path| {
+        stream::repeat_png(
+            input.bytes(),
+            new_dimensions,
+            &metadata,
+            temporary_path,
+            progress,
+        )
+        .map_err(|error| match error.downcast::<png::DecodingError>() {
+            // The input is decoded again for each row of repetitions, so decoding errors can come
+            // after the output has been partly written.
+            Ok(error) => FileError::Decode {
+                path: input_path.to_owned(),
+                source: ImageError::Decoding(DecodingError::new(
+                    ImageFormatHint::Exact(ImageFormat::Png),
+                    *error,
+                )),
+            },
+
+            Err(source) => FileError::Encode {
+                path: new_path.to_owned(),
+                source,
+            },
+        })
+    })?;
+
+    Ok(Report {
+        old_dimensions: Some(dimensions.into()),
+        new_dimensions: Some(new_dimensions.into()),
+        tiles: Some(repetitions(new_dimensions, dimensions).into()),
+
+        ..Report::new(input_path, Status::Resized).with_output(new_path)
+    })
+}
+
+/// Copies the file at `path`, if there is one, to its path with the `--backup` suffix, if one is
+/// given.
+fn back_up(path: &Path, resize_args: &cli::ResizeArgs) -> Result<(), FileError> {
+    let Some(suffix) = &resize_args.backup else {
+        return Ok(());
+    };
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(suffix);
+    let backup_path = PathBuf::from(backup_path);
+    debug!("backing up {} to {}", path.display(), backup_path.display());
+
+    match fs::copy(path, &backup_path) {
+        Ok(_) => Ok(()),
+        Err(source) => Err(FileError::Write {
+            path: backup_path,
+            source,
+        }),
+    }
+}
+
+/// The attributes of the file at `input_path` to copy onto its output, if `--preserve` is given.
+fn preserved_attributes(
+    input_path: &Path,
+    resize_args: &cli::ResizeArgs,
+) -> Result<Option<fs::Metadata>, FileError> {
+    if resize_args.preserve.is_empty() {
+        return Ok(None);
+    }
+
+    fs::metadata(input_path)
+        .map(Some)
+        .map_err(|source| FileError::Read {
+            path: input_path.to_owned(),
+            source,
+        })
+}
+
+/// Copies the attributes given by `--preserve` from the input's `attributes` onto the output at
+/// `new_path`.
+fn preserve(
+    attributes: Option<&fs::Metadata>,
+    new_path: &Path,
+    resize_args: &cli::ResizeArgs,
+) -> Result<(), FileError> {
+    let Some(attributes) = attributes else {
+        return Ok(());
+    };
+    let write_error = |source| FileError::Write {
+        path: new_path.to_owned(),
+        source,
+    };
+
+    // The times are set first, as the file cannot be opened to set them once any permission to
+    // write to it is removed.
+    if resize_args.preserve.contains(&cli::Preserve::Times) {
+        debug!("copying the times of the input onto {}", new_path.display());
+
+        let times = FileTimes::new()
+            .set_accessed(attributes.accessed().map_err(write_error)?)
+            .set_modified(attributes.modified().map_err(write_error)?);
+        File::options()
+            .write(true)
+            .open(new_path)
+            .and_then(|file| file.set_times(times))
+            .map_err(write_error)?;
+    }
+    if resize_args.preserve.contains(&cli::Preserve::Mode) {
+        debug!(
+            "copying the permissions of the input onto {}",
+            new_path.display()
+        );
+        fs::set_permissions(new_path, attributes.permissions()).map_err(write_error)?;
+    }
+
+    Ok(())
+}
+
+/// The dimensions of the output image for an input image with the given `dimensions`, according
+/// to the given `resize_args`.
+///
+/// Errors if the output image would be larger than allowed by `--max-pixels` or
+/// `--max-output-size`.
+fn output_dimensions(
+    dimensions: (u32, u32),
+    resize_args: &cli::ResizeArgs,
+) -> Result<(u32, u32), Box<dyn Error>> {
+    let new_dimensions = match (
+        resize_args.tiles,
+        resize_args.output_size,
+        resize_args.fit_scale,
+        resize_args.output_scale,
+    ) {
+        (Some(tiles), ..) => tiled_dimensions(dimensions, tiles, resize_args)?,
+        (None, Some(output_size), ..) => {
+            sized_dimensions(dimensions, output_size, resize_args.dpi, resize_args.round)?
+        }
+        (None, None, Some(fit_scale), Some(output_scale)) => {
+            scale_dimensions(dimensions, fit_scale, output_scale, resize_args.round)?
+        }
+
+        _ => unreachable!(
+            "clap requires --fit-scale and --output-scale without --tiles or --output-size"
+        ),
+    };
+    check_output_dimensions(new_dimensions, &resize_args.limit_args)?;
+
+    Ok(new_dimensions)
+}
+
+/// Errors if an output image with the given dimensions would be empty, or larger than allowed by
+/// the given `limit_args`.
+pub fn check_output_dimensions(
+    (width, height): (u32, u32),
+    limit_args: &cli::LimitArgs,
+) -> Result<(), Box<dyn Error>> {
+    let max_pixels = limit_args.max_pixels;
+
+    // Sizes that are not whole pixels may round down to nothing.
+    if width == 0 || height == 0 {
+        return Err(InvalidArguments(format!(
+            "the output would be {width}x{height}, but images cannot be empty"
+        ))
+        .into());
+    }
+    if u64::from(width) * u64::from(height) > max_pixels {
+        return Err(InvalidArguments(format!("the output would be {width}x{height}, which is more than the maximum of {max_pixels} pixels (see --max-pixels)")).into());
+    }
+
+    if let Some(Size(max_width, max_height)) = limit_args.max_output_size {
+        if width > max_width || height > max_height {
+            return Err(InvalidArguments(format!("the output would be {width}x{height}, which is larger than the maximum of {max_width}x{max_height} (see --max-output-size)")).into());
+        }
+    }
+
+    Ok(())
+}
+
+impl cli::FitScale {
+    /// The scale of an image with the given `dimensions`.
+    fn resolve(self, (width, height): (u32, u32)) -> Size<Ratio> {
+        let divisor = match self {
+            Self::Size(size) => return size,
+            Self::Auto { reduced: false } => 1,
+            Self::Auto { reduced: true } => gcd(width, height).max(1),
+        };
+
+        Size(
+            Ratio(f64::from(width / divisor)),
+            Ratio(f64::from(height / divisor)),
+        )
+    }
+}
+
+/// The greatest common divisor of `a` and `b`, or 0 if both are 0.
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+impl cli::Rounding {
+    /// Rounds the given `length` to a whole number.
+    fn round(self, length: f64) -> f64 {
+        match self {
+            Self::Floor => length.floor(),
+            Self::Ceil => length.ceil(),
+            Self::Nearest => length.round(),
+            Self::Even => (length / 2.0).round() * 2.0,
+        }
+    }
+
+    /// Divides `numerator` by `denominator`, which must not be 0, rounding the quotient to a whole
+    /// number exactly.
+    fn divide(self, numerator: u64, denominator: u64) -> u64 {
+        let (quotient, remainder) = (numerator / denominator, numerator % denominator);
+
+        match self {
+            Self::Floor => quotient,
+            Self::Ceil => quotient + u64::from(remainder > 0),
+            Self::Nearest => quotient + u64::from(remainder >= denominator - remainder),
+            Self::Even => Self::Nearest.divide(numerator, denominator * 2) * 2,
+        }
+    }
+}
+
+/// The given `ratio` as an integer, if it is a whole number that fits in 32 bits.
+fn whole_ratio(Ratio(ratio): Ratio) -> Option<u32> {
+    (ratio.fract() == 0.0 && ratio <= f64::from(u32::MAX)).then_some(ratio as u32)
+}
+
+/// Scales the given `dimensions` by `output_scale` in relation to `fit_scale`, rounding to whole
+/// pixels as given by `rounding`.
+///
+/// Errors if the scaled dimensions are not finite or do not fit in 32 bits.
+fn scale_dimensions(
+    (width, height): (u32, u32),
+    fit_scale: cli::FitScale,
+    Size(output_width, output_height): Size<Ratio>,
+    rounding: cli::Rounding,
+) -> Result<(u32, u32), Box<dyn Error>> {
+    let Size(fit_width, fit_height) = fit_scale.resolve((width, height));
+
+    // Zero scales are rejected when parsing, and images are never empty, so `fit` is never 0.
+    let scaled =
+        |length: u32, output: Ratio, fit: Ratio| match (whole_ratio(output), whole_ratio(fit)) {
+            // Whole scales are reduced to their simplest ratio and scaled exactly - multiplying two
+            // 32-bit integers cannot overflow 64 bits. Any result that fits in 32 bits is exactly
+            // representable as a float.
+            (Some(output), Some(fit)) => {
+                let divisor = gcd(output, fit);
+                let (output, fit) = (output / divisor, fit / divisor);
+
+                rounding.divide(u64::from(length) * u64::from(output), u64::from(fit)) as f64
+            }
+
+            _ => rounding.round(f64::from(length) * output.0 / fit.0),
+        };
+
+    whole_dimensions(
+        scaled(width, output_width, fit_width),
+        scaled(height, output_height, fit_height),
+    )
+}
+
+/// The dimensions in pixels of the given `output_size` for an image with the given `dimensions`,
+/// converting physical lengths at the given `dpi` and rounding to whole pixels as given by
+/// `rounding`.
+///
+/// Errors if a dimension is physical but no `dpi` is given, or if the dimensions do not fit in 32
+/// bits.
+fn sized_dimensions(
+    (image_width, image_height): (u32, u32),
+    Size(width, height): Size<cli::Length>,
+    dpi: Option<NonZeroU32>,
+    rounding: cli::Rounding,
+) -> Result<(u32, u32), Box<dyn Error>> {
+    let pixels = |length, image_length: u32| match (length, dpi) {
+        (cli::Length::Pixels(pixels), _) => Ok(rounding.round(pixels)),
+        (cli::Length::Percent(percent), _) => {
+            Ok(rounding.round(f64::from(image_length) * percent / 100.0))
+        }
+        (cli::Length::Inches(inches), Some(dpi)) => {
+            Ok(rounding.round(inches * f64::from(dpi.get())))
+        }
+
+        (cli::Length::Inches(_), None) => Err(InvalidArguments(
+            "--output-size is given in physical units, so needs --dpi to convert them to pixels"
+                .to_owned(),
+        )),
+    };
+
+    whole_dimensions(pixels(width, image_width)?, pixels(height, image_height)?)
+}
+
+/// Converts the given whole dimensions to integers.
+///
+/// Errors if the dimensions are not finite or do not fit in 32 bits.
+fn whole_dimensions(width: f64, height: f64) -> Result<(u32, u32), Box<dyn Error>> {
+    let fits = |length: f64| length.is_finite() && length <= f64::from(u32::MAX);
+    if !(fits(width) && fits(height)) {
+        return Err(InvalidArguments(format!(
+            "the output would be {width}x{height}, which is too large for any image"
+        ))
+        .into());
+    }
+
+    Ok((width as u32, height as u32))
+}
+
+/// The dimensions of an output of exactly `tiles` repetitions across and down of an image with the
+/// given `dimensions`, accounting for the gaps or overlaps between them given by `resize_args`.
+fn tiled_dimensions(
+    (width, height): (u32, u32),
+    Size(across, down): Size<NonZeroU32>,
+    resize_args: &cli::ResizeArgs,
+) -> Result<(u32, u32), Box<dyn Error>> {
+    let length = |count: NonZeroU32, repeated: u32| {
+        let count = u64::from(count.get());
+        let (gap, feather) = (
+            u64::from(resize_args.tile_gap),
+            u64::from(resize_args.feather),
+        );
+
+        // Each repetition but the first adds its length and the gap before it, less the part of
+        // it that overlaps the one before it.
+        (u64::from(repeated) * count + gap * (count - 1)).saturating_sub(feather * (count - 1))
+    };
+    let (new_width, new_height) = (length(across, width), length(down, height));
+
+    match (u32::try_from(new_width), u32::try_from(new_height)) {
+        (Ok(new_width), Ok(new_height)) => Ok((new_width, new_height)),
+
+        _ => Err(InvalidArguments(format!(
+            "the output would be {new_width}x{new_height}, which is too large for any image"
+        ))
+        .into()),
+    }
+}
+
+/// The number of horizontal and vertical repetitions of an image with `repeated` dimensions that
+/// fit within the `base` dimensions.
+fn repetitions(
+    (base_width, base_height): (u32, u32),
+    (repeated_width, repeated_height): (u32, u32),
+) -> (u32, u32) {
+    (base_width / repeated_width, base_height / repeated_height)
+}
+
+/// The number of horizontal and vertical repetitions of an image with the given `dimensions` in
+/// its output with the `new_dimensions`, according to the given `resize_args`.
+fn tiles(
+    new_dimensions: (u32, u32),
+    dimensions: (u32, u32),
+    resize_args: &cli::ResizeArgs,
+) -> (u32, u32) {
+    match resize_args.fill {
+        cli::Fill::Tile | cli::Fill::Gradient(_) if resize_args.feather > 0 => {
+            feather::repetitions(new_dimensions, dimensions, resize_args.feather)
+        }
+        cli::Fill::Tile | cli::Fill::Gradient(_) => {
+            Layout::new(resize_args).repetitions(new_dimensions, dimensions)
+        }
+        cli::Fill::Blur => (1, 1),
+    }
+}
+
+/// Extends the given `image` to the given dimensions, filling the new canvas according to the
+/// given `resize_args`.
+fn extended(
+    image: &DynamicImage,
+    dimensions: (u32, u32),
+    resize_args: &cli::ResizeArgs,
+    progress: &ProgressBar,
+) -> DynamicImage {
+    if resize_args.feather > 0 {
+        let new_image = feather::feathered(image, dimensions, resize_args.feather, progress);
+
+        return match resize_args.fill {
+            cli::Fill::Gradient(gradient) => fill::over_gradient(&new_image, gradient),
+            _ => new_image,
+        };
+    }
+
+    let layout = Layout::new(resize_args);
+    let gradient = match resize_args.fill {
+        cli::Fill::Tile => None,
+        cli::Fill::Gradient(gradient) => Some(gradient),
+
+        cli::Fill::Blur => return fill::blurred(image, dimensions, progress),
+    };
+    let gap = (layout.gap > 0 && resize_args.gap_color.0[3] > 0).then(|| {
+        let extent = layout.extent(dimensions, image.dimensions());
+        (extent, resize_args.gap_color)
+    });
+
+    // The background is filled in first, so that `--alpha` decides whether the repetitions are
+    // blended over it or replace it.
+    repeated_over(
+        image,
+        dimensions,
+        fill::background(dimensions, gradient, gap),
+        resize_args.alpha,
+        &layout,
+        progress,
+    )
+}
+
+/// Applies the effects given by the `resize_args` to the given extended `image`: blurring it,
+/// rounding its corners, and then drawing its shadow.
+fn finished(image: DynamicImage, resize_args: &cli::ResizeArgs) -> DynamicImage {
+    let image = match resize_args.blur {
+        Some(cli::Sigma(sigma)) => image.blur(sigma),
+        None => image,
+    };
+    let image = match resize_args.corner_radius {
+        Some(radius) => mask::rounded(&image, radius.get()),
+        None => image,
+    };
+
+    match resize_args.shadow {
+        Some(shadow) => shadow::shadowed(&image, shadow),
+        None => image,
+    }
+}
+
+/// The dimensions of an extended image with the given `dimensions` once it is [finished].
+fn finished_dimensions(dimensions: (u32, u32), resize_args: &cli::ResizeArgs) -> (u32, u32) {
+    match resize_args.shadow {
+        Some(shadow) => shadow::dimensions(dimensions, shadow),
+        None => dimensions,
+    }
+}
+
+/// Repeats the given `image` across a new transparent image with the given dimensions, as
+/// [`repeat`] does, keeping the sample format of the `image`.
+fn repeated(
+    image: &DynamicImage,
+    dimensions: (u32, u32),
+    alpha: cli::Alpha,
+    layout: &Layout,
+    progress: &ProgressBar,
+) -> DynamicImage {
+    repeated_over(image, dimensions, None, alpha, layout, progress)
+}
+
+/// Repeats the given `image` across a new image with the given dimensions, as [`repeat`] does,
+/// starting from the given `background` if there is one rather than a transparent image, and
+/// keeping the sample format of the `image`.
+fn repeated_over(
+    image: &DynamicImage,
+    dimensions: (u32, u32),
+    background: Option<Rgba32FImage>,
+    alpha: cli::Alpha,
+    layout: &Layout,
+    progress: &ProgressBar,
+) -> DynamicImage {
+    fn repeated_buffer<P>(
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+        (width, height): (u32, u32),
+        background: Option<ImageBuffer<P, Vec<P::Subpixel>>>,
+        alpha: cli::Alpha,
+        layout: &Layout,
+        progress: &ProgressBar,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel + Send + Sync + 'static,
+        P::Subpixel: Send + Sync,
+    {
+        let mut new_image = background.unwrap_or_else(|| ImageBuffer::new(width, height));
+        repeat(&mut new_image, image, alpha, layout, progress);
+
+        new_image
+    }
+
+    // Any part of the new image left uncovered is transparent, which needs an alpha channel.
+    let covered = layout.covers(dimensions, image.dimensions());
+
+    let image = if covered || image.color().has_alpha() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(with_alpha(image))
+    };
+
+    // Repetitions are blended over a background at the highest precision, as it is filled in.
+    if let Some(background) = background {
+        let new_image = repeated_buffer(
+            &image.to_rgba32f(),
+            dimensions,
+            Some(background),
+            alpha,
+            layout,
+            progress,
+        );
+
+        return scale::with_color(new_image.into(), image.color());
+    }
+
+    #[cfg(feature = "gpu")]
+    if layout.is_grid() && u64::from(dimensions.0) * u64::from(dimensions.1) >= gpu::MIN_PIXELS {
+        match gpu::repeated(&image, dimensions, progress) {
+            Ok(new_image) => return new_image,
+            Err(error) => debug!("repeating on the CPU instead of the GPU: {error}"),
+        }
+    }
+
+    match &*image {
+        DynamicImage::ImageLuma8(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageLumaA8(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgb8(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgba8(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageLuma16(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageLumaA16(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgb16(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgba16(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgb32F(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+        DynamicImage::ImageRgba32F(image) => {
+            repeated_buffer(image, dimensions, None, alpha, layout, progress).into()
+        }
+
+        // Any other sample format is repeated at the highest precision.
+        image => repeated_buffer(
+            &image.to_rgba32f(),
+            dimensions,
+            None,
+            alpha,
+            layout,
+            progress,
+        )
+        .into(),
+    }
+}
+
+/// Converts the given `image` to the equivalent sample format with an alpha channel.
+fn with_alpha(image: &DynamicImage) -> DynamicImage {
+    match image.color() {
+        ColorType::L8 => image.to_luma_alpha8().into(),
+        ColorType::L16 => image.to_luma_alpha16().into(),
+        ColorType::Rgb16 => image.to_rgba16().into(),
+        ColorType::Rgb32F => image.to_rgba32f().into(),
+
+        _ => image.to_rgba8().into(),
+    }
+}
+
 /// Repeats the given `repeated` image across the given `base` image as many times as it will fit.
 ///
 /// This is similar to [`imageops::tile`], but if the `repeated` image would be cut off, it is not
-/// overlaid.
-pub fn repeat<BaseImage, RepeatedImage>(base: &mut BaseImage, repeated: &RepeatedImage)
-where
-    BaseImage: GenericImage,
-    RepeatedImage: GenericImageView<Pixel = BaseImage::Pixel>,
+/// overlaid. Each row of repetitions is overlaid in parallel.
+///
+/// The `repeated` image is blended over the `base` image, or copied over it if `alpha` is
+/// [`Replace`](cli::Alpha::Replace). Fully opaque images are always copied, as blending them has
+/// the same result. The repetitions are laid out in a grid from the top left corner unless
+/// another `layout` is given.
+///
+/// The length of the given `progress` bar is set to the number of repetitions, and it is advanced
+/// as each is overlaid.
+pub fn repeat<P>(
+    base: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    repeated: &ImageBuffer<P, Vec<P::Subpixel>>,
+    alpha: cli::Alpha,
+    layout: &Layout,
+    progress: &ProgressBar,
+) where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
 {
-    // The number of horizontal repetitions of `repeated`.
-    let horizontal = base.width() / repeated.width();
-    // The number of vertical repetitions of `repeated`.
-    let vertical = base.height() / repeated.height();
-
-    // For each repetition position...
-    for (i, j) in (0..horizontal).mix(0..vertical) {
-        let (x, y) = (
-            i64::from(i * repeated.width()),
-            i64::from(j * repeated.height()),
+    // The number of horizontal and vertical repetitions of `repeated`.
+    let (horizontal, vertical) = repetitions(base.dimensions(), repeated.dimensions());
+
+    progress.set_length(u64::from(horizontal) * u64::from(vertical));
+    debug!("overlaying {horizontal}x{vertical} tiles");
+
+    if horizontal == 0 || vertical == 0 {
+        return;
+    }
+
+    let copy = alpha == cli::Alpha::Replace
+        || repeated
+            .pixels()
+            .all(|pixel| pixel.to_rgba()[3] == P::Subpixel::DEFAULT_MAX_VALUE);
+
+    if !layout.is_grid() {
+        return tiling::repeat(base, repeated, copy, layout, progress);
+    }
+
+    let width = base.width();
+    // The number of samples in each row of the base and each row of the repeated image.
+    let row_length = width as usize * usize::from(P::CHANNEL_COUNT);
+    let repeated_row_length = repeated.width() as usize * usize::from(P::CHANNEL_COUNT);
+    // The number of samples in each row of repetitions.
+    let band_length = row_length * repeated.height() as usize;
+
+    let samples: &mut [P::Subpixel] = base;
+
+    // For each row of repetitions...
+    samples[..band_length * vertical as usize]
+        .par_chunks_mut(band_length)
+        .for_each(|band| {
+            if copy {
+                // Copy each row of the repeated image into each repetition.
+                let repeated_rows = repeated.chunks(repeated_row_length);
+
+                for (row, repeated_row) in band.chunks_mut(row_length).zip(repeated_rows) {
+                    for tile in row[..repeated_row_length * horizontal as usize]
+                        .chunks_mut(repeated_row_length)
+                    {
+                        tile.copy_from_slice(repeated_row);
+                    }
+                }
+
+                progress.inc(u64::from(horizontal));
+                return;
+            }
+
+            let mut band = ImageBuffer::<P, _>::from_raw(width, repeated.height(), band)
+                .expect("bands are the length of a row of repetitions");
+
+            // Overlay the repeated image at each position.
+            for i in 0..horizontal {
+                imageops::overlay(&mut band, repeated, i64::from(i * repeated.width()), 0);
+                progress.inc(1);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Alpha, Color, Gradient};
+    use crate::tiling::Extent;
+    use image::{Rgba, RgbaImage};
+
+    /// Repeats a semi-transparent red image across a 4x4 image over the given `background`.
+    fn repeated_semi_transparent(background: Option<Rgba32FImage>, alpha: Alpha) -> RgbaImage {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 128])).into();
+
+        repeated_over(
+            &image,
+            (4, 4),
+            background,
+            alpha,
+            &Layout::default(),
+            &ProgressBar::hidden(),
+        )
+        .to_rgba8()
+    }
+
+    fn scale(
+        dimensions: (u32, u32),
+        fit_scale: (f64, f64),
+        output_scale: (f64, f64),
+        rounding: cli::Rounding,
+    ) -> Result<(u32, u32), Box<dyn Error>> {
+        scale_dimensions(
+            dimensions,
+            cli::FitScale::Size(Size(Ratio(fit_scale.0), Ratio(fit_scale.1))),
+            Size(Ratio(output_scale.0), Ratio(output_scale.1)),
+            rounding,
+        )
+    }
+
+    #[test]
+    fn fractional_scales_are_rounded() {
+        use cli::Rounding::*;
+
+        // 100 * 1.78 / 3 = 59.33..., and 101 * 2.5 / 2 = 126.25.
+        let dimensions = (101, 100);
+        let (fit, output) = ((2.0, 3.0), (2.5, 1.78));
+
+        assert_eq!(scale(dimensions, fit, output, Nearest).unwrap(), (126, 59));
+        assert_eq!(scale(dimensions, fit, output, Floor).unwrap(), (126, 59));
+        assert_eq!(scale(dimensions, fit, output, Ceil).unwrap(), (127, 60));
+        assert_eq!(scale(dimensions, fit, output, Even).unwrap(), (126, 60));
+    }
+
+    #[test]
+    fn fractional_fit_scales_are_divided_by() {
+        assert_eq!(
+            scale((100, 100), (0.5, 0.25), (1.0, 1.0), cli::Rounding::Nearest).unwrap(),
+            (200, 400)
         );
+    }
 
-        // Overlay the repeated image.
-        imageops::overlay(base, repeated, x, y);
+    #[test]
+    fn gcds_are_found() {
+        assert_eq!(gcd(1920, 1080), 120);
+        assert_eq!(gcd(17, 5), 1);
+        assert_eq!(gcd(7, 0), 7);
+        assert_eq!(gcd(0, 0), 0);
+    }
+
+    #[test]
+    fn automatic_fit_scales_are_reduced() {
+        assert_eq!(
+            cli::FitScale::Auto { reduced: true }.resolve((1920, 1080)),
+            Size(Ratio(16.0), Ratio(9.0))
+        );
+        assert_eq!(
+            cli::FitScale::Auto { reduced: false }.resolve((1920, 1080)),
+            Size(Ratio(1920.0), Ratio(1080.0))
+        );
+    }
+
+    #[test]
+    fn whole_numbers_are_divided_exactly() {
+        use cli::Rounding::*;
+
+        assert_eq!(Floor.divide(7, 3), 2);
+        assert_eq!(Ceil.divide(7, 3), 3);
+        assert_eq!(Ceil.divide(6, 3), 2);
+        assert_eq!(Nearest.divide(7, 3), 2);
+        assert_eq!(Nearest.divide(5, 2), 3);
+        assert_eq!(Even.divide(5, 2), 2);
+        assert_eq!(Even.divide(7, 2), 4);
+    }
+
+    #[test]
+    fn whole_scales_are_exact() {
+        // 2612825766 * 3470919672 / 2873692229 is just under 3155838422.5, but comes to exactly
+        // that in floating point, which would round it up.
+        assert_eq!(
+            scale(
+                (2_612_825_766, 1),
+                (2_873_692_229.0, 1.0),
+                (3_470_919_672.0, 1.0),
+                cli::Rounding::Nearest
+            )
+            .unwrap(),
+            (3_155_838_422, 1)
+        );
+    }
+
+    #[test]
+    fn whole_scales_are_reduced_before_scaling() {
+        assert_eq!(
+            scale(
+                (3, 3),
+                (4_000_000_000.0, 3.0),
+                (2_000_000_000.0, 1.0),
+                cli::Rounding::Nearest
+            )
+            .unwrap(),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn scales_too_large_for_32_bits_are_rejected() {
+        assert!(scale(
+            (100_000, 1),
+            (1.0, 1.0),
+            (100_000.0, 1.0),
+            cli::Rounding::Nearest
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn alpha_modes_differ_over_a_gap_color() {
+        let extent = Extent {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 4,
+        };
+        let background = || fill::background((4, 4), None, Some((extent, Color([0, 255, 0, 255]))));
+
+        let blended = repeated_semi_transparent(background(), Alpha::Blend);
+        let replaced = repeated_semi_transparent(background(), Alpha::Replace);
+
+        assert_eq!(blended.get_pixel(0, 0), &Rgba([128, 127, 0, 255]));
+        assert_eq!(replaced.get_pixel(0, 0), &Rgba([255, 0, 0, 128]));
+    }
+
+    #[test]
+    fn alpha_modes_differ_over_a_gradient() {
+        let gradient = Gradient {
+            from: Color([255, 255, 255, 255]),
+            to: Color([0, 136, 255, 255]),
+            angle: 90,
+        };
+        let background = || fill::background((4, 4), Some(gradient), None);
+
+        let blended = repeated_semi_transparent(background(), Alpha::Blend);
+        let replaced = repeated_semi_transparent(background(), Alpha::Replace);
+
+        assert_ne!(blended, replaced);
+        assert!(blended.pixels().all(|pixel| pixel[3] == u8::MAX));
+        assert!(replaced
+            .pixels()
+            .all(|&pixel| pixel == Rgba([255, 0, 0, 128])));
+    }
+
+    #[test]
+    fn alpha_modes_match_over_transparency() {
+        assert_eq!(
+            repeated_semi_transparent(None, Alpha::Blend),
+            repeated_semi_transparent(None, Alpha::Replace),
+        );
     }
 }
@@ -0,0 +1,369 @@
+use crate::cli::{
+    Color, EncodeArgs, Fill, FitScale, Gradient, Length, Ratio, ResizeArgs, Shadow, Sigma, Size,
+    TileLayout, TileRandom,
+};
+use crate::error::{FileError, InvalidArguments};
+use crate::file::{self, Input};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// A record of how each output given by `--manifest` was made, so that outputs whose inputs and
+/// settings are unchanged can be skipped by later runs, whatever their modification times.
+#[derive(Debug)]
+pub struct Manifest {
+    /// The path the manifest is read from and written to.
+    path: PathBuf,
+    /// How each output was made, by its path.
+    outputs: Mutex<BTreeMap<String, Fingerprint>>,
+}
+
+/// How an output was made: from which input, with which contents, and with which settings.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// The path of the input.
+    pub input: String,
+    /// The SHA-256 hash of the input's contents, in hex.
+    pub input_sha256: String,
+    /// The SHA-256 hash of the settings that affect the output, in hex.
+    pub settings_sha256: String,
+}
+
+impl Fingerprint {
+    /// The fingerprint of an output made from the given `input` with the given `resize_args`.
+    ///
+    /// Errors if the input's path is not valid UTF-8, as it could not be recorded as it is.
+    pub fn new(input: &Input, resize_args: &ResizeArgs) -> Result<Self, InvalidArguments> {
+        Ok(Self {
+            input: key(input.path())?.to_owned(),
+            input_sha256: format!("{:x}", Sha256::digest(input.bytes())),
+            settings_sha256: format!("{:x}", Sha256::digest(settings(resize_args))),
+        })
+    }
+}
+
+/// The given `path` as it is recorded in a manifest, which errors if it is not valid UTF-8 rather
+/// than record a different path.
+fn key(path: &Path) -> Result<&str, InvalidArguments> {
+    path.to_str().ok_or_else(|| {
+        InvalidArguments(format!(
+            "{} cannot be recorded in the --manifest, as its path is not valid UTF-8",
+            path.display()
+        ))
+    })
+}
+
+/// The settings in the given `resize_args` that affect the output, along with the version that
+/// made it, as they are hashed for a [`Fingerprint`].
+///
+/// Each setting is written out by name, so that the hash only changes along with the settings.
+/// Which inputs are resized, where their outputs are written, and how the run is reported, make
+/// no difference to each output.
+fn settings(resize_args: &ResizeArgs) -> String {
+    let ResizeArgs {
+        input_paths: _,
+        files_from: _,
+        null: _,
+        job: _,
+        output_path: _,
+        output_template: _,
+        format: _,
+        output_dir: _,
+        recursive: _,
+        extensions: _,
+        no_clobber: _,
+        only_newer: _,
+        manifest: _,
+        backup: _,
+        preserve,
+        yes: _,
+        fit_scale,
+        output_scale,
+        round,
+        tiles,
+        output_size,
+        // The options a script chooses are already in the `resize_args`.
+        #[cfg(feature = "scripting")]
+            script: _,
+        dpi,
+        watch: _,
+        progress: _,
+        no_progress: _,
+        dry_run: _,
+        json: _,
+        strip_metadata,
+        auto_orient: _,
+        no_auto_orient,
+        convert_to_srgb,
+        stream,
+        fill,
+        feather,
+        blur,
+        corner_radius,
+        shadow,
+        tile_origin,
+        tile_layout,
+        repeat_x,
+        repeat_y,
+        clamp_edges,
+        tile_gap,
+        gap_color,
+        tile_random,
+        alpha,
+        encode_args:
+            EncodeArgs {
+                quality,
+                webp_lossless,
+                colors,
+                dither,
+                set_dpi,
+                #[cfg(feature = "avif")]
+                avif_speed,
+            },
+        limit_args: _,
+    } = resize_args;
+
+    // AVIF outputs can only be encoded with the `avif` feature.
+    #[cfg(feature = "avif")]
+    let avif_speed = Some(avif_speed);
+    #[cfg(not(feature = "avif"))]
+    let avif_speed: Option<&u8> = None;
+
+    let settings = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+
+        "preserve": preserve.iter().map(name).collect::<Vec<_>>(),
+        "fit_scale": fit_scale.map(|fit_scale| match fit_scale {
+            FitScale::Size(size) => ratios(size),
+            FitScale::Auto { reduced: false } => json!("auto"),
+            FitScale::Auto { reduced: true } => json!("auto:reduced"),
+        }),
+        "output_scale": output_scale.map(ratios),
+        "round": name(round),
+        "tiles": tiles.map(|Size(across, down)| [across, down]),
+        "output_size": output_size.map(|Size(width, height)| [length(width), length(height)]),
+        "dpi": dpi,
+
+        "strip_metadata": strip_metadata,
+        "auto_orient": !no_auto_orient,
+        "convert_to_srgb": convert_to_srgb,
+        "stream": stream,
+
+        "fill": match fill {
+            Fill::Tile => json!("tile"),
+            Fill::Blur => json!("blur"),
+            Fill::Gradient(Gradient { from, to, angle }) => {
+                json!({ "from": color(from), "to": color(to), "angle": angle })
+            }
+        },
+        "feather": feather,
+        "blur": blur.map(|Sigma(sigma)| sigma),
+        "corner_radius": corner_radius,
+        "shadow": shadow.as_ref().map(|Shadow { offset: Size(x, y), blur, color: shadow_color }| {
+            json!({
+                "offset": [x, y],
+                "blur": blur.map(|Sigma(sigma)| sigma),
+                "color": color(shadow_color),
+            })
+        }),
+        "tile_origin": name(tile_origin),
+        "tile_layout": match tile_layout {
+            TileLayout::Grid => json!("grid"),
+            TileLayout::Brick(offset) => json!({ "brick": offset }),
+        },
+        "repeat_x": repeat_x,
+        "repeat_y": repeat_y,
+        "clamp_edges": clamp_edges,
+        "tile_gap": tile_gap,
+        "gap_color": color(gap_color),
+        "tile_random": tile_random.as_ref().map(|TileRandom { rotate, flip, seed }| {
+            json!({ "rotate": rotate, "flip": flip, "seed": seed })
+        }),
+        "alpha": name(alpha),
+
+        "quality": quality,
+        "webp_lossless": webp_lossless,
+        "colors": colors,
+        "dither": name(dither),
+        "set_dpi": set_dpi,
+        "avif_speed": avif_speed,
+    });
+
+    settings.to_string()
+}
+
+/// The name the given `value` is given by on the command line.
+fn name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("settings are never skipped values")
+        .get_name()
+        .to_owned()
+}
+
+/// The width and height of the given ratios.
+fn ratios(Size(Ratio(width), Ratio(height)): Size<Ratio>) -> Value {
+    json!([width, height])
+}
+
+/// The given `length`, along with its unit.
+fn length(length: Length) -> Value {
+    match length {
+        Length::Pixels(pixels) => json!({ "px": pixels }),
+        Length::Percent(percent) => json!({ "%": percent }),
+        Length::Inches(inches) => json!({ "in": inches }),
+    }
+}
+
+/// The given `color`, as an `#RRGGBBAA` hex color.
+fn color(&Color([red, green, blue, alpha]): &Color) -> String {
+    format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}")
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, or starts an empty one if there is none yet.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let outputs = match fs::read_to_string(path) {
+            Ok(manifest) => serde_json::from_str(&manifest).map_err(|error| {
+                InvalidArguments(format!("invalid manifest {}: {error}", path.display()))
+            })?,
+
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(source) => {
+                return Err(FileError::Read {
+                    path: path.to_owned(),
+                    source,
+                }
+                .into())
+            }
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            outputs: Mutex::new(outputs),
+        })
+    }
+
+    /// Whether the output at `new_path` exists and was made just as it would be with the given
+    /// `fingerprint`.
+    ///
+    /// Errors if `new_path` is not valid UTF-8, as it could not be recorded as it is.
+    pub fn is_unchanged(
+        &self,
+        new_path: &Path,
+        fingerprint: &Fingerprint,
+    ) -> Result<bool, InvalidArguments> {
+        let key = key(new_path)?;
+
+        Ok(new_path.exists()
+            && self
+                .outputs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)
+                == Some(fingerprint))
+    }
+
+    /// Records that the output at `new_path` was made with the given `fingerprint`.
+    ///
+    /// Errors if `new_path` is not valid UTF-8, as it could not be recorded as it is.
+    pub fn record(
+        &self,
+        new_path: &Path,
+        fingerprint: Fingerprint,
+    ) -> Result<(), InvalidArguments> {
+        let key = key(new_path)?.to_owned();
+        self.outputs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, fingerprint);
+
+        Ok(())
+    }
+
+    /// Writes the manifest back to its path, replacing it.
+    pub fn write(&self) -> Result<(), FileError> {
+        let outputs = self.outputs.lock().unwrap_or_else(PoisonError::into_inner);
+        // Serializing the manifest cannot fail: its keys are strings, and it has no fallible
+        // `Serialize` implementations.
+        let manifest =
+            serde_json::to_string_pretty(&*outputs).expect("manifests are serializable") + "\n";
+
+        file::write_atomically(&self.path, |temporary_path| {
+            fs::write(temporary_path, manifest).map_err(|source| FileError::Write {
+                path: self.path.clone(),
+                source,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+    use clap::Parser;
+
+    /// The settings hashed for the given resize arguments.
+    fn settings_of(args: &[&str]) -> String {
+        let args = [
+            "img-resize",
+            "in.png",
+            "--fit-scale",
+            "1x1",
+            "--output-scale",
+            "2x2",
+        ]
+        .iter()
+        .chain(args);
+
+        settings(&cli::Args::parse_from(args).resize_args.unwrap())
+    }
+
+    #[test]
+    fn settings_only_change_with_the_output() {
+        let settings = settings_of(&[]);
+
+        assert_eq!(
+            settings,
+            settings_of(&["--dry-run", "--json", "--only-newer"])
+        );
+        assert_eq!(
+            settings,
+            settings_of(&["--output-dir", "out", "--no-progress"])
+        );
+        assert_ne!(settings, settings_of(&["--fill", "blur"]));
+        assert_ne!(settings, settings_of(&["--gap-color", "#fff"]));
+        assert_ne!(settings, settings_of(&["--no-auto-orient"]));
+        assert_ne!(settings, settings_of(&["--colors", "16"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_rejected() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let manifest = Manifest {
+            path: PathBuf::from("manifest.json"),
+            outputs: Mutex::new(BTreeMap::new()),
+        };
+        let fingerprint = Fingerprint {
+            input: "in.png".to_owned(),
+            input_sha256: String::new(),
+            settings_sha256: String::new(),
+        };
+        let path = Path::new(OsStr::from_bytes(b"out\xff.png"));
+
+        assert!(manifest.is_unchanged(path, &fingerprint).is_err());
+        assert!(manifest.record(path, fingerprint).is_err());
+        assert!(manifest.outputs.lock().unwrap().is_empty());
+    }
+}
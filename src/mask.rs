@@ -0,0 +1,38 @@
+use crate::scale;
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+
+/// Makes the corners of the given `image` transparent outside of a circle with the given
+/// `radius`, anti-aliasing its edge, with the sample format of the `image` plus an alpha channel.
+///
+/// The radius is at most half the image's shorter side, which makes its shorter edges round.
+pub fn rounded(image: &DynamicImage, radius: u32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let radius = radius.min(width.min(height) / 2);
+
+    // The corners become transparent, which needs an alpha channel.
+    let image = if image.color().has_alpha() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(crate::with_alpha(image))
+    };
+    let mut new_image = image.to_rgba32f();
+
+    let radius_f = radius as f32;
+    // The distance of a pixel's center from the center of the nearest corner's circle along one
+    // axis, if it is within that corner.
+    let from_corner = |position: u32, length: u32| {
+        let position = position.min(length - 1 - position);
+        (position < radius).then_some(radius_f - (position as f32 + 0.5))
+    };
+
+    for (x, y, pixel) in new_image.enumerate_pixels_mut() {
+        if let (Some(dx), Some(dy)) = (from_corner(x, width), from_corner(y, height)) {
+            // The fraction of the pixel covered by the circle, approximately.
+            let coverage = (radius_f - dx.hypot(dy) + 0.5).clamp(0.0, 1.0);
+            pixel.0[3] *= coverage;
+        }
+    }
+
+    scale::with_color(new_image.into(), image.color())
+}
@@ -0,0 +1,517 @@
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use image::ImageFormat;
+use log::warn;
+use std::io::{Read, Write};
+use std::iter;
+
+/// The signature at the start of every PNG file.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// The length of the PNG signature and the `IHDR` chunk, which must come first.
+const PNG_HEADER_LENGTH: usize = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+/// The keyword identifying a PNG `iTXt` chunk containing XMP.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+/// The profile name given to written PNG `iCCP` chunks.
+const PNG_ICC_NAME: &[u8] = b"ICC Profile";
+
+/// The JPEG start of image marker, which must come first.
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+/// The JPEG `APP0` marker, used for the JFIF header.
+const JPEG_APP0: u8 = 0xE0;
+/// The header identifying a JPEG `APP0` segment as the JFIF header.
+const JPEG_JFIF_HEADER: &[u8] = b"JFIF\0";
+/// The offset of the density units within the JFIF header, after the version.
+const JPEG_JFIF_UNITS_OFFSET: usize = JPEG_JFIF_HEADER.len() + 2;
+/// The JPEG `APP1` marker, used for EXIF and XMP.
+const JPEG_APP1: u8 = 0xE1;
+/// The JPEG `APP2` marker, used for ICC profiles.
+const JPEG_APP2: u8 = 0xE2;
+/// The JPEG start of scan marker, after which only image data follows.
+const JPEG_SOS: u8 = 0xDA;
+/// The JPEG end of image marker.
+const JPEG_EOI: u8 = 0xD9;
+/// The header identifying a JPEG `APP1` segment containing EXIF.
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// The header identifying a JPEG `APP1` segment containing XMP.
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// The header identifying a JPEG `APP2` segment containing part of an ICC profile.
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+/// The maximum length of a JPEG segment's data.
+const JPEG_MAX_SEGMENT_LENGTH: usize = u16::MAX as usize - 2;
+
+/// The number of meters in an inch, used to convert between the pixels per meter of PNG images
+/// and dots per inch.
+const METERS_PER_INCH: f64 = 0.0254;
+/// The PNG `pHYs` unit for pixels per meter.
+const PNG_UNIT_METER: u8 = 1;
+/// The JFIF density unit for dots per inch.
+const JFIF_UNIT_INCH: u8 = 1;
+/// The JFIF density unit for dots per centimeter.
+const JFIF_UNIT_CENTIMETER: u8 = 2;
+
+/// The EXIF tag of the image's orientation.
+const EXIF_ORIENTATION_TAG: u16 = 0x0112;
+/// The EXIF orientation of an image that is already the right way up.
+const EXIF_ORIENTATION_NORMAL: u16 = 1;
+
+/// Metadata carried by an image alongside its pixels.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Metadata {
+    /// EXIF data, starting with its TIFF header.
+    pub exif: Option<Vec<u8>>,
+    /// An XMP packet.
+    pub xmp: Option<Vec<u8>>,
+    /// An ICC color profile.
+    pub icc: Option<Vec<u8>>,
+    /// The pixel density in dots per inch, used when printing the image.
+    pub dpi: Option<u32>,
+}
+
+impl Metadata {
+    /// Reads the metadata from the given encoded image `bytes` in the given `format`.
+    ///
+    /// Only PNG and JPEG images are supported; no metadata is read from other formats. Malformed
+    /// metadata is ignored.
+    pub fn read(bytes: &[u8], format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Png => Self::read_png(bytes),
+            ImageFormat::Jpeg => Self::read_jpeg(bytes),
+
+            _ => Self::default(),
+        }
+    }
+
+    /// Whether there is no metadata.
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none() && self.icc.is_none() && self.dpi.is_none()
+    }
+
+    /// Writes the metadata into the given encoded image `bytes` in the given `format`.
+    ///
+    /// Only PNG and JPEG images are supported; for other formats, a warning is logged and the
+    /// metadata is dropped. The pixel density is dropped silently, as it is only relevant to
+    /// printing.
+    pub fn write(&self, bytes: Vec<u8>, format: ImageFormat) -> Vec<u8> {
+        if self.is_empty() {
+            return bytes;
+        }
+
+        match format {
+            ImageFormat::Png => self.write_png(bytes),
+            ImageFormat::Jpeg => self.write_jpeg(bytes),
+
+            _ => {
+                if self.exif.is_some() || self.xmp.is_some() || self.icc.is_some() {
+                    warn!(
+                        "metadata cannot be written to {format:?} images, so it has been dropped"
+                    );
+                }
+                bytes
+            }
+        }
+    }
+
+    /// The EXIF orientation of the image, if there is one.
+    ///
+    /// This is a number from 1 to 8 describing how the image must be rotated and flipped to be
+    /// displayed the right way up.
+    pub fn orientation(&self) -> Option<u16> {
+        let exif = self.exif.as_deref()?;
+        let (offset, big_endian) = exif_orientation_offset(exif)?;
+
+        read_u16(exif, offset, big_endian)
+    }
+
+    /// Sets the EXIF orientation to say the image is the right way up, if there is one.
+    pub fn reset_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            if let Some((offset, big_endian)) = exif_orientation_offset(exif) {
+                let bytes = if big_endian {
+                    EXIF_ORIENTATION_NORMAL.to_be_bytes()
+                } else {
+                    EXIF_ORIENTATION_NORMAL.to_le_bytes()
+                };
+
+                exif[offset..offset + 2].copy_from_slice(&bytes);
+            }
+        }
+    }
+
+    fn read_png(bytes: &[u8]) -> Self {
+        let mut metadata = Self::default();
+
+        for (kind, data) in png_chunks(bytes) {
+            match kind {
+                b"eXIf" => metadata.exif = Some(data.to_vec()),
+
+                // Pixels per unit horizontally, pixels per unit vertically, unit.
+                b"pHYs" => {
+                    if let [x_0, x_1, x_2, x_3, _, _, _, _, PNG_UNIT_METER] = *data {
+                        let density = f64::from(u32::from_be_bytes([x_0, x_1, x_2, x_3]));
+                        metadata.dpi = Some(dpi(density * METERS_PER_INCH));
+                    }
+                }
+
+                // Profile name, null separator, compression method, compressed profile.
+                b"iCCP" => {
+                    metadata.icc = split_null(data)
+                        .and_then(|(_name, rest)| rest.get(1..))
+                        .and_then(decompress)
+                }
+
+                // Keyword, null separator, compression flag, compression method, language tag,
+                // null separator, translated keyword, null separator, text.
+                b"iTXt" => {
+                    if let Some((PNG_XMP_KEYWORD, rest)) = split_null(data) {
+                        let compressed = rest.first() == Some(&1);
+
+                        metadata.xmp = rest
+                            .get(2..)
+                            .and_then(split_null)
+                            .and_then(|(_language, rest)| split_null(rest))
+                            .and_then(|(_translated, text)| {
+                                if compressed {
+                                    decompress(text)
+                                } else {
+                                    Some(text.to_vec())
+                                }
+                            });
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        metadata
+    }
+
+    fn write_png(&self, bytes: Vec<u8>) -> Vec<u8> {
+        if bytes.get(PNG_SIGNATURE.len() + 4..PNG_SIGNATURE.len() + 8) != Some(b"IHDR") {
+            warn!("the encoded PNG has no header, so its metadata has been dropped");
+            return bytes;
+        }
+
+        let mut chunks = Vec::new();
+        for (kind, data) in self.png_chunks() {
+            write_png_chunk(&mut chunks, &kind, &data);
+        }
+
+        // The metadata chunks are placed immediately after the header.
+        let (header, rest) = bytes.split_at(PNG_HEADER_LENGTH);
+
+        [header, &chunks, rest].concat()
+    }
+
+    /// The types and data of the PNG chunks holding the metadata, which must come before the
+    /// image data.
+    pub fn png_chunks(&self) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut chunks = Vec::new();
+
+        if let Some(icc) = &self.icc {
+            let data = [PNG_ICC_NAME, &[0, 0], &compress(icc)].concat();
+            chunks.push((*b"iCCP", data));
+        }
+        if let Some(exif) = &self.exif {
+            chunks.push((*b"eXIf", exif.clone()));
+        }
+        if let Some(xmp) = &self.xmp {
+            // Uncompressed, with no language tag or translated keyword.
+            let data = [PNG_XMP_KEYWORD, &[0, 0, 0, 0, 0], xmp].concat();
+            chunks.push((*b"iTXt", data));
+        }
+        if let Some(dpi) = self.dpi {
+            let pixels_per_meter = (f64::from(dpi) / METERS_PER_INCH).round() as u32;
+            let density = pixels_per_meter.to_be_bytes();
+            chunks.push((
+                *b"pHYs",
+                [&density[..], &density, &[PNG_UNIT_METER]].concat(),
+            ));
+        }
+
+        chunks
+    }
+
+    fn read_jpeg(bytes: &[u8]) -> Self {
+        let mut metadata = Self::default();
+        // The ICC profile may be split across multiple segments, each with its sequence number.
+        let mut icc_chunks = Vec::new();
+
+        for (marker, data) in jpeg_segments(bytes) {
+            match marker {
+                // Version, units, horizontal density, vertical density, thumbnail.
+                JPEG_APP0
+                    if let Some([_, _, units, x_high, x_low, ..]) =
+                        data.strip_prefix(JPEG_JFIF_HEADER) =>
+                {
+                    let density = f64::from(u16::from_be_bytes([*x_high, *x_low]));
+
+                    metadata.dpi = match *units {
+                        JFIF_UNIT_INCH => Some(dpi(density)),
+                        JFIF_UNIT_CENTIMETER => Some(dpi(density * 2.54)),
+
+                        _ => None,
+                    };
+                }
+                JPEG_APP1 if let Some(exif) = data.strip_prefix(JPEG_EXIF_HEADER) => {
+                    metadata.exif = Some(exif.to_vec())
+                }
+                JPEG_APP1 if let Some(xmp) = data.strip_prefix(JPEG_XMP_HEADER) => {
+                    metadata.xmp = Some(xmp.to_vec())
+                }
+
+                // Sequence number, number of chunks, profile chunk.
+                JPEG_APP2
+                    if let Some([sequence, _count, chunk @ ..]) =
+                        data.strip_prefix(JPEG_ICC_HEADER) =>
+                {
+                    icc_chunks.push((*sequence, chunk))
+                }
+
+                _ => (),
+            }
+        }
+
+        if !icc_chunks.is_empty() {
+            icc_chunks.sort_by_key(|(sequence, _)| *sequence);
+
+            metadata.icc = Some(
+                icc_chunks
+                    .into_iter()
+                    .flat_map(|(_, chunk)| chunk)
+                    .copied()
+                    .collect(),
+            );
+        }
+
+        metadata
+    }
+
+    fn write_jpeg(&self, mut bytes: Vec<u8>) -> Vec<u8> {
+        if !bytes.starts_with(&JPEG_SOI) {
+            warn!(
+                "the encoded JPEG has no start of image marker, so its metadata has been dropped"
+            );
+            return bytes;
+        }
+
+        let mut segments = Vec::new();
+
+        // The length of the JFIF header, which must come first, if there is one.
+        let jfif = match jpeg_segments(&bytes).next() {
+            Some((JPEG_APP0, data))
+                if data.starts_with(JPEG_JFIF_HEADER)
+                    && data.len() >= JPEG_JFIF_UNITS_OFFSET + 5 =>
+            {
+                Some(data.len())
+            }
+
+            _ => None,
+        };
+        if let Some(dpi) = self.dpi {
+            match u16::try_from(dpi) {
+                // The existing JFIF header's density is replaced.
+                Ok(dpi) if jfif.is_some() => {
+                    let units = JPEG_SOI.len() + 4 + JPEG_JFIF_UNITS_OFFSET;
+                    let density = dpi.to_be_bytes();
+                    bytes[units..units + 5].copy_from_slice(&[
+                        JFIF_UNIT_INCH,
+                        density[0],
+                        density[1],
+                        density[0],
+                        density[1],
+                    ]);
+                }
+                Ok(dpi) => {
+                    let density = dpi.to_be_bytes();
+                    let data = [
+                        JPEG_JFIF_HEADER,
+                        // Version 1.02, with no thumbnail.
+                        &[1, 2, JFIF_UNIT_INCH],
+                        &density,
+                        &density,
+                        &[0, 0],
+                    ]
+                    .concat();
+                    write_jpeg_segment(&mut segments, JPEG_APP0, &data);
+                }
+
+                Err(_) => warn!("{dpi} dpi is too high to be written to a JPEG image"),
+            }
+        }
+
+        if let Some(exif) = &self.exif {
+            write_jpeg_segment(&mut segments, JPEG_APP1, &[JPEG_EXIF_HEADER, exif].concat());
+        }
+        if let Some(xmp) = &self.xmp {
+            write_jpeg_segment(&mut segments, JPEG_APP1, &[JPEG_XMP_HEADER, xmp].concat());
+        }
+        if let Some(icc) = &self.icc {
+            let chunk_length = JPEG_MAX_SEGMENT_LENGTH - JPEG_ICC_HEADER.len() - 2;
+            let chunks: Vec<_> = icc.chunks(chunk_length).collect();
+
+            match u8::try_from(chunks.len()) {
+                Ok(count) => {
+                    for (sequence, chunk) in (1..=count).zip(chunks) {
+                        let data = [JPEG_ICC_HEADER, &[sequence, count], chunk].concat();
+                        write_jpeg_segment(&mut segments, JPEG_APP2, &data);
+                    }
+                }
+
+                Err(_) => warn!("the ICC profile is too large to be written to a JPEG image"),
+            }
+        }
+
+        // The metadata segments are placed after the JFIF header if there is one, or otherwise
+        // immediately after the start of image marker.
+        let insert_at = match jfif {
+            Some(length) => JPEG_SOI.len() + 4 + length,
+            None => JPEG_SOI.len(),
+        };
+        let (header, rest) = bytes.split_at(insert_at);
+
+        [header, &segments, rest].concat()
+    }
+}
+
+/// The given pixel density as a whole number of dots per inch.
+fn dpi(density: f64) -> u32 {
+    density.round() as u32
+}
+
+/// Finds the offset of the orientation value in the given `exif` data, along with whether the data
+/// is big-endian.
+fn exif_orientation_offset(exif: &[u8]) -> Option<(usize, bool)> {
+    // Byte order, the number 42, offset of the first image file directory (IFD).
+    let big_endian = match exif.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+
+        _ => return None,
+    };
+    let ifd_offset = read_u32(exif, 4, big_endian)? as usize;
+
+    // Number of entries, followed by the entries: tag, type, count, value.
+    let entries = read_u16(exif, ifd_offset, big_endian)?;
+
+    (0..usize::from(entries))
+        .map(|index| ifd_offset + 2 + index * 12)
+        .find(|&entry| read_u16(exif, entry, big_endian) == Some(EXIF_ORIENTATION_TAG))
+        .map(|entry| (entry + 8, big_endian))
+        .filter(|&(offset, _)| offset + 2 <= exif.len())
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?.try_into().ok()?;
+
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?.try_into().ok()?;
+
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// An iterator over the types and data of the chunks in a PNG image.
+///
+/// Stops at the first malformed chunk.
+fn png_chunks(bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = bytes.strip_prefix(PNG_SIGNATURE).unwrap_or_default();
+
+    iter::from_fn(move || {
+        // Length, chunk type, data, CRC.
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let kind = rest.get(4..8)?;
+        let data = rest.get(8..8 + length)?;
+
+        rest = rest.get(8 + length + 4..)?;
+
+        Some((kind, data))
+    })
+}
+
+/// Appends a PNG chunk of the given `kind` containing `data` to `bytes`.
+fn write_png_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// An iterator over the markers and data of the segments in a JPEG image before its image data.
+///
+/// Stops at the first malformed segment.
+fn jpeg_segments(bytes: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = bytes.strip_prefix(&JPEG_SOI).unwrap_or_default();
+
+    iter::from_fn(move || {
+        // Marker, length (including the length itself), data.
+        let [0xFF, marker, length_high, length_low, ..] = *rest else {
+            return None;
+        };
+        if matches!(marker, JPEG_SOS | JPEG_EOI) {
+            return None;
+        }
+
+        let length = usize::from(u16::from_be_bytes([length_high, length_low]));
+        let data = rest.get(4..2 + length)?;
+
+        rest = rest.get(2 + length..)?;
+
+        Some((marker, data))
+    })
+}
+
+/// Appends a JPEG segment with the given `marker` containing `data` to `bytes`.
+///
+/// If `data` is too long to fit in a segment, a warning is logged and nothing is written.
+fn write_jpeg_segment(bytes: &mut Vec<u8>, marker: u8, data: &[u8]) {
+    if data.len() > JPEG_MAX_SEGMENT_LENGTH {
+        warn!("metadata segment is too large to be written to a JPEG image");
+        return;
+    }
+
+    bytes.extend_from_slice(&[0xFF, marker]);
+    bytes.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+    bytes.extend_from_slice(data);
+}
+
+/// Splits the given `bytes` at the first null byte, excluding it.
+fn split_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let position = bytes.iter().position(|&byte| byte == 0)?;
+
+    Some((&bytes[..position], &bytes[position + 1..]))
+}
+
+fn decompress(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(bytes)
+        .read_to_end(&mut decompressed)
+        .ok()?;
+
+    Some(decompressed)
+}
+
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+    // Writing to a `Vec` cannot fail.
+    encoder
+        .write_all(bytes)
+        .expect("writing to a `Vec` cannot fail");
+    encoder.finish().expect("writing to a `Vec` cannot fail")
+}
@@ -0,0 +1,180 @@
+use crate::cli::{Color, Gravity, MontageArgs, Size, ThumbnailQuality};
+use crate::error::InvalidArguments;
+use crate::metadata::Metadata;
+use crate::{file, font, thumbnail};
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use log::{debug, info};
+use std::borrow::Cow;
+use std::error::Error;
+
+/// The space above and below each label, in pixels.
+const LABEL_PADDING: u32 = 2;
+
+/// Lays out the images given in the `montage_args` on a contact sheet.
+pub fn run(montage_args: &MontageArgs) -> Result<(), Box<dyn Error>> {
+    let MontageArgs {
+        input_paths,
+        output_path,
+        columns,
+        cell,
+        spacing,
+        background,
+        labels,
+        label_color,
+        encode_args,
+        limit_args,
+    } = montage_args;
+
+    let mut images = Vec::with_capacity(input_paths.len());
+    for input_path in input_paths {
+        let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+        let (image, _) = input.decode()?;
+
+        images.push(image);
+    }
+
+    let cell = match cell {
+        Some(Size(width, height)) => (width.get(), height.get()),
+        None => images.iter().fold((0, 0), |(width, height), image| {
+            (width.max(image.width()), height.max(image.height()))
+        }),
+    };
+    let columns = columns.map_or_else(
+        || (images.len() as f64).sqrt().ceil() as u32,
+        |columns| columns.get().min(images.len() as u32),
+    );
+
+    let dimensions = dimensions(images.len(), columns, cell, *spacing, *labels)?;
+    crate::check_output_dimensions(dimensions, limit_args)?;
+
+    let labels: Option<Vec<_>> = labels.then(|| {
+        input_paths
+            .iter()
+            .map(|input_path| {
+                input_path.file_name().map_or_else(
+                    || input_path.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                )
+            })
+            .collect()
+    });
+
+    debug!("laying out {} images in {columns} columns", images.len());
+    let new_image = montage(
+        &images,
+        dimensions,
+        columns,
+        cell,
+        *spacing,
+        *background,
+        labels.as_deref().map(|labels| (labels, *label_color)),
+    );
+
+    info!("writing {}", output_path.display());
+    file::write(
+        &new_image.into(),
+        &Metadata::default(),
+        output_path,
+        encode_args,
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// The height of each cell's label, or 0 if cells are not `labelled`.
+fn label_height(labelled: bool) -> u32 {
+    if labelled {
+        font::GLYPH_HEIGHT + 2 * LABEL_PADDING
+    } else {
+        0
+    }
+}
+
+/// The dimensions of a contact sheet of `count` images, in the given number of `columns` of
+/// cells with the given dimensions, with `spacing` around and between them.
+///
+/// Errors if the contact sheet would be too large for any image.
+fn dimensions(
+    count: usize,
+    columns: u32,
+    (cell_width, cell_height): (u32, u32),
+    spacing: u32,
+    labelled: bool,
+) -> Result<(u32, u32), InvalidArguments> {
+    let rows = (count as u64).div_ceil(u64::from(columns));
+    let cell_height = u64::from(cell_height) + u64::from(label_height(labelled));
+
+    // Each cell is followed by spacing, as is the edge before the first.
+    let length = |count: u64, cell: u64| count * (cell + u64::from(spacing)) + u64::from(spacing);
+    let (width, height) = (
+        length(u64::from(columns), u64::from(cell_width)),
+        length(rows, cell_height),
+    );
+
+    match (u32::try_from(width), u32::try_from(height)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+
+        _ => Err(InvalidArguments(format!(
+            "the contact sheet would be {width}x{height}, which is too large for any image"
+        ))),
+    }
+}
+
+/// Lays out the given `images` on a contact sheet of the given dimensions, filled with the given
+/// `background` color.
+///
+/// Each image is centered in its cell in the grid, shrunk to fit it if it is larger. If `labels`
+/// are given, each cell is labelled by its own in the given color.
+pub fn montage(
+    images: &[DynamicImage],
+    dimensions: (u32, u32),
+    columns: u32,
+    (cell_width, cell_height): (u32, u32),
+    spacing: u32,
+    Color(background): Color,
+    labels: Option<(&[String], Color)>,
+) -> RgbaImage {
+    let (width, height) = dimensions;
+    let mut new_image = RgbaImage::from_pixel(width, height, Rgba(background));
+    let label_height = label_height(labels.is_some());
+
+    for (index, image) in (0..).zip(images) {
+        let (column, row) = (index % columns, index / columns);
+        let x = spacing + column * (cell_width + spacing);
+        let y = spacing + row * (cell_height + label_height + spacing);
+
+        let image = if image.width() > cell_width || image.height() > cell_height {
+            Cow::Owned(thumbnail::thumbnail(
+                image,
+                (cell_width, cell_height),
+                None,
+                ThumbnailQuality::High,
+            ))
+        } else {
+            Cow::Borrowed(image)
+        };
+        let (offset_x, offset_y) =
+            Gravity::Center.position((cell_width, cell_height), image.dimensions());
+        imageops::overlay(
+            &mut new_image,
+            &image.to_rgba8(),
+            i64::from(x) + offset_x,
+            i64::from(y) + offset_y,
+        );
+
+        if let Some((labels, Color(color))) = labels {
+            let label = font::truncate(&labels[index as usize], cell_width);
+            let label_x = x + (cell_width - font::width(label)) / 2;
+
+            font::draw(
+                &mut new_image,
+                label,
+                (label_x, y + cell_height + LABEL_PADDING),
+                Rgba(color),
+            );
+        }
+    }
+
+    new_image
+}
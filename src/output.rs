@@ -0,0 +1,57 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::{Mutex, PoisonError};
+
+/// Where a command prints its results, such as JSON reports and comparison scores.
+///
+/// These are printed to stdout from the command line, but captured to be sent back with the reply
+/// to a command run by the daemon.
+#[derive(Debug)]
+pub enum Output {
+    /// Printed to stdout.
+    Stdout,
+    /// Captured in a buffer.
+    Captured(Mutex<Vec<u8>>),
+}
+
+impl Output {
+    /// An output captured in a buffer, to be taken with [`Output::into_captured`].
+    pub fn captured() -> Self {
+        Self::Captured(Mutex::default())
+    }
+
+    /// Prints the given `line`, which is kept whole even when lines are printed from several
+    /// threads at once.
+    pub fn println(&self, line: impl Display) {
+        match self {
+            Self::Stdout => println!("{line}"),
+            Self::Captured(buffer) => {
+                let mut buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+                // Writing to a `Vec` cannot fail.
+                writeln!(buffer, "{line}").expect("buffers are writable");
+            }
+        }
+    }
+
+    /// Prints whatever the given `write` function writes to the writer it is given, for printing
+    /// with functions that take a writer.
+    pub fn write<T>(&self, write: impl FnOnce(&mut dyn Write) -> T) -> T {
+        match self {
+            Self::Stdout => write(&mut io::stdout().lock()),
+            Self::Captured(buffer) => {
+                write(&mut *buffer.lock().unwrap_or_else(PoisonError::into_inner))
+            }
+        }
+    }
+
+    /// What has been captured, which is nothing for stdout.
+    pub fn into_captured(self) -> String {
+        match self {
+            Self::Stdout => String::new(),
+            Self::Captured(buffer) => {
+                let buffer = buffer.into_inner().unwrap_or_else(PoisonError::into_inner);
+                String::from_utf8_lossy(&buffer).into_owned()
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use crate::cli::{Alpha, Color, LimitArgs, Ratio, RunArgs, Sigma, Size};
+use crate::error::InvalidArguments;
+use crate::tiling::Layout;
+use crate::{border, file, scale};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use indicatif::ProgressBar;
+use log::{debug, info};
+use std::error::Error;
+use std::fmt::Display;
+use std::num::NonZeroU32;
+
+/// An operation in the pipeline given to `run`, or to a job in a `--job` file.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Step {
+    /// Crops the image to the given dimensions, from the given position of its top left corner.
+    Crop {
+        dimensions: (u32, u32),
+        position: (u32, u32),
+    },
+    /// Resamples the image by the given factor.
+    ScaleBy(f64),
+    /// Resamples the image to the given dimensions.
+    ScaleTo((u32, u32)),
+    /// Repeats the image across the given dimensions.
+    Tile((u32, u32)),
+    /// Blurs the image with the given standard deviation.
+    Blur(f32),
+    /// Draws a border of the given thickness and color over the edges of the image.
+    Border { thickness: u32, color: Color },
+    /// Rotates the image clockwise by the given number of quarter turns.
+    Rotate(u8),
+}
+
+/// Applies the pipeline of operations given in the `run_args` to the image, in order.
+///
+/// Every operation is parsed before the image is read, and the image is only encoded once the
+/// last of them has been applied.
+pub fn run(run_args: &RunArgs) -> Result<(), Box<dyn Error>> {
+    let RunArgs {
+        input_path,
+        output_path,
+        operations,
+        no_auto_orient,
+        encode_args,
+        limit_args,
+    } = run_args;
+
+    let steps = operations
+        .split(|word| word == "--")
+        .filter(|words| !words.is_empty())
+        .map(parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let input =
+        file::Input::read(input_path, !no_auto_orient)?.limit_pixels(limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    let (mut image, metadata) = input.decode()?;
+
+    for step in &steps {
+        debug!("{}: applying {step:?}", input_path.display());
+        image = apply(&image, *step, limit_args)?;
+    }
+
+    info!("writing {}", new_path.display());
+    file::write(&image, &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
+
+/// Parses an operation from its name followed by its arguments.
+pub fn parse(words: &[String]) -> Result<Step, InvalidArguments> {
+    let invalid = |reason: &dyn Display| {
+        InvalidArguments(format!("invalid operation `{}`: {reason}", words.join(" ")))
+    };
+    let dimensions = |size: &str| {
+        size.parse::<Size<NonZeroU32>>()
+            .map(|Size(width, height)| (width.get(), height.get()))
+            .map_err(|error| invalid(&error))
+    };
+
+    let step = match words {
+        [name, geometry] if name == "crop" => {
+            let (size, position) = geometry
+                .split_once('+')
+                .ok_or_else(|| invalid(&"expected WIDTHxHEIGHT+X+Y"))?;
+            let (x, y) = position
+                .split_once('+')
+                .ok_or_else(|| invalid(&"expected WIDTHxHEIGHT+X+Y"))?;
+            let offset = |offset: &str| {
+                offset
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|error| invalid(&error))
+            };
+
+            Step::Crop {
+                dimensions: dimensions(size)?,
+                position: (offset(x)?, offset(y)?),
+            }
+        }
+
+        [name, size] if name == "scale" => {
+            let ratio = |ratio: &str| {
+                ratio
+                    .parse::<Ratio>()
+                    .map(|Ratio(ratio)| ratio)
+                    .map_err(|error| invalid(&error))
+            };
+            let factor = match (size.strip_suffix(['x', 'X']), size.strip_suffix('%')) {
+                (Some(factor), _) => Some(ratio(factor)?),
+                (_, Some(percent)) => Some(ratio(percent)? / 100.0),
+
+                (None, None) => None,
+            };
+
+            match factor {
+                Some(factor) if factor > 0.0 => Step::ScaleBy(factor),
+                Some(_) => return Err(invalid(&"the factor must be positive")),
+
+                None => Step::ScaleTo(dimensions(size)?),
+            }
+        }
+
+        [name, size] if name == "tile" => Step::Tile(dimensions(size)?),
+
+        [name, sigma] if name == "blur" => {
+            let Sigma(sigma) = sigma.parse().map_err(|error: String| invalid(&error))?;
+
+            Step::Blur(sigma)
+        }
+
+        [name, thickness, color @ ..] if name == "border" && color.len() <= 1 => {
+            let thickness = thickness
+                .parse::<NonZeroU32>()
+                .map_err(|error| invalid(&error))?;
+            let color = match color.first() {
+                Some(color) => color.parse().map_err(|error: String| invalid(&error))?,
+                None => Color([0, 0, 0, u8::MAX]),
+            };
+
+            Step::Border {
+                thickness: thickness.get(),
+                color,
+            }
+        }
+
+        [name, degrees] if name == "rotate" => match degrees.as_str() {
+            "90" => Step::Rotate(1),
+            "180" => Step::Rotate(2),
+            "270" => Step::Rotate(3),
+
+            _ => return Err(invalid(&"only 90, 180, or 270 degrees are supported")),
+        },
+
+        _ => return Err(invalid(
+            &"expected `crop`, `scale`, `tile`, `blur`, `border`, or `rotate` and its arguments",
+        )),
+    };
+
+    Ok(step)
+}
+
+/// Applies the given `step` to the `image`.
+///
+/// Errors if a crop falls outside the image, or if the result would be larger than allowed by the
+/// given `limit_args`.
+pub fn apply(
+    image: &DynamicImage,
+    step: Step,
+    limit_args: &LimitArgs,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    let (width, height) = image.dimensions();
+
+    let new_image = match step {
+        Step::Crop {
+            dimensions: (crop_width, crop_height),
+            position: (x, y),
+        } => {
+            let fits = |offset: u32, length: u32, image_length: u32| {
+                offset
+                    .checked_add(length)
+                    .is_some_and(|end| end <= image_length)
+            };
+            if !(fits(x, crop_width, width) && fits(y, crop_height, height)) {
+                return Err(InvalidArguments(format!(
+                    "the crop {crop_width}x{crop_height}+{x}+{y} falls outside the {width}x{height} image"
+                ))
+                .into());
+            }
+
+            image.crop_imm(x, y, crop_width, crop_height)
+        }
+
+        Step::ScaleBy(factor) => {
+            let scaled = |length: u32| (f64::from(length) * factor).round().max(1.0);
+            let dimensions = crate::whole_dimensions(scaled(width), scaled(height))?;
+            crate::check_output_dimensions(dimensions, limit_args)?;
+
+            scale::scale(image, dimensions, FilterType::Lanczos3, true)
+        }
+        Step::ScaleTo(dimensions) => {
+            crate::check_output_dimensions(dimensions, limit_args)?;
+
+            scale::scale(image, dimensions, FilterType::Lanczos3, true)
+        }
+
+        Step::Tile(dimensions) => {
+            crate::check_output_dimensions(dimensions, limit_args)?;
+
+            crate::repeated(
+                image,
+                dimensions,
+                Alpha::Blend,
+                &Layout::default(),
+                &ProgressBar::hidden(),
+            )
+        }
+
+        Step::Blur(sigma) => image.blur(sigma),
+
+        Step::Border { thickness, color } => border::bordered(image, thickness, color, None, false),
+
+        Step::Rotate(1) => image.rotate90(),
+        Step::Rotate(2) => image.rotate180(),
+        Step::Rotate(_) => image.rotate270(),
+    };
+
+    Ok(new_image)
+}
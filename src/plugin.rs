@@ -0,0 +1,153 @@
+//! Operations loaded from WebAssembly plugins.
+//!
+//! A plugin is a WebAssembly module that exports:
+//!
+//! - `memory`, its linear memory.
+//! - `alloc(length: i32) -> i32`, which reserves `length` bytes of its memory and returns where
+//!   they start.
+//! - `apply(pixels: i32, width: i32, height: i32) -> i32`, which changes the 8-bit RGBA pixels of
+//!   an image of the given dimensions at `pixels`, row by row, in place. It returns 0 on success,
+//!   and anything else to fail.
+//!
+//! Plugins cannot import anything, so they can only change the pixels they are given.
+
+use crate::cli::ApplyArgs;
+use crate::error::FileError;
+use crate::file;
+use image::RgbaImage;
+use log::{debug, info};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmi::{Engine, Instance, Linker, Memory, Module, Store};
+
+/// An operation applied to an image.
+pub trait Operation {
+    /// The name of the operation, used in messages.
+    fn name(&self) -> String;
+
+    /// Applies the operation to the given `image`.
+    fn apply(&mut self, image: &mut RgbaImage) -> Result<(), Box<dyn Error>>;
+}
+
+/// An [operation](Operation) exported by a WebAssembly plugin.
+pub struct Plugin {
+    path: PathBuf,
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Plugin {
+    /// Loads the plugin in the WebAssembly module at `path`, in its binary or text format.
+    ///
+    /// Errors if the module is invalid, imports anything, or does not export `memory`.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|source| FileError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let error = |error: wasmi::Error| format!("plugin {}: {error}", path.display());
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, bytes).map_err(error)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine)
+            .instantiate_and_start(&mut store, &module)
+            .map_err(error)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| format!("plugin {} does not export its memory", path.display()))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            store,
+            instance,
+            memory,
+        })
+    }
+}
+
+impl Plugin {
+    /// An error from this plugin with the given `message`.
+    fn error(&self, message: impl Display) -> String {
+        format!("plugin {}: {message}", self.path.display())
+    }
+}
+
+impl Operation for Plugin {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn apply(&mut self, image: &mut RgbaImage) -> Result<(), Box<dyn Error>> {
+        let (width, height) = image.dimensions();
+        // Both dimensions fit in an `i32` if the number of bytes does.
+        let length = i32::try_from(image.len())
+            .map_err(|_| self.error(format!("{width}x{height} images are too large")))?;
+
+        let alloc = self
+            .instance
+            .get_typed_func::<i32, i32>(&self.store, "alloc")
+            .map_err(|error| self.error(error))?;
+        let apply = self
+            .instance
+            .get_typed_func::<(i32, i32, i32), i32>(&self.store, "apply")
+            .map_err(|error| self.error(error))?;
+
+        let pixels = alloc
+            .call(&mut self.store, length)
+            .map_err(|error| self.error(error))?;
+        let offset =
+            usize::try_from(pixels).map_err(|_| self.error("alloc returned a negative pointer"))?;
+        self.memory
+            .write(&mut self.store, offset, image)
+            .map_err(|error| self.error(error))?;
+
+        let code = apply
+            .call(&mut self.store, (pixels, width as i32, height as i32))
+            .map_err(|error| self.error(error))?;
+        if code != 0 {
+            return Err(self.error(format!("failed with code {code}")).into());
+        }
+
+        self.memory
+            .read(&self.store, offset, image)
+            .map_err(|error| self.error(error))?;
+
+        Ok(())
+    }
+}
+
+/// Applies the operations of the plugins given in the `apply_args` to the image, in order.
+pub fn run(apply_args: &ApplyArgs) -> Result<(), Box<dyn Error>> {
+    let ApplyArgs {
+        input_path,
+        output_path,
+        plugin_paths,
+        encode_args,
+        limit_args,
+    } = apply_args;
+
+    let mut operations = plugin_paths
+        .iter()
+        .map(|path| Plugin::load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    let (image, metadata) = input.decode()?;
+    let mut new_image = image.into_rgba8();
+
+    for operation in &mut operations {
+        debug!("{}: applying {}", input_path.display(), operation.name());
+        operation.apply(&mut new_image)?;
+    }
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image.into(), &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use crate::cli::{self, PresetAction};
+use crate::config;
+use crate::error::InvalidArguments;
+use crate::output::Output;
+use clap::CommandFactory;
+use log::info;
+use std::error::Error;
+
+/// Runs the given preset `action`, printing any presets listed to `output`.
+pub fn run(action: &PresetAction, output: &Output) -> Result<(), Box<dyn Error>> {
+    match action {
+        PresetAction::Save { name, options } => save(name, options),
+        PresetAction::List => list(output),
+    }
+}
+
+/// Saves the given command line `options` in the configuration file as the preset `name`.
+fn save(name: &str, options: &[String]) -> Result<(), Box<dyn Error>> {
+    let command = cli::Args::command();
+    let mut preset = toml::Table::new();
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let Some(option) = option.strip_prefix("--") else {
+            return Err(InvalidArguments(format!(
+                "{option} is not an option - options must be given by their long names"
+            ))
+            .into());
+        };
+        // Values may be given after an `=` or as the next argument.
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_owned())),
+            None => (option, None),
+        };
+
+        let arg = config::find_option(&command, key)
+            .ok_or_else(|| InvalidArguments(format!("unknown option: --{key}")))?;
+        if key == "preset" {
+            return Err(InvalidArguments("presets cannot include other presets".into()).into());
+        }
+
+        let value = if arg.get_action().takes_values() {
+            let value = value
+                .or_else(|| options.next().cloned())
+                .ok_or_else(|| InvalidArguments(format!("--{key} requires a value")))?;
+
+            toml::Value::String(value)
+        } else {
+            toml::Value::Boolean(value.map_or(Ok(true), |value| value.parse())?)
+        };
+
+        preset.insert(key.to_owned(), value);
+    }
+
+    let mut config = config::read()?;
+    let mut presets = config::presets(&config)?;
+    presets.insert(name.to_owned(), preset.into());
+    config.insert(config::PRESETS.to_owned(), presets.into());
+
+    config::write(&config)?;
+    info!("saved preset {name}");
+
+    Ok(())
+}
+
+/// Prints the name and options of each preset in the configuration file to `output`.
+fn list(output: &Output) -> Result<(), Box<dyn Error>> {
+    for (name, options) in config::presets(&config::read()?)? {
+        let options: Vec<_> = options
+            .as_table()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| match value {
+                toml::Value::Boolean(true) => format!("--{key}"),
+                toml::Value::String(value) => format!("--{key} {value}"),
+
+                value => format!("--{key} {value}"),
+            })
+            .collect();
+
+        output.println(format_args!("{name}: {}", options.join(" ")));
+    }
+
+    Ok(())
+}
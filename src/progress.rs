@@ -0,0 +1,40 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The template used for every progress bar.
+const TEMPLATE: &str = "{bar:40} {pos}/{len} {msg} [{elapsed_precise}]";
+
+/// Whether every progress bar is hidden, as with `--batch`.
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Hides every progress bar created from now on, even those that would otherwise be visible.
+pub fn hide() {
+    HIDDEN.store(true, Ordering::Relaxed);
+}
+
+/// Creates a progress bar counting through `length` images, or a hidden one if `!visible`.
+pub fn images(length: usize, visible: bool) -> ProgressBar {
+    let bar = bar(visible).with_message("images");
+    bar.set_length(length as u64);
+
+    bar
+}
+
+/// Creates a progress bar counting overlaid tiles, or a hidden one if `!visible`.
+///
+/// Its length is set once the number of tiles is known; see [`repeat`].
+///
+/// [`repeat`]: crate::repeat
+pub fn tiles(visible: bool) -> ProgressBar {
+    bar(visible).with_message("tiles")
+}
+
+fn bar(visible: bool) -> ProgressBar {
+    if visible && !HIDDEN.load(Ordering::Relaxed) {
+        ProgressBar::new(0).with_style(
+            ProgressStyle::with_template(TEMPLATE).expect("the progress bar template is valid"),
+        )
+    } else {
+        ProgressBar::hidden()
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cli::Dither;
+use color_quant::NeuQuant;
+use image::{Rgba, RgbaImage};
+
+/// How many pixels NeuQuant skips between those it learns the palette from, from 1 to 30 - 10 is
+/// its recommended balance between speed and quality.
+const SAMPLE_FACTOR: i32 = 10;
+
+/// The 8x8 Bayer threshold matrix used for ordered dithering, of values from 0 to 63.
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reduces the given `image` to a palette of `colors` colors, returning the palette along with
+/// the index of each pixel's color in it.
+///
+/// The palette is learnt with NeuQuant, and each pixel is mapped to a color in it according to
+/// the given `dither`.
+pub fn quantize(image: &RgbaImage, colors: usize, dither: Dither) -> (Vec<[u8; 4]>, Vec<u8>) {
+    debug_assert!(colors <= usize::from(u8::MAX) + 1);
+    let quantizer = NeuQuant::new(SAMPLE_FACTOR, colors, image.as_raw());
+
+    let palette: Vec<[u8; 4]> = quantizer
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect();
+    let index_of = |color: &[u8; 4]| quantizer.index_of(color) as u8;
+
+    let indices = match dither {
+        Dither::None => image.pixels().map(|Rgba(color)| index_of(color)).collect(),
+        Dither::Ordered => ordered(image, colors, index_of),
+        Dither::FloydSteinberg => floyd_steinberg(image, &palette, index_of),
+    };
+
+    (palette, indices)
+}
+
+/// Maps each pixel of the given `image` with `index_of` after offsetting its color by the
+/// [Bayer matrix](BAYER), by up to about the distance between colors in a palette of `colors`
+/// colors.
+fn ordered(image: &RgbaImage, colors: usize, index_of: impl Fn(&[u8; 4]) -> u8) -> Vec<u8> {
+    // Spread evenly, the palette would have the cube root of its colors along each channel.
+    let spread = 256.0 / (colors as f32).cbrt();
+
+    image
+        .enumerate_pixels()
+        .map(|(x, y, Rgba([red, green, blue, alpha]))| {
+            let threshold = f32::from(BAYER[y as usize % 8][x as usize % 8]) / 64.0 - 0.5;
+            let offset = |channel: u8| (f32::from(channel) + threshold * spread).round() as u8;
+
+            index_of(&[offset(*red), offset(*green), offset(*blue), *alpha])
+        })
+        .collect()
+}
+
+/// Maps each pixel of the given `image` with `index_of` into the given `palette`, spreading the
+/// difference between its color and the one it is mapped to onto the pixels to its right and
+/// below.
+fn floyd_steinberg(
+    image: &RgbaImage,
+    palette: &[[u8; 4]],
+    index_of: impl Fn(&[u8; 4]) -> u8,
+) -> Vec<u8> {
+    let width = image.width() as usize;
+    let mut indices = Vec::with_capacity(image.pixels().len());
+
+    // The error spread onto each pixel of the current row and the next, with a pixel of padding
+    // either side so that neighbors past the edges can be written to.
+    let mut errors = vec![[0.0_f32; 4]; width + 2];
+    let mut next_errors = vec![[0.0_f32; 4]; width + 2];
+
+    for row in image.rows() {
+        for (x, Rgba(color)) in row.enumerate() {
+            let mut wanted = [0.0; 4];
+            let mut actual = [0; 4];
+            for channel in 0..4 {
+                wanted[channel] = f32::from(color[channel]) + errors[x + 1][channel];
+                actual[channel] = wanted[channel].round() as u8;
+            }
+
+            let index = index_of(&actual);
+            indices.push(index);
+
+            let mapped = palette[usize::from(index)];
+            for channel in 0..4 {
+                let error = wanted[channel] - f32::from(mapped[channel]);
+
+                errors[x + 2][channel] += error * 7.0 / 16.0;
+                next_errors[x][channel] += error * 3.0 / 16.0;
+                next_errors[x + 1][channel] += error * 5.0 / 16.0;
+                next_errors[x + 2][channel] += error / 16.0;
+            }
+        }
+
+        errors = std::mem::replace(&mut next_errors, vec![[0.0; 4]; width + 2]);
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A gradient through every channel, with far more colors than any palette.
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([
+                (x * 4) as u8,
+                (y * 4) as u8,
+                ((x + y) * 2) as u8,
+                255 - (x * 2) as u8,
+            ])
+        })
+    }
+
+    #[test]
+    fn dithering_only_uses_palettes_of_at_most_the_colors_given() {
+        let image = gradient();
+
+        for dither in [Dither::None, Dither::Ordered, Dither::FloydSteinberg] {
+            for colors in [2, 16, 256] {
+                let (palette, indices) = quantize(&image, colors, dither);
+
+                assert!(palette.len() <= colors, "{dither:?} with {colors} colors");
+                assert_eq!(indices.len(), image.pixels().len());
+                assert!(
+                    indices
+                        .iter()
+                        .all(|&index| usize::from(index) < palette.len()),
+                    "{dither:?} with {colors} colors"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use image::ImageFormat;
+use serde::Serialize;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+/// The outcome of resizing a single image, printed as JSON by `--json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    /// The path of the input image.
+    pub input: String,
+    /// The path the output image was (or would have been) written to.
+    pub output: Option<String>,
+    /// The format of the output image.
+    pub format: Option<String>,
+
+    /// The dimensions of the input image.
+    pub old_dimensions: Option<Dimensions>,
+    /// The dimensions of the output image.
+    pub new_dimensions: Option<Dimensions>,
+    /// The number of horizontal and vertical repetitions of the input in the output.
+    pub tiles: Option<Dimensions>,
+
+    /// How long resizing the image took, in seconds.
+    pub elapsed: f64,
+
+    pub status: Status,
+    /// The error that caused the resize to fail, if `status` is [`Status::Failed`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// The output image was written.
+    Resized,
+    /// Nothing was written, such as for `--dry-run`.
+    Skipped,
+    /// Resizing the image failed.
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Report {
+    /// Creates a report for the given `input` image with only its `status` known so far.
+    pub fn new(input: &Path, status: Status) -> Self {
+        Self {
+            input: input.display().to_string(),
+            output: None,
+            format: None,
+
+            old_dimensions: None,
+            new_dimensions: None,
+            tiles: None,
+
+            elapsed: 0.0,
+
+            status,
+            error: None,
+        }
+    }
+
+    /// Creates a report for the given `input` image that failed to resize with the given `error`.
+    pub fn failed(input: &Path, error: &dyn Error) -> Self {
+        Self {
+            error: Some(error.to_string()),
+
+            ..Self::new(input, Status::Failed)
+        }
+    }
+
+    /// Sets the `output` path, also determining the output [format] from its extension.
+    ///
+    /// [format]: Self::format
+    pub fn with_output(self, output: &Path) -> Self {
+        Self {
+            output: Some(output.display().to_string()),
+            format: ImageFormat::from_path(output)
+                .ok()
+                .map(|format| format!("{format:?}").to_lowercase()),
+
+            ..self
+        }
+    }
+
+    pub fn with_elapsed(self, elapsed: Duration) -> Self {
+        Self {
+            elapsed: elapsed.as_secs_f64(),
+
+            ..self
+        }
+    }
+}
+
+impl From<(u32, u32)> for Dimensions {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self { width, height }
+    }
+}
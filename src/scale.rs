@@ -0,0 +1,223 @@
+use crate::cli::{Filter, ScaleArgs, Size};
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::error::InvalidArguments;
+use crate::file;
+use image::imageops::{self, FilterType};
+use image::{ColorType, DynamicImage, GenericImageView, Rgba, Rgba32FImage};
+use log::{debug, info};
+use std::error::Error;
+use std::num::NonZeroU32;
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => Self::Nearest,
+            Filter::Triangle => Self::Triangle,
+            Filter::CatmullRom => Self::CatmullRom,
+            Filter::Gaussian => Self::Gaussian,
+            Filter::Lanczos3 => Self::Lanczos3,
+        }
+    }
+}
+
+/// Scales the image according to the given `scale_args`.
+pub fn run(scale_args: &ScaleArgs) -> Result<(), Box<dyn Error>> {
+    let ScaleArgs {
+        input_path,
+        output_path,
+        size: Size(width, height),
+        filter,
+        no_linear,
+        supersample,
+        ..
+    } = scale_args;
+    let (width, height) = (width.get(), height.get());
+
+    crate::check_output_dimensions((width, height), &scale_args.limit_args)?;
+    let factor = supersample.map_or(1, NonZeroU32::get);
+    let supersampled =
+        supersampled_dimensions((width, height), factor, scale_args.limit_args.max_pixels)?;
+
+    let input =
+        file::Input::read(input_path, true)?.limit_pixels(scale_args.limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    // Rasterizing at the new dimensions keeps vector images sharp, rather than resampling them.
+    #[cfg(feature = "svg")]
+    if input.is_svg() {
+        let (new_image, metadata) = input.rasterize(supersampled)?;
+        let new_image = downsample(new_image, factor, !no_linear);
+
+        info!("writing {}", new_path.display());
+        file::write(
+            &new_image,
+            &metadata,
+            new_path,
+            &scale_args.encode_args,
+            false,
+        )?;
+
+        return Ok(());
+    }
+
+    let indexed = input.indexed;
+    let (image, metadata) = input.decode()?;
+
+    debug!(
+        "{}: scaling from {:?} to {:?}",
+        input_path.display(),
+        image.dimensions(),
+        (width, height),
+    );
+    let new_image = scale(&image, supersampled, (*filter).into(), !no_linear);
+    let new_image = downsample(new_image, factor, !no_linear);
+
+    info!("writing {}", new_path.display());
+    file::write(
+        &new_image,
+        &metadata,
+        new_path,
+        &scale_args.encode_args,
+        indexed,
+    )?;
+
+    Ok(())
+}
+
+/// Resamples the given `image` to the given dimensions using the given `filter`, keeping its
+/// sample format.
+///
+/// If `linear` is true, the image is resampled in linear light with premultiplied alpha, rather
+/// than the gamma-encoded values being resampled directly. Averaging gamma-encoded values gives
+/// too much weight to dark values, darkening fine detail.
+pub fn scale(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    filter: FilterType,
+    linear: bool,
+) -> DynamicImage {
+    if !linear {
+        return image.resize_exact(width, height, filter);
+    }
+
+    let mut resized = imageops::resize(&linearized(image), width, height, filter);
+    delinearize(&mut resized);
+
+    with_color(resized.into(), image.color())
+}
+
+/// Shrinks the given `image` by the given `factor`, averaging each `factor` by `factor` block of
+/// its pixels into one, and keeping its sample format.
+///
+/// As with [`scale`], the blocks are averaged in linear light with premultiplied alpha if `linear`
+/// is true. The `image`'s dimensions must be multiples of `factor`.
+fn downsample(image: DynamicImage, factor: u32, linear: bool) -> DynamicImage {
+    if factor == 1 {
+        return image;
+    }
+
+    let (width, height) = image.dimensions();
+    debug_assert!(width % factor == 0 && height % factor == 0);
+
+    let samples = if linear {
+        linearized(&image)
+    } else {
+        image.to_rgba32f()
+    };
+    let area = (factor * factor) as f32;
+
+    let mut downsampled = Rgba32FImage::from_fn(width / factor, height / factor, |x, y| {
+        let mut sum = [0.0; 4];
+
+        for block_y in y * factor..(y + 1) * factor {
+            for block_x in x * factor..(x + 1) * factor {
+                let Rgba(pixel) = samples.get_pixel(block_x, block_y);
+                for (sum, sample) in sum.iter_mut().zip(pixel) {
+                    *sum += sample;
+                }
+            }
+        }
+
+        Rgba(sum.map(|sum| sum / area))
+    });
+    if linear {
+        delinearize(&mut downsampled);
+    }
+
+    with_color(downsampled.into(), image.color())
+}
+
+/// The dimensions to resample to before [downsampling] by `factor` to the given dimensions.
+///
+/// Errors if an image with those dimensions would have more than `max_pixels` pixels.
+///
+/// [downsampling]: downsample
+fn supersampled_dimensions(
+    (width, height): (u32, u32),
+    factor: u32,
+    max_pixels: u64,
+) -> Result<(u32, u32), InvalidArguments> {
+    let (supersampled_width, supersampled_height) = (
+        u64::from(width) * u64::from(factor),
+        u64::from(height) * u64::from(factor),
+    );
+    let pixels = supersampled_width.checked_mul(supersampled_height);
+
+    match (u32::try_from(supersampled_width), u32::try_from(supersampled_height)) {
+        (Ok(supersampled_width), Ok(supersampled_height))
+            if pixels.is_some_and(|pixels| pixels <= max_pixels) =>
+        {
+            Ok((supersampled_width, supersampled_height))
+        }
+
+        _ => Err(InvalidArguments(format!(
+            "supersampling by {factor} would resample to {supersampled_width}x{supersampled_height}, which is more than the maximum of {max_pixels} pixels (see --max-pixels)"
+        ))),
+    }
+}
+
+/// Converts the given `image` to 32-bit floating point RGBA in linear light with premultiplied
+/// alpha.
+fn linearized(image: &DynamicImage) -> Rgba32FImage {
+    let mut linear_image = image.to_rgba32f();
+    for Rgba([red, green, blue, alpha]) in linear_image.pixels_mut() {
+        for channel in [red, green, blue] {
+            *channel = srgb_to_linear(*channel) * *alpha;
+        }
+    }
+
+    linear_image
+}
+
+/// Converts the given [linearized] `image` back to gamma-encoded values with straight alpha,
+/// clamping any values resampling took out of range.
+///
+/// [linearized]: linearized
+fn delinearize(image: &mut Rgba32FImage) {
+    for Rgba([red, green, blue, alpha]) in image.pixels_mut() {
+        *alpha = alpha.clamp(0.0, 1.0);
+
+        for channel in [red, green, blue] {
+            let value = if *alpha > 0.0 { *channel / *alpha } else { 0.0 };
+            *channel = linear_to_srgb(value.clamp(0.0, 1.0));
+        }
+    }
+}
+
+/// Converts the given `image` to the given `color` type.
+pub fn with_color(image: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => image.to_luma8().into(),
+        ColorType::La8 => image.to_luma_alpha8().into(),
+        ColorType::Rgb8 => image.to_rgb8().into(),
+        ColorType::Rgba8 => image.to_rgba8().into(),
+        ColorType::L16 => image.to_luma16().into(),
+        ColorType::La16 => image.to_luma_alpha16().into(),
+        ColorType::Rgb16 => image.to_rgb16().into(),
+        ColorType::Rgba16 => image.to_rgba16().into(),
+        ColorType::Rgb32F => image.to_rgb32f().into(),
+        ColorType::Rgba32F => image.to_rgba32f().into(),
+
+        _ => image,
+    }
+}
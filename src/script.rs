@@ -0,0 +1,73 @@
+use crate::cli::{Length, ResizeArgs, Size};
+use crate::error::InvalidArguments;
+use crate::file;
+use log::debug;
+use rhai::{Dynamic, Engine, Scope};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// The given `resize_args` with the options overridden by their `--script` for the given `input`,
+/// the `index`th input.
+///
+/// The script is given the input's `path`, `width`, `height`, and `index`. It can evaluate to a
+/// map of the options to override - `size` for `--output-size` and `output` for `--output-path` -
+/// or to nothing to leave them as they are.
+pub fn apply<'a>(
+    resize_args: &'a ResizeArgs,
+    input: &file::Input,
+    index: usize,
+) -> Result<Cow<'a, ResizeArgs>, Box<dyn Error>> {
+    let Some(script_path) = &resize_args.script else {
+        return Ok(Cow::Borrowed(resize_args));
+    };
+    let script_error = |error: &dyn Display| {
+        InvalidArguments(format!("script {}: {error}", script_path.display()))
+    };
+
+    let (width, height) = input.dimensions()?;
+    let mut scope = Scope::new();
+    scope.push("path", input.path().display().to_string());
+    scope.push("width", i64::from(width));
+    scope.push("height", i64::from(height));
+    scope.push("index", index as i64);
+
+    // The script is compiled again for each input, which takes far less time than resizing it.
+    let result: Dynamic = Engine::new()
+        .eval_file_with_scope(&mut scope, script_path.clone())
+        .map_err(|error| script_error(&*error))?;
+    if result.is_unit() {
+        return Ok(Cow::Borrowed(resize_args));
+    }
+
+    let Some(overrides) = result.try_cast::<rhai::Map>() else {
+        return Err(script_error(&"the script must evaluate to a map or nothing").into());
+    };
+    debug!(
+        "{}: the script overrides {overrides:?}",
+        input.path().display()
+    );
+
+    let mut resize_args = resize_args.clone();
+    for (option, value) in overrides {
+        let value = value
+            .into_string()
+            .map_err(|kind| script_error(&format!("{option} must be a string, not {kind}")))?;
+
+        match option.as_str() {
+            "size" => {
+                let size: Size<Length> = value
+                    .parse()
+                    .map_err(|error| script_error(&format!("invalid size: {error}")))?;
+
+                (resize_args.tiles, resize_args.output_size) = (None, Some(size));
+            }
+            "output" => resize_args.output_path = Some(PathBuf::from(value)),
+
+            _ => return Err(script_error(&format!("unknown option {option}")).into()),
+        }
+    }
+
+    Ok(Cow::Owned(resize_args))
+}
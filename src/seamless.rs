@@ -0,0 +1,77 @@
+use crate::cli::SeamlessArgs;
+use crate::error::InvalidArguments;
+use crate::{file, scale};
+use image::{DynamicImage, GenericImageView, Rgba32FImage};
+use log::{debug, info};
+use rayon::prelude::*;
+use std::error::Error;
+
+/// Makes the image seamless according to the given `seamless_args`.
+pub fn run(seamless_args: &SeamlessArgs) -> Result<(), Box<dyn Error>> {
+    let SeamlessArgs {
+        input_path,
+        output_path,
+        blend,
+        encode_args,
+        limit_args,
+    } = seamless_args;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let new_path = output_path.as_deref().unwrap_or(input_path);
+
+    let (image, metadata) = input.decode()?;
+    let (width, height) = image.dimensions();
+    let shorter_side = width.min(height);
+
+    let blend = blend.map_or((shorter_side / 4).max(1), |blend| blend.get());
+    if blend > shorter_side / 2 {
+        return Err(InvalidArguments(format!(
+            "--blend {blend} is more than half of the {width}x{height} image"
+        ))
+        .into());
+    }
+
+    debug!(
+        "{}: blending a {blend}-pixel border with the offset image",
+        input_path.display()
+    );
+    let new_image = seamless(&image, blend);
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image, &metadata, new_path, encode_args, false)?;
+
+    Ok(())
+}
+
+/// Makes the given `image` tile seamlessly, keeping its sample format.
+///
+/// The image is fully replaced at its edges by a copy offset by half its width and height, whose
+/// edges meet seamlessly as they were adjacent in the original. Within `blend` pixels of the
+/// edges, the original fades into the offset copy, which hides the copy's own seams in the middle.
+pub fn seamless(image: &DynamicImage, blend: u32) -> DynamicImage {
+    let original = image.to_rgba32f();
+    let (width, height) = original.dimensions();
+    let mut new_image = Rgba32FImage::new(width, height);
+
+    new_image
+        .par_chunks_mut(width as usize * 4)
+        .zip(0..height)
+        .for_each(|(row, y)| {
+            for (pixel, x) in row.chunks_mut(4).zip(0..width) {
+                let edge_distance = x.min(width - 1 - x).min(y).min(height - 1 - y);
+                let weight = (edge_distance as f32 / blend as f32).min(1.0);
+
+                let offset = original.get_pixel((x + width / 2) % width, (y + height / 2) % height);
+
+                for ((sample, original), offset) in pixel
+                    .iter_mut()
+                    .zip(original.get_pixel(x, y).0)
+                    .zip(offset.0)
+                {
+                    *sample = original * weight + offset * (1.0 - weight);
+                }
+            }
+        });
+
+    scale::with_color(new_image.into(), image.color())
+}
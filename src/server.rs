@@ -0,0 +1,323 @@
+use crate::cli::{Alpha, ServeArgs, Size, ThumbnailQuality};
+use crate::error::{FileError, InvalidArguments};
+use crate::thumbnail::{self, Crop};
+use crate::tiling::Layout;
+use crate::{encode, file, scale};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use indicatif::ProgressBar;
+use log::{info, warn};
+use std::error::Error;
+use std::io::Read;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The name given to uploaded images in place of a path.
+const UPLOAD_NAME: &str = "upload";
+
+/// How an uploaded image is made to fit the requested size.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mode {
+    /// Repeat the image across the size.
+    Tile,
+    /// Resample the image to exactly the size, ignoring its aspect ratio.
+    Scale,
+    /// Shrink the image to fit within the size, keeping its aspect ratio.
+    Fit,
+    /// Shrink the image to cover the size, keeping its aspect ratio, and crop it to the size.
+    Cover,
+}
+
+/// What is done to an uploaded image, as given by the query parameters of its request.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Query {
+    /// The size to make the image.
+    size: (u32, u32),
+    /// How the image is made to fit the size.
+    mode: Mode,
+    /// The format of the response, or that of the upload if not given.
+    format: Option<ImageFormat>,
+}
+
+/// An error responded with in place of an image.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Rejection {
+    /// The HTTP status code.
+    status: u16,
+    /// What went wrong.
+    message: String,
+}
+
+impl Rejection {
+    /// A rejection with the HTTP status which best describes the given `error`.
+    fn new(error: &(dyn Error + 'static)) -> Self {
+        let status = match error.downcast_ref::<FileError>() {
+            Some(FileError::TooLarge { .. }) => 413,
+            Some(FileError::Decode { .. }) => 422,
+            Some(_) => 500,
+
+            None if error.is::<InvalidArguments>() => 400,
+            None => 500,
+        };
+
+        Self {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Serves the HTTP endpoint described by the `serve_args` until the process is stopped, handling
+/// each request in parallel.
+pub fn run(serve_args: &ServeArgs) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(serve_args.listen)
+        .map_err(|error| format!("failed to listen on {}: {error}", serve_args.listen))?;
+    info!("listening on http://{}", serve_args.listen);
+
+    let serve_args = Arc::new(serve_args.clone());
+    for mut request in server.incoming_requests() {
+        let serve_args = Arc::clone(&serve_args);
+
+        rayon::spawn(move || {
+            let (method, url) = (request.method().clone(), request.url().to_owned());
+
+            let response = match handle(&mut request, &serve_args) {
+                Ok((image, format)) => Response::from_data(image)
+                    .with_header(header("Content-Type", format.to_mime_type())),
+
+                Err(Rejection { status, message }) => Response::from_string(format!("{message}\n"))
+                    .with_status_code(status)
+                    .with_header(header("Content-Type", "text/plain; charset=utf-8")),
+            };
+            info!("{method} {url} {}", response.status_code().0);
+
+            if let Err(error) = request.respond(response) {
+                warn!("failed to respond to {method} {url}: {error}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Processes the image uploaded in the given `request` as given by its query parameters,
+/// returning the encoded result and its format.
+fn handle(
+    request: &mut Request,
+    serve_args: &ServeArgs,
+) -> Result<(Vec<u8>, ImageFormat), Rejection> {
+    let ServeArgs {
+        max_upload_size,
+        encode_args,
+        limit_args,
+        ..
+    } = serve_args;
+    let reject = |status, message: String| Rejection { status, message };
+
+    if *request.method() != Method::Post {
+        return Err(reject(405, "images must be uploaded with POST".to_owned()));
+    }
+    let query = parse_query(request.url()).map_err(|error| Rejection::new(&error))?;
+
+    // Read one byte more than allowed, to tell whether the upload is too large.
+    let mut bytes = Vec::new();
+    request
+        .as_reader()
+        .take(max_upload_size.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|error| reject(400, format!("failed to read the upload: {error}")))?;
+    if bytes.len() as u64 > *max_upload_size {
+        return Err(reject(
+            413,
+            format!("the upload is larger than the maximum of {max_upload_size} bytes"),
+        ));
+    }
+
+    let input = file::Input::from_bytes(Path::new(UPLOAD_NAME), bytes, true)
+        .limit_pixels(limit_args.max_pixels)
+        .map_err(|error| Rejection::new(&error))?;
+    let format = query
+        .format
+        .or(input.format)
+        .ok_or_else(|| reject(422, "the upload is not in a known image format".to_owned()))?;
+    crate::check_output_dimensions(query.size, limit_args)
+        .map_err(|error| Rejection::new(&*error))?;
+
+    let indexed = input.indexed;
+    let (image, metadata) = input.decode().map_err(|error| Rejection::new(&error))?;
+    let new_image = process(&image, &query);
+
+    let encoded = encode::encode(&new_image, format, encode_args, indexed)
+        .map_err(|error| reject(500, format!("failed to encode the image: {error}")))?;
+
+    Ok((
+        file::with_density(&metadata, encode_args).write(encoded, format),
+        format,
+    ))
+}
+
+/// Makes the given `image` the size given by the `query`, in its mode.
+fn process(image: &DynamicImage, query: &Query) -> DynamicImage {
+    match query.mode {
+        Mode::Tile => crate::repeated(
+            image,
+            query.size,
+            Alpha::Blend,
+            &Layout::default(),
+            &ProgressBar::hidden(),
+        ),
+        Mode::Scale => scale::scale(image, query.size, FilterType::Lanczos3, true),
+        Mode::Fit => thumbnail::thumbnail(image, query.size, None, ThumbnailQuality::High),
+        Mode::Cover => thumbnail::thumbnail(
+            image,
+            query.size,
+            Some(Crop::Center),
+            ThumbnailQuality::High,
+        ),
+    }
+}
+
+/// Parses the query parameters of the given request `url`.
+///
+/// Errors if a parameter is unknown or invalid, or if no `size` is given.
+fn parse_query(url: &str) -> Result<Query, InvalidArguments> {
+    let (mut size, mut mode, mut format) = (None, Mode::Tile, None);
+
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+        let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        let value = percent_decoded(value);
+        let invalid = |reason: &dyn std::fmt::Display| {
+            InvalidArguments(format!("invalid {name} parameter {value:?}: {reason}"))
+        };
+
+        match name {
+            "size" => {
+                let Size(width, height) = value
+                    .parse::<Size<NonZeroU32>>()
+                    .map_err(|error| invalid(&error))?;
+
+                size = Some((width.get(), height.get()));
+            }
+            "mode" => {
+                mode = match value.as_str() {
+                    "tile" => Mode::Tile,
+                    "scale" => Mode::Scale,
+                    "fit" => Mode::Fit,
+                    "cover" => Mode::Cover,
+
+                    _ => return Err(invalid(&"expected tile, scale, fit, or cover")),
+                };
+            }
+            "format" => {
+                format = Some(
+                    ImageFormat::from_extension(&value)
+                        .ok_or_else(|| invalid(&"unknown image format"))?,
+                );
+            }
+
+            _ => {
+                return Err(InvalidArguments(format!(
+                    "unknown parameter {name:?}: expected size, mode, or format"
+                )))
+            }
+        }
+    }
+
+    Ok(Query {
+        size: size.ok_or_else(|| InvalidArguments("no size parameter was given".to_owned()))?,
+        mode,
+        format,
+    })
+}
+
+/// Decodes the `%XX` escapes and `+` spaces in the given query parameter `value`, leaving any
+/// invalid escapes as they are.
+fn percent_decoded(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut index = 0;
+    while index < bytes.len() {
+        // Only hex digits are checked for, as `from_str_radix` would also accept a sign.
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The HTTP header with the given `name` and `value`.
+fn header(name: &str, value: &str) -> Header {
+    // Both are ASCII, which every header name and value is allowed to be.
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("headers are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_and_pluses_are_decoded() {
+        assert_eq!(percent_decoded("50%25x1080"), "50%x1080");
+        assert_eq!(percent_decoded("%2Fa%2fb"), "/a/b");
+        assert_eq!(percent_decoded("a+b%20c"), "a b c");
+        assert_eq!(percent_decoded("%D1%84"), "ф");
+    }
+
+    #[test]
+    fn invalid_escapes_are_kept() {
+        assert_eq!(percent_decoded("100%"), "100%");
+        assert_eq!(percent_decoded("%4"), "%4");
+        // Signs are not hex digits, so the `+` is still a space.
+        assert_eq!(percent_decoded("%zz%+1"), "%zz% 1");
+        // Invalid UTF-8 is replaced rather than rejected.
+        assert_eq!(percent_decoded("a%FFb"), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn queries_are_parsed() {
+        assert_eq!(
+            parse_query("/?size=640x480&mode=cover&format=webp"),
+            Ok(Query {
+                size: (640, 480),
+                mode: Mode::Cover,
+                format: Some(ImageFormat::WebP),
+            })
+        );
+        assert_eq!(
+            parse_query("/?size=640%2C480&").map(|query| query.size),
+            Ok((640, 480))
+        );
+
+        for url in [
+            "/",
+            "/?size=0x480",
+            "/?size=%4",
+            "/?size=1x1&mode=crop",
+            "/?width=1",
+        ] {
+            assert!(parse_query(url).is_err(), "{url}");
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::cli::{Color, Shadow, Sigma, Size};
+use crate::scale;
+use image::{imageops, DynamicImage, GenericImageView, Rgba, Rgba32FImage};
+use std::borrow::Cow;
+
+/// How many standard deviations the blur of a shadow extends beyond its edges, past which it is
+/// too faint to see.
+const BLUR_EXTENT: f32 = 3.0;
+
+/// The space around an image: its left, top, right, and bottom margins.
+struct Margins(u32, u32, u32, u32);
+
+/// The margins needed around an image for the given `shadow` to fit.
+fn margins(shadow: Shadow) -> Margins {
+    let Shadow {
+        offset: Size(x, y),
+        blur,
+        ..
+    } = shadow;
+    let extent = blur.map_or(0, |Sigma(sigma)| (sigma * BLUR_EXTENT).ceil() as u32);
+
+    // The shadow reaches past the image by its blur, minus however far it is offset away from
+    // that side.
+    let margin = |offset: i32| extent.saturating_add_signed(-offset);
+
+    Margins(margin(x), margin(y), margin(-x), margin(-y))
+}
+
+/// The dimensions of an image with the given `dimensions` once the given `shadow` is drawn
+/// behind it.
+pub fn dimensions((width, height): (u32, u32), shadow: Shadow) -> (u32, u32) {
+    let Margins(left, top, right, bottom) = margins(shadow);
+
+    (
+        width.saturating_add(left).saturating_add(right),
+        height.saturating_add(top).saturating_add(bottom),
+    )
+}
+
+/// Draws the given `shadow` behind the given `image`, extending the canvas to fit it, with the
+/// sample format of the `image` plus an alpha channel.
+///
+/// The shadow has the shape of the image's opaque pixels.
+pub fn shadowed(image: &DynamicImage, shadow: Shadow) -> DynamicImage {
+    let Shadow {
+        offset: Size(x, y),
+        blur,
+        color: Color([red, green, blue, alpha]),
+    } = shadow;
+    let Margins(left, top, ..) = margins(shadow);
+    let (width, height) = dimensions(image.dimensions(), shadow);
+
+    // The canvas around the image is transparent, which needs an alpha channel.
+    let image = if image.color().has_alpha() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(crate::with_alpha(image))
+    };
+    let image_layer = image.to_rgba32f();
+
+    // Transparent pixels take the shadow's color too, so that blurring it keeps the color even.
+    let [red, green, blue, alpha] =
+        [red, green, blue, alpha].map(|sample| f32::from(sample) / 255.0);
+    let mut new_image = Rgba32FImage::from_pixel(width, height, Rgba([red, green, blue, 0.0]));
+
+    let (shadow_x, shadow_y) = (
+        i64::from(left) + i64::from(x),
+        i64::from(top) + i64::from(y),
+    );
+    for (image_x, image_y, pixel) in image_layer.enumerate_pixels() {
+        let (x, y) = (shadow_x + i64::from(image_x), shadow_y + i64::from(image_y));
+        if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) {
+            new_image.put_pixel(x, y, Rgba([red, green, blue, alpha * pixel.0[3]]));
+        }
+    }
+
+    if let Some(Sigma(sigma)) = blur {
+        new_image = imageops::blur(&new_image, sigma);
+    }
+
+    imageops::overlay(
+        &mut new_image,
+        &image_layer,
+        i64::from(left),
+        i64::from(top),
+    );
+
+    scale::with_color(new_image.into(), image.color())
+}
@@ -0,0 +1,98 @@
+use crate::cli::{Size, SplitArgs};
+use crate::error::InvalidArguments;
+use crate::file;
+use crate::template::{self, Placeholders};
+use image::GenericImageView;
+use log::{debug, info};
+use std::error::Error;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+/// Splits the image into tiles according to the given `split_args`.
+pub fn run(split_args: &SplitArgs) -> Result<(), Box<dyn Error>> {
+    let SplitArgs {
+        input_path,
+        output_template,
+        grid,
+        tile_size,
+        encode_args,
+        limit_args,
+    } = split_args;
+
+    // Check the template is valid before writing any tiles.
+    template::render(
+        output_template,
+        &Placeholders {
+            tile: Some((0, 0)),
+            ..Placeholders::new(Path::new(""), (0, 0), 0)
+        },
+    )?;
+
+    let input = file::Input::read(input_path, true)?.limit_pixels(limit_args.max_pixels)?;
+    let indexed = input.indexed;
+    let (image, metadata) = input.decode()?;
+    let (width, height) = image.dimensions();
+
+    let (columns, rows) = match (grid, tile_size) {
+        (Some(Size(across, down)), _) => (edges(width, across.get())?, edges(height, down.get())?),
+        (None, Some(Size(tile_width, tile_height))) => (
+            fixed_edges(width, *tile_width),
+            fixed_edges(height, *tile_height),
+        ),
+
+        (None, None) => unreachable!("clap requires --grid or --tile-size"),
+    };
+    debug!(
+        "{}: splitting into {}x{} tiles",
+        input_path.display(),
+        columns.len() - 1,
+        rows.len() - 1,
+    );
+
+    for (row, vertical) in (0..).zip(rows.windows(2)) {
+        for (column, horizontal) in (0..).zip(columns.windows(2)) {
+            let (x, y) = (horizontal[0], vertical[0]);
+            let dimensions = (horizontal[1] - x, vertical[1] - y);
+            let index = row as usize * (columns.len() - 1) + column as usize;
+
+            let new_path = template::render(
+                output_template,
+                &Placeholders {
+                    tile: Some((column, row)),
+                    ..Placeholders::new(input_path, dimensions, index)
+                },
+            )?;
+            let tile = image.crop_imm(x, y, dimensions.0, dimensions.1);
+
+            info!("writing {}", new_path.display());
+            file::write(&tile, &metadata, &new_path, encode_args, indexed)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The positions of the edges between `count` tiles along a `length` as close to the same size
+/// as possible, including the start and the end.
+///
+/// Errors if there are more tiles than pixels.
+fn edges(length: u32, count: u32) -> Result<Vec<u32>, InvalidArguments> {
+    if count > length {
+        return Err(InvalidArguments(format!(
+            "cannot split {length} pixels into {count} tiles"
+        )));
+    }
+
+    Ok((0..=count)
+        .map(|index| (u64::from(length) * u64::from(index) / u64::from(count)) as u32)
+        .collect())
+}
+
+/// The positions of the edges between tiles of the given `size` along a `length`, including the
+/// start and the end, which may make the last tile smaller.
+fn fixed_edges(length: u32, size: NonZeroU32) -> Vec<u32> {
+    (0..length)
+        .step_by(size.get() as usize)
+        .chain([length])
+        .collect()
+}
@@ -0,0 +1,389 @@
+use crate::cli::SpritesheetArgs;
+use crate::error::{FileError, InvalidArguments};
+use crate::file;
+use crate::metadata::Metadata;
+use image::{imageops, RgbaImage};
+use log::{debug, info};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a sprite is placed on a sprite sheet.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Sprite {
+    /// The position of the sprite's left edge.
+    pub x: u32,
+    /// The position of the sprite's top edge.
+    pub y: u32,
+    /// The width of the sprite.
+    pub width: u32,
+    /// The height of the sprite.
+    pub height: u32,
+}
+
+/// The layout of a sprite sheet written by `--layout`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Layout<'a> {
+    /// The path of the sprite sheet.
+    pub image: &'a str,
+    /// The width of the sprite sheet.
+    pub width: u32,
+    /// The height of the sprite sheet.
+    pub height: u32,
+    /// Each sprite on the sheet, in the order they were given.
+    pub sprites: Vec<NamedSprite<'a>>,
+}
+
+/// A sprite in a sprite sheet's [layout](Layout).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NamedSprite<'a> {
+    /// The name of the sprite, from the file name of its image.
+    pub name: &'a str,
+    /// Where the sprite is placed.
+    #[serde(flatten)]
+    pub sprite: Sprite,
+}
+
+/// A segment of the skyline along the top of the sprites packed so far, which sprites are placed
+/// on top of.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Segment {
+    x: u64,
+    y: u64,
+    width: u64,
+}
+
+/// Packs the images given in the `spritesheet_args` onto a sprite sheet.
+pub fn run(spritesheet_args: &SpritesheetArgs) -> Result<(), Box<dyn Error>> {
+    let SpritesheetArgs {
+        input_paths,
+        output_path,
+        columns,
+        pack,
+        padding,
+        layout,
+        encode_args,
+        limit_args,
+    } = spritesheet_args;
+
+    let (names, images): (Vec<_>, Vec<_>) = read_sprites(input_paths, limit_args.max_pixels)?
+        .into_iter()
+        .unzip();
+    let dimensions: Vec<_> = images.iter().map(RgbaImage::dimensions).collect();
+
+    let (sprites, sheet_dimensions) = if *pack {
+        debug!("packing {} sprites", images.len());
+        packed(&dimensions, *padding)?
+    } else {
+        let columns = columns.map_or_else(
+            || (images.len() as f64).sqrt().ceil() as u32,
+            |columns| columns.get().min(images.len() as u32),
+        );
+
+        debug!("packing {} sprites in {columns} columns", images.len());
+        grid(&dimensions, columns, *padding)?
+    };
+    crate::check_output_dimensions(sheet_dimensions, limit_args)?;
+
+    let sheet = sheet(&images, &sprites, sheet_dimensions);
+
+    info!("writing {}", output_path.display());
+    file::write(
+        &sheet.into(),
+        &Metadata::default(),
+        output_path,
+        encode_args,
+        false,
+    )?;
+
+    if let Some(layout_path) = layout {
+        let image = output_path.display().to_string();
+        let layout = Layout {
+            image: &image,
+            width: sheet_dimensions.0,
+            height: sheet_dimensions.1,
+            sprites: names
+                .iter()
+                .zip(sprites)
+                .map(|(name, sprite)| NamedSprite { name, sprite })
+                .collect(),
+        };
+
+        info!("writing {}", layout_path.display());
+        write_layout(&layout, layout_path)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the images at the given `input_paths` along with their names, or the frames of the image
+/// if there is only one and it is animated.
+///
+/// Each image is named by its file name without its extension, and each frame by that of its
+/// image followed by its position, from 1.
+///
+/// Errors if any image has more than `max_pixels` pixels.
+pub fn read_sprites(
+    input_paths: &[PathBuf],
+    max_pixels: u64,
+) -> Result<Vec<(String, RgbaImage)>, Box<dyn Error>> {
+    let name = |input_path: &Path| {
+        input_path.file_stem().map_or_else(
+            || input_path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+    };
+
+    if let [input_path] = input_paths {
+        let input = file::Input::read(input_path, true)?.limit_pixels(max_pixels)?;
+
+        return match input.decode_animation()? {
+            Some((animation, _)) => Ok((1..)
+                .zip(animation.frames)
+                .map(|(index, frame)| {
+                    (format!("{}-{index}", name(input_path)), frame.into_buffer())
+                })
+                .collect()),
+            None => Ok(vec![(name(input_path), input.decode()?.0.into_rgba8())]),
+        };
+    }
+
+    input_paths
+        .iter()
+        .map(|input_path| {
+            let input = file::Input::read(input_path, true)?.limit_pixels(max_pixels)?;
+            Ok((name(input_path), input.decode()?.0.into_rgba8()))
+        })
+        .collect()
+}
+
+/// Places sprites with the given `dimensions` in order in a grid of the given number of
+/// `columns`, each cell of which fits the largest sprite with `padding` around it, returning
+/// where each sprite is placed along with the dimensions of the sheet.
+///
+/// Errors if the sheet would be too large for any image.
+fn grid(
+    dimensions: &[(u32, u32)],
+    columns: u32,
+    padding: u32,
+) -> Result<(Vec<Sprite>, (u32, u32)), InvalidArguments> {
+    let (width, height) = dimensions
+        .iter()
+        .fold((0, 0), |(max_width, max_height), &(width, height)| {
+            (max_width.max(width), max_height.max(height))
+        });
+    let rows = (dimensions.len() as u64).div_ceil(u64::from(columns));
+
+    let cell = |length: u32| u64::from(length) + 2 * u64::from(padding);
+    let (sheet_width, sheet_height) = (u64::from(columns) * cell(width), rows * cell(height));
+    let (Ok(sheet_width), Ok(sheet_height)) =
+        (u32::try_from(sheet_width), u32::try_from(sheet_height))
+    else {
+        return Err(InvalidArguments(format!(
+            "the sprite sheet would be {sheet_width}x{sheet_height}, which is too large for any image"
+        )));
+    };
+
+    // The sheet fits in 32 bits, so each cell does too.
+    let (cell_width, cell_height) = (width + 2 * padding, height + 2 * padding);
+    let sprites = (0..)
+        .zip(dimensions)
+        .map(|(index, &(width, height))| Sprite {
+            x: index % columns * cell_width + padding,
+            y: index / columns * cell_height + padding,
+            width,
+            height,
+        })
+        .collect();
+
+    Ok((sprites, (sheet_width, sheet_height)))
+}
+
+/// Packs sprites with the given `dimensions` tightly, with `padding` around each, returning where
+/// each sprite is placed along with the dimensions of the sheet.
+///
+/// The sheet is about as wide as it is tall. From the tallest to the shortest, each sprite is
+/// placed as low and then as far left as it fits on the skyline of those placed before it.
+///
+/// Errors if the sheet would be too large for any image.
+fn packed(
+    dimensions: &[(u32, u32)],
+    padding: u32,
+) -> Result<(Vec<Sprite>, (u32, u32)), InvalidArguments> {
+    let padded = |(width, height): (u32, u32)| {
+        (
+            u64::from(width) + 2 * u64::from(padding),
+            u64::from(height) + 2 * u64::from(padding),
+        )
+    };
+
+    let area: u64 = dimensions
+        .iter()
+        .map(|&dimensions| {
+            let (width, height) = padded(dimensions);
+            width * height
+        })
+        .sum();
+    let widest = dimensions
+        .iter()
+        .map(|&dimensions| padded(dimensions).0)
+        .max()
+        .unwrap_or_default();
+    let max_width = widest.max((area as f64).sqrt().ceil() as u64);
+
+    let mut order: Vec<_> = (0..dimensions.len()).collect();
+    order.sort_by_key(|&index| {
+        let (width, height) = padded(dimensions[index]);
+        (std::cmp::Reverse(height), std::cmp::Reverse(width))
+    });
+
+    let mut skyline = vec![Segment {
+        x: 0,
+        y: 0,
+        width: max_width,
+    }];
+    let mut positions = vec![(0, 0); dimensions.len()];
+    let (mut sheet_width, mut sheet_height) = (0, 0);
+
+    for index in order {
+        let (width, height) = padded(dimensions[index]);
+
+        // The lowest, and then leftmost, position where the sprite sits on the skyline.
+        let (y, x) = (0..skyline.len())
+            .map_while(|start| {
+                let x = skyline[start].x;
+                let y = skyline[start..]
+                    .iter()
+                    .take_while(|segment| segment.x < x + width)
+                    .map(|segment| segment.y)
+                    .max()?;
+
+                (x + width <= max_width).then_some((y, x))
+            })
+            .min()
+            .expect("the widest sprite fits within the sheet");
+        positions[index] = (x, y);
+        (sheet_width, sheet_height) = (sheet_width.max(x + width), sheet_height.max(y + height));
+
+        // Raise the skyline where the sprite was placed.
+        let mut raised = Vec::with_capacity(skyline.len() + 2);
+        raised.extend(
+            skyline
+                .iter()
+                .filter(|segment| segment.x < x)
+                .map(|segment| Segment {
+                    width: segment.width.min(x - segment.x),
+                    ..*segment
+                }),
+        );
+        raised.push(Segment {
+            x,
+            y: y + height,
+            width,
+        });
+        raised.extend(skyline.iter().filter_map(|segment| {
+            let start = segment.x.max(x + width);
+            let width = (segment.x + segment.width).checked_sub(start)?;
+
+            (width > 0).then_some(Segment {
+                x: start,
+                width,
+                ..*segment
+            })
+        }));
+
+        skyline = raised;
+    }
+
+    let (Ok(sheet_width), Ok(sheet_height)) =
+        (u32::try_from(sheet_width), u32::try_from(sheet_height))
+    else {
+        return Err(InvalidArguments(format!(
+            "the sprite sheet would be {sheet_width}x{sheet_height}, which is too large for any image"
+        )));
+    };
+
+    // The sheet fits in 32 bits, so each position within it does too.
+    let sprites = positions
+        .into_iter()
+        .zip(dimensions)
+        .map(|((x, y), &(width, height))| Sprite {
+            x: x as u32 + padding,
+            y: y as u32 + padding,
+            width,
+            height,
+        })
+        .collect();
+
+    Ok((sprites, (sheet_width, sheet_height)))
+}
+
+/// Copies each of the given `images` onto a new transparent sheet of the given dimensions, where
+/// its [sprite](Sprite) is placed.
+pub fn sheet(images: &[RgbaImage], sprites: &[Sprite], (width, height): (u32, u32)) -> RgbaImage {
+    let mut sheet = RgbaImage::new(width, height);
+
+    for (image, sprite) in images.iter().zip(sprites) {
+        imageops::replace(&mut sheet, image, i64::from(sprite.x), i64::from(sprite.y));
+    }
+
+    sheet
+}
+
+/// Writes the given `layout` to `path`, as CSS classes if it ends in `.css` and as JSON
+/// otherwise.
+fn write_layout(layout: &Layout, path: &Path) -> Result<(), FileError> {
+    let is_css = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("css"));
+
+    let contents = if is_css {
+        css(layout)
+    } else {
+        // Serializing the layout cannot fail: it contains no maps and no fallible `Serialize`
+        // implementations.
+        serde_json::to_string_pretty(layout).expect("layouts are serializable") + "\n"
+    };
+
+    fs::write(path, contents).map_err(|source| FileError::Write {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// A CSS class for each sprite in the given `layout`, named `sprite-` followed by the sprite's
+/// name, which shows the sprite as the element's background.
+fn css(layout: &Layout) -> String {
+    // The background is offset to the left and up to bring the sprite into view.
+    let offset = |position: u32| match position {
+        0 => "0".to_owned(),
+        position => format!("-{position}px"),
+    };
+
+    let mut css = String::new();
+    for NamedSprite { name, sprite } in &layout.sprites {
+        // Characters that cannot be in a class name are replaced.
+        let class: String = name
+            .chars()
+            .map(|character| match character {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => character,
+                _ => '-',
+            })
+            .collect();
+
+        // Writing to a string cannot fail.
+        let _ = writeln!(
+            css,
+            ".sprite-{class} {{\n  background: url(\"{}\") {} {};\n  width: {}px;\n  height: {}px;\n}}",
+            layout.image,
+            offset(sprite.x),
+            offset(sprite.y),
+            sprite.width,
+            sprite.height,
+        );
+    }
+
+    css
+}
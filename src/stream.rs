@@ -0,0 +1,118 @@
+use crate::metadata::Metadata;
+use indicatif::ProgressBar;
+use log::debug;
+use png::chunk::ChunkType;
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::iter;
+use std::path::Path;
+
+/// Repeats the PNG image in the given `bytes` across a new PNG image with the given dimensions,
+/// writing it to `path` along with the given `metadata` one row at a time.
+///
+/// As with [`repeat`], repetitions that would be cut off are left transparent. Only a single row
+/// of the output is held in memory: rather than keeping the decoded input, it is decoded again
+/// for each vertical repetition.
+///
+/// The length of the given `progress` bar is set to the number of output rows, and it is advanced
+/// as each is written.
+///
+/// [`repeat`]: crate::repeat
+pub fn repeat_png(
+    bytes: &[u8],
+    (width, height): (u32, u32),
+    metadata: &Metadata,
+    path: &Path,
+    progress: &ProgressBar,
+) -> Result<(), Box<dyn Error>> {
+    let mut reader = decoder(bytes).read_info()?;
+    if reader.info().interlaced {
+        return Err("interlaced PNG images cannot be streamed".into());
+    }
+
+    let (repeated_width, repeated_height) = reader.info().size();
+    let (color, depth) = reader.output_color_type();
+    let (horizontal, vertical) = (width / repeated_width, height / repeated_height);
+    debug!("streaming {horizontal}x{vertical} tiles");
+
+    // Any part of the new image left uncovered is transparent, which needs an alpha channel.
+    let covered = (horizontal * repeated_width, vertical * repeated_height) == (width, height);
+    let new_color = match color {
+        ColorType::Grayscale if !covered => ColorType::GrayscaleAlpha,
+        ColorType::Rgb if !covered => ColorType::Rgba,
+
+        color => color,
+    };
+
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(new_color);
+    encoder.set_depth(depth);
+
+    let mut writer = encoder.write_header()?;
+    for (kind, data) in metadata.png_chunks() {
+        writer.write_chunk(ChunkType(kind), &data)?;
+    }
+    let mut writer = writer.stream_writer()?;
+
+    progress.set_length(u64::from(height));
+    progress.set_message("rows");
+
+    let sample_length = if depth == BitDepth::Sixteen { 2 } else { 1 };
+    let pixel_length = new_color.samples() * sample_length;
+    let mut new_row = vec![0; width as usize * pixel_length];
+    let tiles_length = (horizontal * repeated_width) as usize * pixel_length;
+
+    for repetition in 0..vertical {
+        if repetition > 0 {
+            reader = decoder(bytes).read_info()?;
+        }
+
+        while let Some(row) = reader.next_row()? {
+            let row = if new_color == color {
+                row.data().to_vec()
+            } else {
+                with_opaque_alpha(row.data(), color.samples() * sample_length, sample_length)
+            };
+
+            for tile in new_row[..tiles_length].chunks_mut(row.len()) {
+                tile.copy_from_slice(&row);
+            }
+
+            writer.write_all(&new_row)?;
+            progress.inc(1);
+        }
+    }
+
+    // The rows below the last vertical repetition are left transparent.
+    new_row.fill(0);
+    for _ in vertical * repeated_height..height {
+        writer.write_all(&new_row)?;
+        progress.inc(1);
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn decoder(bytes: &[u8]) -> Decoder<&[u8]> {
+    let mut decoder = Decoder::new(bytes);
+    // Palettes, transparency chunks, and bit depths below 8 are expanded to plain samples.
+    decoder.set_transformations(Transformations::EXPAND);
+
+    decoder
+}
+
+/// Appends an opaque alpha sample to each pixel in the given `row`.
+fn with_opaque_alpha(row: &[u8], pixel_length: usize, sample_length: usize) -> Vec<u8> {
+    row.chunks(pixel_length)
+        .flat_map(|pixel| {
+            pixel
+                .iter()
+                .copied()
+                .chain(iter::repeat_n(u8::MAX, sample_length))
+        })
+        .collect()
+}
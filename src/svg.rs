@@ -0,0 +1,62 @@
+use image::RgbaImage;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{Options, Tree};
+use std::error::Error;
+use std::path::Path;
+
+/// Whether the image at `path` is an SVG image, determined by its extension.
+pub fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("svg") || extension.eq_ignore_ascii_case("svgz")
+        })
+}
+
+/// The intrinsic dimensions of the SVG image in the given `bytes`, rounded to whole pixels.
+pub fn dimensions(bytes: &[u8]) -> Result<(u32, u32), Box<dyn Error + Send + Sync>> {
+    let size = parse(bytes)?.size().to_int_size();
+
+    Ok((size.width(), size.height()))
+}
+
+/// Rasterizes the SVG image in the given `bytes` at the given `dimensions`, or at its intrinsic
+/// dimensions if none are given.
+///
+/// The image is stretched to fill the given dimensions if their aspect ratio differs.
+pub fn rasterize(
+    bytes: &[u8],
+    dimensions: Option<(u32, u32)>,
+) -> Result<RgbaImage, Box<dyn Error + Send + Sync>> {
+    let tree = parse(bytes)?;
+    let size = tree.size();
+
+    let (width, height) = dimensions.unwrap_or_else(|| {
+        let size = size.to_int_size();
+        (size.width(), size.height())
+    });
+    let mut pixmap = Pixmap::new(width, height).ok_or("SVG images cannot be rasterized empty")?;
+
+    let transform =
+        Transform::from_scale(width as f32 / size.width(), height as f32 / size.height());
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // Pixmaps are premultiplied, while `RgbaImage`s are not.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("pixmaps have one pixel per position"))
+}
+
+fn parse(bytes: &[u8]) -> Result<Tree, Box<dyn Error + Send + Sync>> {
+    let mut options = Options::default();
+    options.fontdb_mut().load_system_fonts();
+
+    Ok(Tree::from_data(bytes, &options)?)
+}
@@ -0,0 +1,189 @@
+use crate::error::InvalidArguments;
+use image::ImageFormat;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// The values of the placeholders in an `--output-template`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Placeholders<'a> {
+    /// The directory of the input, or `.` if it has none.
+    pub dir: &'a OsStr,
+    /// The file name of the input without its extension.
+    pub stem: &'a OsStr,
+    /// The extension of the input.
+    pub ext: &'a OsStr,
+    /// The extension of the output's format, which is the input's format unless another is
+    /// given.
+    pub format: &'a OsStr,
+    /// The dimensions of the output.
+    pub dimensions: (u32, u32),
+    /// The position of the input among the inputs, from 0.
+    pub index: usize,
+    /// The column and row of the tile being written when splitting the input, from 0.
+    pub tile: Option<(u32, u32)>,
+}
+
+impl<'a> Placeholders<'a> {
+    /// The placeholders for the input at `input_path`, the `index`th input, resized to the given
+    /// `dimensions`.
+    pub fn new(input_path: &'a Path, dimensions: (u32, u32), index: usize) -> Self {
+        let dir = input_path
+            .parent()
+            .map(Path::as_os_str)
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or(OsStr::new("."));
+
+        let ext = input_path.extension().unwrap_or_default();
+        // The format's own extension is used where there are several, such as `jpg` for `.jpeg`.
+        let format = ImageFormat::from_path(input_path)
+            .ok()
+            .and_then(|format| format.extensions_str().first())
+            .map_or(ext, OsStr::new);
+
+        Self {
+            dir,
+            stem: input_path.file_stem().unwrap_or_default(),
+            ext,
+            format,
+            dimensions,
+            index,
+            tile: None,
+        }
+    }
+}
+
+/// Replaces each placeholder in the given `template`, such as `{stem}`, with its value.
+///
+/// `{{` and `}}` are replaced with literal braces. Errors if a placeholder is unknown or
+/// unclosed.
+///
+/// The input's directory, stem, and extension are kept as they are, even if they are not valid
+/// UTF-8.
+pub fn render(template: &str, placeholders: &Placeholders) -> Result<PathBuf, InvalidArguments> {
+    let (width, height) = placeholders.dimensions;
+
+    let mut rendered = OsString::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(position) = rest.find(['{', '}']) {
+        rendered.push(&rest[..position]);
+        let brace = &rest[position..];
+
+        if let Some(after) = brace
+            .strip_prefix("{{")
+            .or_else(|| brace.strip_prefix("}}"))
+        {
+            rendered.push(&brace[..1]);
+            rest = after;
+            continue;
+        }
+
+        let Some((name, after)) = brace
+            .strip_prefix('{')
+            .and_then(|brace| brace.split_once('}'))
+        else {
+            return Err(InvalidArguments(format!(
+                "unmatched brace in --output-template {template} - use {{{{ or }}}} for literal braces"
+            )));
+        };
+
+        match name {
+            "dir" => rendered.push(placeholders.dir),
+            "stem" => rendered.push(placeholders.stem),
+            "ext" => rendered.push(placeholders.ext),
+            "format" => rendered.push(placeholders.format),
+            "width" => rendered.push(width.to_string()),
+            "height" => rendered.push(height.to_string()),
+            "index" => rendered.push((placeholders.index + 1).to_string()),
+            "column" | "row" => {
+                let Some((column, row)) = placeholders.tile else {
+                    return Err(InvalidArguments(format!(
+                        "{{{name}}} is only available when splitting an image"
+                    )));
+                };
+
+                let value = if name == "column" { column } else { row };
+                rendered.push((value + 1).to_string());
+            }
+
+            _ => {
+                return Err(InvalidArguments(format!(
+                    "unknown placeholder in --output-template: {{{name}}}"
+                )))
+            }
+        }
+
+        rest = after;
+    }
+    rendered.push(rest);
+
+    Ok(rendered.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::UNUSUAL_NAMES;
+
+    fn render_for(template: &str, input_path: &Path) -> Result<PathBuf, InvalidArguments> {
+        render(template, &Placeholders::new(input_path, (4, 3), 0))
+    }
+
+    #[test]
+    fn unusual_file_names_are_kept() {
+        for (name, stem) in UNUSUAL_NAMES {
+            let input_path = Path::new("in").join(name);
+            let rendered = render_for("{dir}/{stem}-{width}x{height}.{ext}", &input_path).unwrap();
+
+            assert_eq!(rendered, Path::new("in").join(format!("{stem}-4x3.png")));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names_are_kept() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let input_path = Path::new(OsStr::from_bytes(b"in/\xffimage.png"));
+        let rendered = render_for("{dir}/{stem}-small.{ext}", input_path).unwrap();
+
+        assert_eq!(rendered.as_os_str().as_bytes(), b"in/\xffimage-small.png");
+    }
+
+    #[test]
+    fn inputs_without_a_directory_are_in_the_current_one() {
+        let rendered = render_for("{dir}/{stem}.{ext}", Path::new(".hidden")).unwrap();
+
+        assert_eq!(rendered, Path::new("./.hidden."));
+    }
+
+    #[test]
+    fn formats_are_inferred_from_the_input_unless_given() {
+        let input_path = Path::new("photo.JPEG");
+        let rendered = render_for("{stem}.{format}", input_path).unwrap();
+
+        assert_eq!(rendered, Path::new("photo.jpg"));
+
+        let placeholders = Placeholders {
+            format: OsStr::new("webp"),
+            ..Placeholders::new(input_path, (4, 3), 0)
+        };
+        let rendered = render("{stem}.{format}", &placeholders).unwrap();
+
+        assert_eq!(rendered, Path::new("photo.webp"));
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let rendered = render_for("{{stem}}-{stem}-{index}}}", Path::new("image.png")).unwrap();
+
+        assert_eq!(rendered, Path::new("{stem}-image-1}"));
+    }
+
+    #[test]
+    fn unmatched_and_unknown_placeholders_are_rejected() {
+        for template in ["{stem", "stem}", "{size}", "{column}"] {
+            assert!(render_for(template, Path::new("image.png")).is_err());
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs};
+
+/// File names which are awkward in paths or templates, along with their stems.
+pub const UNUSUAL_NAMES: [(&str, &str); 6] = [
+    ("my photo.png", "my photo"),
+    ("фото 📷.png", "фото 📷"),
+    (".hidden.png", ".hidden"),
+    ("100%.png", "100%"),
+    ("{stem}.png", "{stem}"),
+    ("a}b{c.png", "a}b{c"),
+];
+
+/// How many test directories have been made so far, which tells apart those with the same name.
+static TEST_DIRECTORIES: AtomicUsize = AtomicUsize::new(0);
+
+/// A new empty directory for a test, which is removed along with its contents when dropped - even
+/// if the test fails.
+#[derive(Debug)]
+pub struct TestDirectory(PathBuf);
+
+impl TestDirectory {
+    /// A new empty directory in the temporary directory, named after the test using it.
+    pub fn new(name: &str) -> Self {
+        let count = TEST_DIRECTORIES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("img-resize-{}-{count}-{name}", process::id()));
+        fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+}
+
+impl Deref for TestDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
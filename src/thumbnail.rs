@@ -0,0 +1,276 @@
+use crate::cli::{EncodeArgs, Size, ThumbnailArgs, ThumbnailQuality};
+use crate::error::{self, Failures};
+use crate::template::{self, Placeholders};
+use crate::{file, progress, scale};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use log::{debug, error, info};
+use rayon::prelude::*;
+use std::error::Error;
+use std::path::Path;
+
+impl ThumbnailQuality {
+    /// The encoding quality used for thumbnails of this quality.
+    fn encoding_quality(self) -> u8 {
+        match self {
+            Self::Low => 50,
+            Self::Medium => 75,
+            Self::High => 90,
+        }
+    }
+}
+
+/// Makes a thumbnail of each image given in the `thumbnail_args`, in parallel.
+///
+/// A failure to make one of the thumbnails is reported without stopping the others.
+pub fn run(thumbnail_args: &ThumbnailArgs) -> Result<(), Box<dyn Error>> {
+    let ThumbnailArgs {
+        input_paths,
+        progress,
+        no_progress,
+        ..
+    } = thumbnail_args;
+
+    // Check the template is valid before making any thumbnails.
+    template::render(
+        &thumbnail_args.output_template,
+        &Placeholders::new(Path::new(""), (0, 0), 0),
+    )?;
+
+    let progress = progress::images(
+        input_paths.len(),
+        input_paths.len() > 1 && crate::show_progress(*progress, *no_progress),
+    );
+
+    let exit_codes: Vec<_> = input_paths
+        .par_iter()
+        .enumerate()
+        .filter_map(|(index, input_path)| {
+            let result = thumbnail_one(input_path, index, thumbnail_args);
+            progress.inc(1);
+
+            match result {
+                Ok(()) => None,
+
+                Err(error) => {
+                    progress.suspend(|| error!("{}", error::describe(input_path, &*error)));
+                    Some(error::exit_code(&*error))
+                }
+            }
+        })
+        .collect();
+
+    progress.finish_and_clear();
+
+    if !exit_codes.is_empty() {
+        return Err(Failures {
+            action: "thumbnail",
+            exit_codes,
+            total: input_paths.len(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Makes a thumbnail of the image at `input_path`, the `index`th input.
+fn thumbnail_one(
+    input_path: &Path,
+    index: usize,
+    thumbnail_args: &ThumbnailArgs,
+) -> Result<(), Box<dyn Error>> {
+    let ThumbnailArgs {
+        output_template,
+        size: Size(width, height),
+        crop,
+        smart_crop,
+        quality_preset,
+        no_auto_orient,
+        encode_args,
+        limit_args,
+        ..
+    } = thumbnail_args;
+
+    let input =
+        file::Input::read(input_path, !no_auto_orient)?.limit_pixels(limit_args.max_pixels)?;
+    let (image, metadata) = input.decode()?;
+
+    debug!(
+        "{}: making a thumbnail of {:?} within {:?}",
+        input_path.display(),
+        image.dimensions(),
+        (width, height),
+    );
+    let crop = match (crop, smart_crop) {
+        (true, true) => Some(Crop::Smart),
+        (true, false) => Some(Crop::Center),
+        (false, _) => None,
+    };
+    let new_image = thumbnail(&image, (width.get(), height.get()), crop, *quality_preset);
+
+    let new_path = template::render(
+        output_template,
+        &Placeholders::new(input_path, new_image.dimensions(), index),
+    )?;
+    let encode_args = EncodeArgs {
+        quality: encode_args
+            .quality
+            .or(Some(quality_preset.encoding_quality())),
+        ..encode_args.clone()
+    };
+
+    info!("writing {}", new_path.display());
+    file::write(&new_image, &metadata, &new_path, &encode_args, false)?;
+
+    Ok(())
+}
+
+/// How a thumbnail which covers its dimensions is cropped to them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Crop {
+    /// Whatever overflows the dimensions is cropped evenly from either side.
+    Center,
+    /// The crop is placed over wherever the image has the most edges, and so probably its
+    /// subject.
+    Smart,
+}
+
+/// Shrinks the given `image` to fit within the given dimensions, keeping its aspect ratio.
+///
+/// If a `crop` is given, the image instead covers the dimensions, and is cropped to them.
+/// Thumbnails of [`High`] quality are resampled with Lanczos in
+/// linear light, and the rest with fast area averaging.
+///
+/// [`High`]: ThumbnailQuality::High
+pub fn thumbnail(
+    image: &DynamicImage,
+    (width, height): (u32, u32),
+    crop: Option<Crop>,
+    quality: ThumbnailQuality,
+) -> DynamicImage {
+    let (image_width, image_height) = image.dimensions();
+    let (horizontal, vertical) = (
+        f64::from(width) / f64::from(image_width),
+        f64::from(height) / f64::from(image_height),
+    );
+    let ratio = if crop.is_some() {
+        horizontal.max(vertical)
+    } else {
+        horizontal.min(vertical)
+    };
+
+    let scaled = |length: u32| ((f64::from(length) * ratio).round() as u32).max(1);
+    let (scaled_width, scaled_height) = (scaled(image_width), scaled(image_height));
+
+    let resized = match quality {
+        ThumbnailQuality::High => scale::scale(
+            image,
+            (scaled_width, scaled_height),
+            FilterType::Lanczos3,
+            true,
+        ),
+        ThumbnailQuality::Low | ThumbnailQuality::Medium => {
+            image.thumbnail_exact(scaled_width, scaled_height)
+        }
+    };
+
+    let Some(crop) = crop else {
+        return resized;
+    };
+
+    let (width, height) = (width.min(scaled_width), height.min(scaled_height));
+    let (x, y) = match crop {
+        Crop::Center => ((scaled_width - width) / 2, (scaled_height - height) / 2),
+        Crop::Smart => busiest(&resized, (width, height)),
+    };
+
+    resized.crop_imm(x, y, width, height)
+}
+
+/// The position of the window with the given dimensions within the given `image` that has the
+/// most edges, by the sum of the differences between the luma of each pixel and its neighbors.
+///
+/// The window, which must fit within the `image`, only slides along the axis it overflows.
+fn busiest(image: &DynamicImage, (width, height): (u32, u32)) -> (u32, u32) {
+    let luma = image.to_luma8();
+    let (image_width, image_height) = luma.dimensions();
+
+    // How many edges are in each column and each row of the image.
+    let mut columns = vec![0_u64; image_width as usize];
+    let mut rows = vec![0_u64; image_height as usize];
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        let difference = |other_x: u32, other_y: u32| {
+            u64::from(pixel[0].abs_diff(luma.get_pixel(other_x, other_y)[0]))
+        };
+        let edges = if x + 1 < image_width {
+            difference(x + 1, y)
+        } else {
+            0
+        } + if y + 1 < image_height {
+            difference(x, y + 1)
+        } else {
+            0
+        };
+
+        columns[x as usize] += edges;
+        rows[y as usize] += edges;
+    }
+
+    (
+        busiest_offset(&columns, width as usize) as u32,
+        busiest_offset(&rows, height as usize) as u32,
+    )
+}
+
+/// The offset of the run of `length` elements of `edges` with the highest sum, preferring the
+/// most central of any ties.
+fn busiest_offset(edges: &[u64], length: usize) -> usize {
+    let offsets = edges.len() - length + 1;
+    let center = (offsets - 1) / 2;
+
+    let mut sum: u64 = edges[..length].iter().sum();
+    let (mut best_offset, mut best_sum) = (0_usize, sum);
+
+    for offset in 1..offsets {
+        sum = sum + edges[offset + length - 1] - edges[offset - 1];
+
+        let closer = offset.abs_diff(center) < best_offset.abs_diff(center);
+        if sum > best_sum || (sum == best_sum && closer) {
+            (best_offset, best_sum) = (offset, sum);
+        }
+    }
+
+    best_offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn crops_cover_the_busiest_region() {
+        // A checkerboard in the top right of an otherwise flat image.
+        let image = GrayImage::from_fn(40, 30, |x, y| {
+            let textured = (28..34).contains(&x) && (4..10).contains(&y);
+            Luma([if textured && (x + y) % 2 == 0 {
+                255
+            } else {
+                128
+            }])
+        });
+
+        let (x, y) = busiest(&image.into(), (10, 10));
+
+        assert!(x <= 28 && x + 10 >= 34, "{x}");
+        assert!(y <= 4 && y + 10 >= 10, "{y}");
+    }
+
+    #[test]
+    fn ties_are_broken_towards_the_center() {
+        assert_eq!(busiest_offset(&[0; 10], 4), 3);
+        assert_eq!(busiest_offset(&[0, 0, 0, 0, 0, 0, 0, 9, 0, 0], 3), 5);
+        assert_eq!(busiest_offset(&[5, 0, 0, 0], 4), 0);
+    }
+}
@@ -0,0 +1,524 @@
+use crate::cli::{self, TileLayout, TileOrigin};
+use image::{imageops, GenericImage, ImageBuffer, Pixel};
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the repetitions of an image are laid out across its output.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Layout {
+    /// Where the repetitions start from.
+    pub origin: TileOrigin,
+    /// How the rows of repetitions are laid out.
+    pub rows: TileLayout,
+    /// Whether the image is repeated horizontally, rather than placed once across.
+    pub repeat_x: bool,
+    /// Whether the image is repeated vertically, rather than placed once down.
+    pub repeat_y: bool,
+    /// Whether the edges of the repetitions are extended across the rest of the output along an
+    /// axis that is not repeated.
+    pub clamp_edges: bool,
+    /// The space between adjacent repetitions, in pixels.
+    pub gap: u32,
+    /// How each repetition is randomly varied, if at all.
+    pub variation: Option<Variation>,
+}
+
+/// How the repetitions of an image are randomly varied.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Variation {
+    /// Whether repetitions are rotated.
+    pub rotate: bool,
+    /// Whether repetitions are flipped.
+    pub flip: bool,
+    /// The seed which each repetition's variation is derived from.
+    pub seed: u64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            origin: TileOrigin::Corner,
+            rows: TileLayout::Grid,
+            repeat_x: true,
+            repeat_y: true,
+            clamp_edges: false,
+            gap: 0,
+            variation: None,
+        }
+    }
+}
+
+impl Layout {
+    /// The layout given by the `resize_args`.
+    pub fn new(resize_args: &cli::ResizeArgs) -> Self {
+        Self {
+            origin: resize_args.tile_origin,
+            rows: resize_args.tile_layout,
+            repeat_x: !resize_args.repeat_y,
+            repeat_y: !resize_args.repeat_x,
+            clamp_edges: resize_args.clamp_edges,
+            gap: resize_args.tile_gap,
+            // The seed is chosen before any images are resized if none is given, so that they
+            // are all varied the same way.
+            variation: resize_args.tile_random.map(|random| Variation {
+                rotate: random.rotate,
+                flip: random.flip,
+                seed: random.seed.unwrap_or_default(),
+            }),
+        }
+    }
+
+    /// Whether this is the default layout: a grid of repetitions from the top left corner.
+    pub fn is_grid(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The number of horizontal and vertical repetitions of an image with `repeated` dimensions
+    /// that fit within the `base` dimensions.
+    pub fn repetitions(
+        &self,
+        (base_width, base_height): (u32, u32),
+        repeated: (u32, u32),
+    ) -> (u32, u32) {
+        let (repeated_width, repeated_height) = repeated;
+        // Each repetition but the last is followed by a gap.
+        let (horizontal, vertical) = crate::repetitions(
+            (
+                base_width.saturating_add(self.gap),
+                base_height.saturating_add(self.gap),
+            ),
+            (
+                repeated_width.saturating_add(self.gap),
+                repeated_height.saturating_add(self.gap),
+            ),
+        );
+
+        (
+            if self.repeat_x {
+                horizontal
+            } else {
+                horizontal.min(1)
+            },
+            if self.repeat_y {
+                vertical
+            } else {
+                vertical.min(1)
+            },
+        )
+    }
+
+    /// How far the given `row` of repetitions of an image `repeated_width` wide is offset to the
+    /// right, in pixels.
+    fn row_offset(&self, row: u32, repeated_width: u32) -> u32 {
+        match self.rows {
+            TileLayout::Brick(fraction) if row % 2 == 1 => {
+                (fraction * (repeated_width + self.gap) as f32).round() as u32
+            }
+
+            _ => 0,
+        }
+    }
+
+    /// Whether the repetitions of an image with `repeated` dimensions cover the whole of the
+    /// `base` dimensions, rather than leaving some of it transparent.
+    ///
+    /// Gaps between the repetitions are left transparent.
+    pub fn covers(&self, base: (u32, u32), repeated: (u32, u32)) -> bool {
+        let (horizontal, vertical) = self.repetitions(base, repeated);
+        let Extent { width, height, .. } = self.extent(base, repeated);
+
+        // Clamping only extends the edges if there are any repetitions to extend.
+        let clamped =
+            |repeated: bool| self.clamp_edges && !repeated && horizontal > 0 && vertical > 0;
+
+        self.gap == 0
+            && (width == base.0 || clamped(self.repeat_x))
+            && (height == base.1 || clamped(self.repeat_y))
+    }
+
+    /// The area covered by the repetitions of an image with `repeated` dimensions within the
+    /// `base` dimensions, including the gaps between them.
+    pub fn extent(&self, (base_width, base_height): (u32, u32), repeated: (u32, u32)) -> Extent {
+        let (repeated_width, repeated_height) = repeated;
+        let (horizontal, vertical) = self.repetitions((base_width, base_height), repeated);
+
+        let length =
+            |count: u32, repeated: u32| count * repeated + count.saturating_sub(1) * self.gap;
+        let (width, height) = (
+            length(horizontal, repeated_width),
+            length(vertical, repeated_height),
+        );
+
+        let (x, y) = match self.origin {
+            TileOrigin::Corner => (0, 0),
+            // Whatever space is left over is split evenly between each side.
+            TileOrigin::Center => ((base_width - width) / 2, (base_height - height) / 2),
+        };
+
+        Extent {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// An area within an image.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Extent {
+    /// The position of the area's left edge.
+    pub x: u32,
+    /// The position of the area's top edge.
+    pub y: u32,
+    /// The width of the area.
+    pub width: u32,
+    /// The height of the area.
+    pub height: u32,
+}
+
+/// Repeats the given `repeated` image across the given `base` image according to the given
+/// `layout`, as [`crate::repeat`] does.
+///
+/// The `repeated` image is copied over the `base` image if `copy` is true, and otherwise blended
+/// over it. Rows which are offset are filled out with partial repetitions at either end. Each row
+/// of repetitions is overlaid in parallel, and the given `progress` bar is advanced as each
+/// repetition is overlaid.
+pub fn repeat<P>(
+    base: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    repeated: &ImageBuffer<P, Vec<P::Subpixel>>,
+    copy: bool,
+    layout: &Layout,
+    progress: &ProgressBar,
+) where
+    P: Pixel + Send + Sync + 'static,
+    P::Subpixel: Send + Sync,
+{
+    let (horizontal, vertical) = layout.repetitions(base.dimensions(), repeated.dimensions());
+    let extent = layout.extent(base.dimensions(), repeated.dimensions());
+
+    let width = base.width();
+    // The number of samples in each row of the base, in each row of repetitions, and in each row
+    // of repetitions along with the gap below it.
+    let row_length = width as usize * usize::from(P::CHANNEL_COUNT);
+    let band_length = row_length * repeated.height() as usize;
+    let step_length = row_length * (repeated.height() + layout.gap) as usize;
+
+    let samples: &mut [P::Subpixel] = base;
+
+    let variants = layout
+        .variation
+        .map(|variation| (variants(repeated, variation), variation.seed));
+
+    samples[row_length * extent.y as usize..][..row_length * extent.height as usize]
+        .par_chunks_mut(step_length)
+        .zip(0..vertical)
+        .for_each(|(band, row)| {
+            let mut buffer =
+                ImageBuffer::<P, _>::from_raw(width, repeated.height(), &mut band[..band_length])
+                    .expect("bands are the length of a row of repetitions");
+            // Repetitions are clipped to the extent, so that offset rows end where the others do.
+            let mut band = buffer.sub_image(extent.x, 0, extent.width, repeated.height());
+
+            let step = i64::from(repeated.width() + layout.gap);
+            let (start, columns) = match layout.row_offset(row, repeated.width()) {
+                0 => (0, horizontal),
+                // An offset row starts with the part of a repetition that sticks out of its left.
+                offset => (i64::from(offset) - step, horizontal + 1),
+            };
+            for i in 0..columns {
+                let x = start + i64::from(i) * step;
+                let repeated = match &variants {
+                    Some((variants, seed)) => {
+                        &variants[random_index(*seed, row, i, variants.len())]
+                    }
+                    None => repeated,
+                };
+
+                if copy {
+                    imageops::replace(&mut *band, repeated, x, 0);
+                } else {
+                    imageops::overlay(&mut *band, repeated, x, 0);
+                }
+            }
+            progress.inc(u64::from(horizontal));
+        });
+    if layout.clamp_edges && horizontal > 0 && vertical > 0 {
+        if !layout.repeat_x {
+            clamp_columns(base, extent.x, extent.x + extent.width);
+        }
+        if !layout.repeat_y {
+            clamp_rows(base, extent.y, extent.y + extent.height);
+        }
+    }
+}
+
+/// Extends the columns of the given `image` at `start` and just before `end` outwards to its
+/// left and right edges.
+fn clamp_columns<P>(image: &mut ImageBuffer<P, Vec<P::Subpixel>>, start: u32, end: u32)
+where
+    P: Pixel + Send + Sync,
+    P::Subpixel: Send + Sync,
+{
+    let channels = usize::from(P::CHANNEL_COUNT);
+    let row_length = image.width() as usize * channels;
+    let (start, end) = (start as usize * channels, end as usize * channels);
+
+    let samples: &mut [P::Subpixel] = image;
+    samples.par_chunks_mut(row_length).for_each(|row| {
+        let (left, rest) = row.split_at_mut(start);
+        for pixel in left.chunks_mut(channels) {
+            pixel.copy_from_slice(&rest[..channels]);
+        }
+
+        let (rest, right) = row.split_at_mut(end);
+        for pixel in right.chunks_mut(channels) {
+            pixel.copy_from_slice(&rest[end - channels..]);
+        }
+    });
+}
+
+/// Extends the rows of the given `image` at `start` and just before `end` outwards to its top and
+/// bottom edges.
+fn clamp_rows<P>(image: &mut ImageBuffer<P, Vec<P::Subpixel>>, start: u32, end: u32)
+where
+    P: Pixel,
+{
+    let row_length = image.width() as usize * usize::from(P::CHANNEL_COUNT);
+    let (start, end) = (start as usize * row_length, end as usize * row_length);
+
+    let samples: &mut [P::Subpixel] = image;
+
+    let (top, rest) = samples.split_at_mut(start);
+    for row in top.chunks_mut(row_length) {
+        row.copy_from_slice(&rest[..row_length]);
+    }
+
+    let (rest, bottom) = samples.split_at_mut(end);
+    for row in bottom.chunks_mut(row_length) {
+        row.copy_from_slice(&rest[end - row_length..]);
+    }
+}
+
+/// Each variant of the given `repeated` image allowed by the given `variation`.
+///
+/// Quarter turns are only allowed for square images, as they would otherwise not fit in place of
+/// the original.
+fn variants<P>(
+    repeated: &ImageBuffer<P, Vec<P::Subpixel>>,
+    variation: Variation,
+) -> Vec<ImageBuffer<P, Vec<P::Subpixel>>>
+where
+    P: Pixel + 'static,
+{
+    let mut rotations = vec![repeated.clone()];
+    if variation.rotate {
+        rotations.push(imageops::rotate180(repeated));
+
+        if repeated.width() == repeated.height() {
+            rotations.push(imageops::rotate90(repeated));
+            rotations.push(imageops::rotate270(repeated));
+        }
+    }
+
+    if !variation.flip {
+        return rotations;
+    }
+
+    rotations
+        .into_iter()
+        .flat_map(|rotation| {
+            let flipped = imageops::flip_horizontal(&rotation);
+
+            // Flipping both ways is the same as a half turn, which is already included when
+            // rotating.
+            if variation.rotate {
+                vec![rotation, flipped]
+            } else {
+                let flipped_vertically = imageops::flip_vertical(&rotation);
+                let flipped_both = imageops::flip_vertical(&flipped);
+
+                vec![rotation, flipped, flipped_vertically, flipped_both]
+            }
+        })
+        .collect()
+}
+
+/// A seed for random variations, taken from the current time.
+pub fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// A pseudorandom index less than `count` for the repetition at the given `row` and `column`,
+/// derived from the `seed` so that it does not depend on the order repetitions are overlaid in.
+fn random_index(seed: u64, row: u32, column: u32, count: usize) -> usize {
+    // SplitMix64's finalizer, which mixes every bit of its input into every bit of its output.
+    let mut hash = seed ^ (u64::from(row) << 32 | u64::from(column));
+    hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+
+    (hash % count as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    /// A layout with the given `gap` between repetitions, and otherwise the default.
+    fn with_gap(gap: u32) -> Layout {
+        Layout {
+            gap,
+            ..Layout::default()
+        }
+    }
+
+    /// The image one pixel high with the given `values`.
+    fn row(values: &[u8]) -> GrayImage {
+        GrayImage::from_raw(values.len() as u32, 1, values.to_vec()).unwrap()
+    }
+
+    /// The values of the given `image`'s pixels, row by row.
+    fn values(image: &GrayImage) -> Vec<u8> {
+        image.pixels().map(|&Luma([value])| value).collect()
+    }
+
+    #[test]
+    fn gaps_follow_every_repetition_but_the_last() {
+        let layout = with_gap(2);
+
+        assert_eq!(layout.repetitions((10, 4), (4, 4)), (2, 1));
+        assert_eq!(layout.extent((10, 4), (4, 4)).width, 10);
+        assert_eq!(layout.repetitions((9, 4), (4, 4)), (1, 1));
+        assert_eq!(layout.extent((9, 4), (4, 4)).width, 4);
+    }
+
+    #[test]
+    fn images_larger_than_the_output_leave_empty_extents() {
+        let layout = with_gap(2);
+
+        assert_eq!(layout.repetitions((3, 3), (4, 4)), (0, 0));
+        assert_eq!(
+            layout.extent((3, 3), (4, 4)),
+            Extent {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0
+            }
+        );
+        assert!(!layout.covers((3, 3), (4, 4)));
+    }
+
+    #[test]
+    fn single_axes_repeat_once_across_the_other() {
+        let layout = Layout {
+            repeat_y: false,
+            ..Layout::default()
+        };
+
+        assert_eq!(layout.repetitions((12, 12), (4, 4)), (3, 1));
+        assert!(!layout.covers((12, 12), (4, 4)));
+        assert!(Layout {
+            clamp_edges: true,
+            ..layout
+        }
+        .covers((12, 12), (4, 4)));
+    }
+
+    #[test]
+    fn centered_extents_split_the_space_left_over() {
+        let layout = Layout {
+            origin: TileOrigin::Center,
+            ..Layout::default()
+        };
+
+        assert_eq!(
+            layout.extent((11, 10), (4, 3)),
+            Extent {
+                x: 1,
+                y: 0,
+                width: 8,
+                height: 9
+            }
+        );
+        assert!(!layout.covers((11, 10), (4, 3)));
+        assert!(layout.covers((12, 9), (4, 3)));
+    }
+
+    #[test]
+    fn gaps_are_left_between_repetitions() {
+        let mut base = GrayImage::new(5, 1);
+
+        repeat(
+            &mut base,
+            &row(&[10, 20]),
+            true,
+            &with_gap(1),
+            &ProgressBar::hidden(),
+        );
+
+        assert_eq!(values(&base), [10, 20, 0, 10, 20]);
+    }
+
+    #[test]
+    fn brick_rows_are_offset_and_filled_out() {
+        let layout = Layout {
+            rows: TileLayout::Brick(0.5),
+            ..Layout::default()
+        };
+        let mut base = GrayImage::new(6, 2);
+
+        repeat(
+            &mut base,
+            &row(&[10, 20]),
+            true,
+            &layout,
+            &ProgressBar::hidden(),
+        );
+
+        assert_eq!(
+            values(&base),
+            [10, 20, 10, 20, 10, 20, 20, 10, 20, 10, 20, 10]
+        );
+    }
+
+    #[test]
+    fn edge_columns_and_rows_are_clamped_outwards() {
+        let mut image = row(&[0, 1, 2, 3, 4]);
+        clamp_columns(&mut image, 1, 3);
+
+        assert_eq!(values(&image), [1, 1, 2, 2, 2]);
+
+        let mut image = GrayImage::from_raw(1, 4, vec![0, 1, 2, 3]).unwrap();
+        clamp_rows(&mut image, 1, 2);
+
+        assert_eq!(values(&image), [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn variants_depend_on_the_variation_and_shape() {
+        let count = |dimensions: (u32, u32), rotate, flip| {
+            let image = GrayImage::new(dimensions.0, dimensions.1);
+            let variation = Variation {
+                rotate,
+                flip,
+                seed: 0,
+            };
+
+            variants(&image, variation).len()
+        };
+
+        assert_eq!(count((2, 2), false, false), 1);
+        assert_eq!(count((2, 2), true, false), 4);
+        assert_eq!(count((2, 1), true, false), 2);
+        assert_eq!(count((2, 1), false, true), 4);
+        assert_eq!(count((2, 2), true, true), 8);
+        assert_eq!(count((2, 1), true, true), 4);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::cli::{self, SelfUpdateArgs};
+use crate::output::Output;
+use log::info;
+use self_update::backends::github;
+use self_update::cargo_crate_version;
+use std::error::Error;
+use std::io::{self, IsTerminal};
+
+/// The owner of the GitHub repository that releases are published to.
+const REPOSITORY_OWNER: &str = "Antikyth";
+/// The name of the GitHub repository that releases are published to.
+const REPOSITORY_NAME: &str = "img-resize";
+
+/// Replaces the running binary with the latest GitHub release according to the given
+/// `self_update_args`, or only checks whether there is a newer one, printing which to `output`.
+///
+/// Nothing is asked and no progress is shown with `--batch`.
+pub fn run(
+    self_update_args: &SelfUpdateArgs,
+    batch: bool,
+    output: &Output,
+) -> Result<(), Box<dyn Error>> {
+    let SelfUpdateArgs { check, yes } = self_update_args;
+    let current_version = cargo_crate_version!();
+
+    let update = github::Update::configure()
+        .repo_owner(REPOSITORY_OWNER)
+        .repo_name(REPOSITORY_NAME)
+        .bin_name(cli::NAME)
+        .current_version(current_version)
+        .show_download_progress(!batch && io::stderr().is_terminal())
+        // The release is printed to stdout, which is kept for data.
+        .show_output(false)
+        .no_confirm(*yes || batch)
+        .build()?;
+
+    if *check {
+        match update.is_update_available()? {
+            Some(release) => output.println(format_args!(
+                "{} {} is available",
+                cli::NAME,
+                release.version()
+            )),
+            None => output.println(format_args!(
+                "{} {current_version} is the latest version",
+                cli::NAME
+            )),
+        }
+
+        return Ok(());
+    }
+
+    let status = update.update()?;
+    if status.is_updated() {
+        info!("updated {} to {}", cli::NAME, status.version());
+    } else {
+        info!(
+            "{} {current_version} is already the latest version",
+            cli::NAME
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::animation::Animation;
+use crate::cli;
+use crate::output::Output;
+use image::ImageFormat;
+
+/// Each optional feature, and whether it was enabled.
+const FEATURES: [(&str, bool); 8] = [
+    ("avif", cfg!(feature = "avif")),
+    ("gpu", cfg!(feature = "gpu")),
+    ("heif", cfg!(feature = "heif")),
+    ("plugins", cfg!(feature = "plugins")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("self-update", cfg!(feature = "self-update")),
+    ("server", cfg!(feature = "server")),
+    ("svg", cfg!(feature = "svg")),
+];
+
+/// Prints the version, and if `verbose` is true, the optional features, image formats, and build
+/// it was compiled with, as is needed to reproduce problems with decoding or encoding, to
+/// `output`.
+pub fn print(verbose: bool, output: &Output) {
+    output.println(format_args!("{} {}", cli::NAME, env!("CARGO_PKG_VERSION")));
+
+    if !verbose {
+        return;
+    }
+
+    // These are only missing when building without Cargo.
+    let build = |value: Option<&'static str>| value.unwrap_or("unknown");
+    output.println(format_args!(
+        "target: {} ({})",
+        build(option_env!("BUILD_TARGET")),
+        build(option_env!("BUILD_PROFILE"))
+    ));
+    output.println(format_args!("rustc: {}", build(option_env!("BUILD_RUSTC"))));
+
+    let features: Vec<_> = FEATURES
+        .iter()
+        .map(|(feature, enabled)| format!("{}{feature}", if *enabled { '+' } else { '-' }))
+        .collect();
+    output.println(format_args!("features: {}", features.join(" ")));
+
+    let mut decodes: Vec<_> = ImageFormat::all()
+        // AVIF images are only encoded, with the `avif` feature.
+        .filter(|&format| format.can_read() && format != ImageFormat::Avif)
+        .map(name)
+        .collect();
+    if cfg!(feature = "heif") {
+        decodes.push("heif");
+    }
+    if cfg!(feature = "svg") {
+        decodes.push("svg");
+    }
+    output.println(format_args!("decodes: {}", decodes.join(", ")));
+
+    let encodes: Vec<_> = ImageFormat::all()
+        .filter(|&format| {
+            format.can_write() && (format != ImageFormat::Avif || cfg!(feature = "avif"))
+        })
+        .map(name)
+        .collect();
+    output.println(format_args!("encodes: {}", encodes.join(", ")));
+
+    let animates: Vec<_> = ImageFormat::all()
+        .filter(|&format| Animation::can_encode(format))
+        .map(name)
+        .collect();
+    output.println(format_args!("animates: {}", animates.join(", ")));
+}
+
+/// The name of the given `format`, by its most common extension.
+fn name(format: ImageFormat) -> &'static str {
+    format
+        .extensions_str()
+        .first()
+        .copied()
+        .unwrap_or("unknown")
+}
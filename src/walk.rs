@@ -0,0 +1,283 @@
+use crate::error::{FileError, InvalidArguments};
+#[cfg(feature = "heif")]
+use crate::heif;
+#[cfg(feature = "svg")]
+use crate::svg;
+use image::ImageFormat;
+use std::error::Error;
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// An input image, given directly or found in an input directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    /// The path of the image.
+    pub path: PathBuf,
+    /// The path of the image relative to the input directory it was found in, or its file name if
+    /// it was given directly.
+    pub relative_path: PathBuf,
+}
+
+impl Entry {
+    /// The path to write the output for this image to in `output_dir`, mirroring its place in its
+    /// input directory, with the given `file_name` if there is one.
+    pub fn output_path(&self, output_dir: &Path, file_name: Option<&Path>) -> PathBuf {
+        let path = output_dir.join(&self.relative_path);
+
+        match file_name {
+            Some(file_name) => path.with_file_name(file_name),
+            None => path,
+        }
+    }
+}
+
+/// Lists the images at the given `input_paths`, finding every image in each directory and its
+/// subdirectories if `recursive` is true.
+///
+/// Images are found in directories by their extensions, which must be one of the given
+/// `extensions` if any are given, and otherwise that of a supported format. They are listed in
+/// order of their paths.
+///
+/// Errors if one of the `input_paths` is a directory and `recursive` is false.
+pub fn inputs(
+    input_paths: &[PathBuf],
+    recursive: bool,
+    extensions: &[String],
+) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+
+    for path in input_paths {
+        if !path.is_dir() {
+            entries.push(Entry {
+                path: path.clone(),
+                relative_path: path.file_name().map(PathBuf::from).unwrap_or_default(),
+            });
+
+            continue;
+        }
+
+        if !recursive {
+            return Err(InvalidArguments(format!(
+                "{} is a directory - use --recursive to resize the images in it",
+                path.display()
+            ))
+            .into());
+        }
+
+        let mut files = Vec::new();
+        walk(path, &mut files)?;
+        files.sort();
+
+        for file in files {
+            if !is_image(&file, extensions) {
+                continue;
+            }
+
+            let relative_path = file
+                .strip_prefix(path)
+                .expect("files are found within their directory")
+                .to_owned();
+
+            entries.push(Entry {
+                path: file,
+                relative_path,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reads the paths listed in the file at `path`, one per line, or those listed on stdin if `path`
+/// is `-`.
+///
+/// Empty lines are skipped, and a carriage return at the end of a line is not part of its path.
+/// If `null` is true, the paths are instead separated by NUL, and kept exactly as they are.
+pub fn list(path: &Path, null: bool) -> Result<Vec<PathBuf>, FileError> {
+    let contents = if path == Path::new("-") {
+        let mut contents = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut contents)
+            .map(|_| contents)
+    } else {
+        fs::read(path)
+    }
+    .map_err(|source| FileError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    let separator = if null { b'\0' } else { b'\n' };
+
+    Ok(contents
+        .split(|&byte| byte == separator)
+        .map(|line| match line.strip_suffix(b"\r") {
+            Some(line) if !null => line,
+            _ => line,
+        })
+        .filter(|line| !line.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// The path made of the given `bytes`, which must be UTF-8 to be kept as they are on platforms
+/// other than Unix.
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    return PathBuf::from(OsStr::from_bytes(bytes));
+
+    #[cfg(not(unix))]
+    return PathBuf::from(&*String::from_utf8_lossy(bytes));
+}
+
+/// Adds the path of every file in the given `directory` and its subdirectories to `files`.
+fn walk(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), FileError> {
+    let read_error = |source| FileError::Read {
+        path: directory.to_owned(),
+        source,
+    };
+
+    for entry in fs::read_dir(directory).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let path = entry.path();
+
+        // Symbolic links to directories are not followed, as they may lead back to a directory
+        // already being walked.
+        if entry.file_type().map_err(read_error)?.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the file at `path` is an image, according to its extension.
+///
+/// If any `extensions` are given, it must be one of them; otherwise, it must be that of a
+/// supported format.
+fn is_image(path: &Path, extensions: &[String]) -> bool {
+    let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+        return false;
+    };
+
+    if !extensions.is_empty() {
+        return extensions.iter().any(|allowed| {
+            allowed
+                .trim_start_matches('.')
+                .eq_ignore_ascii_case(extension)
+        });
+    }
+
+    #[cfg(feature = "heif")]
+    if heif::is_heif(path) {
+        return true;
+    }
+    #[cfg(feature = "svg")]
+    if svg::is_svg(path) {
+        return true;
+    }
+
+    ImageFormat::from_path(path).is_ok_and(|format| format.can_read())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestDirectory, UNUSUAL_NAMES};
+
+    #[test]
+    fn unusual_file_names_are_found() {
+        let directory = TestDirectory::new("found");
+        fs::create_dir(directory.join("sub dir")).unwrap();
+        for (name, _) in UNUSUAL_NAMES {
+            fs::write(directory.join("sub dir").join(name), b"").unwrap();
+        }
+        fs::write(directory.join("notes.txt"), b"").unwrap();
+
+        let entries = inputs(&[directory.to_path_buf()], true, &[]).unwrap();
+
+        let mut expected: Vec<_> = UNUSUAL_NAMES
+            .iter()
+            .map(|(name, _)| Path::new("sub dir").join(name))
+            .collect();
+        expected.sort();
+        let relative_paths: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.relative_path.clone())
+            .collect();
+        assert_eq!(relative_paths, expected);
+
+        for entry in &entries {
+            assert_eq!(
+                entry.output_path(Path::new("out"), None),
+                Path::new("out").join(&entry.relative_path)
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn looping_symlinks_are_not_followed() {
+        let directory = TestDirectory::new("loop");
+        fs::create_dir(directory.join("sub")).unwrap();
+        fs::write(directory.join("sub").join("image.png"), b"").unwrap();
+        std::os::unix::fs::symlink(&*directory, directory.join("sub").join("loop")).unwrap();
+
+        let entries = inputs(&[directory.to_path_buf()], true, &[]).unwrap();
+
+        let relative_paths: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.relative_path.clone())
+            .collect();
+        assert_eq!(relative_paths, [Path::new("sub").join("image.png")]);
+    }
+
+    #[test]
+    fn listed_paths_keep_unusual_names() {
+        let directory = TestDirectory::new("list");
+        let list_path = directory.join("list.txt");
+        let names = UNUSUAL_NAMES.map(|(name, _)| name);
+        fs::write(&list_path, names.join("\r\n\n")).unwrap();
+
+        let paths = list(&list_path, false).unwrap();
+
+        assert_eq!(paths, names.map(PathBuf::from));
+    }
+
+    #[test]
+    fn null_separated_paths_are_kept_exactly() {
+        let directory = TestDirectory::new("null");
+        let list_path = directory.join("list");
+        fs::write(&list_path, b"line\nbreak.png\0 spaced .png\r\0\0").unwrap();
+
+        let paths = list(&list_path, true).unwrap();
+
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("line\nbreak.png"),
+                PathBuf::from(" spaced .png\r")
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_listed() {
+        let directory = TestDirectory::new("non-utf8");
+        let list_path = directory.join("list");
+        fs::write(&list_path, b"\xffimage.png\n").unwrap();
+
+        let paths = list(&list_path, false).unwrap();
+
+        assert_eq!(paths, [PathBuf::from(OsStr::from_bytes(b"\xffimage.png"))]);
+    }
+}
@@ -0,0 +1,60 @@
+use log::{error, info};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::error::Error;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long to wait for further changes after the first before running again.
+///
+/// Image editors commonly save a file in several steps (truncate, write, rename, ...), each of
+/// which produces its own event; they are collapsed into a single run.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Runs `run` once, and then again every time the file at `path` changes.
+///
+/// The parent directory of `path` is watched rather than the file itself so that the watch
+/// survives editors replacing the file (rather than writing to it in place).
+///
+/// Errors returned by `run` are printed rather than returned, as they are most likely caused by
+/// the file being part-way through being edited. This only returns if the watch itself fails.
+pub fn watch<F>(path: &Path, mut run: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut() -> Result<(), Box<dyn Error>>,
+{
+    let path = path.canonicalize()?;
+    let directory = path.parent().unwrap_or(&path);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    info!("watching {} for changes", path.display());
+
+    report(run());
+
+    for event in &receiver {
+        let event = event?;
+
+        // Only react to changes to the contents of the watched file.
+        let is_relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event.paths.contains(&path);
+
+        if is_relevant {
+            // Wait for the rest of the events from this change to come in and discard them.
+            while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+
+            info!("{} changed", path.display());
+
+            report(run());
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the given `result`'s error, if there is one.
+fn report(result: Result<(), Box<dyn Error>>) {
+    if let Err(error) = result {
+        error!("{error}");
+    }
+}